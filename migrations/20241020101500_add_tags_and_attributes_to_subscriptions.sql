@@ -0,0 +1,4 @@
+ALTER TABLE subscriptions
+    ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE subscriptions
+    ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,27 @@
+CREATE TABLE segments
+(
+    segment_id    uuid        NOT NULL,
+    name          TEXT        NOT NULL UNIQUE,
+    tags          TEXT[]      NOT NULL,
+    attributes    JSONB       NOT NULL,
+    joined_after  timestamptz NULL,
+    joined_before timestamptz NULL,
+    created_at    timestamptz NOT NULL,
+    PRIMARY KEY (segment_id)
+);
+
+ALTER TABLE newsletter_issues
+    ADD COLUMN segment_id uuid NULL REFERENCES segments (segment_id);
+
+-- Evaluate a segment definition against a subscriber
+CREATE FUNCTION subscriber_in_segment(s subscriptions, g segments)
+    RETURNS boolean
+    LANGUAGE sql
+    STABLE
+AS
+$$
+SELECT s.tags @> g.tags
+           AND s.attributes @> g.attributes
+           AND (g.joined_after IS NULL OR s.subscribed_at > g.joined_after)
+           AND (g.joined_before IS NULL OR s.subscribed_at < g.joined_before)
+$$;
@@ -0,0 +1,12 @@
+-- Audit trail of GDPR data subject access and erasure requests
+CREATE TABLE data_subject_requests
+(
+    request_id    uuid        NOT NULL,
+    kind          TEXT        NOT NULL,
+    subject_hash  TEXT        NOT NULL,
+    source        TEXT        NOT NULL,
+    requested_by  uuid        NULL REFERENCES users (user_id),
+    affected_rows BIGINT      NOT NULL,
+    requested_at  timestamptz NOT NULL,
+    PRIMARY KEY (request_id)
+);
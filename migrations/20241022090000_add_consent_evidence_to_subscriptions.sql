@@ -0,0 +1,8 @@
+-- Record double opt-in consent evidence alongside the subscriber
+ALTER TABLE subscriptions
+    ADD COLUMN consent_version TEXT NULL,
+    ADD COLUMN subscribe_ip TEXT NULL,
+    ADD COLUMN subscribe_user_agent TEXT NULL,
+    ADD COLUMN confirmed_at timestamptz NULL,
+    ADD COLUMN confirm_ip TEXT NULL,
+    ADD COLUMN confirm_user_agent TEXT NULL;
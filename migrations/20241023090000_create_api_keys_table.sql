@@ -0,0 +1,14 @@
+-- API keys used by automated clients to call the admin REST API
+CREATE TABLE api_keys
+(
+    api_key_id   uuid        NOT NULL,
+    user_id      uuid        NOT NULL REFERENCES users (user_id),
+    name         TEXT        NOT NULL,
+    key_prefix   TEXT        NOT NULL,
+    key_hash     TEXT        NOT NULL UNIQUE,
+    scopes       TEXT[]      NOT NULL,
+    created_at   timestamptz NOT NULL,
+    last_used_at timestamptz NULL,
+    revoked_at   timestamptz NULL,
+    PRIMARY KEY (api_key_id)
+);
@@ -0,0 +1,13 @@
+-- Single-use invitations that let admins invite collaborators
+CREATE TABLE invitations
+(
+    invitation_id    uuid        NOT NULL,
+    email            TEXT        NOT NULL,
+    token_hash       TEXT        NOT NULL UNIQUE,
+    invited_by       uuid        NOT NULL REFERENCES users (user_id),
+    created_at       timestamptz NOT NULL,
+    expires_at       timestamptz NOT NULL,
+    accepted_at      timestamptz NULL,
+    accepted_user_id uuid        NULL REFERENCES users (user_id),
+    PRIMARY KEY (invitation_id)
+);
@@ -0,0 +1,9 @@
+-- Role-based access control for admin users, existing users keep full access
+ALTER TABLE users
+    ADD COLUMN role TEXT NOT NULL DEFAULT 'owner'
+        CHECK (role IN ('owner', 'editor', 'viewer'));
+
+-- Role granted to invited collaborators once they accept the invitation
+ALTER TABLE invitations
+    ADD COLUMN role TEXT NOT NULL DEFAULT 'editor'
+        CHECK (role IN ('owner', 'editor', 'viewer'));
@@ -0,0 +1,16 @@
+-- Email address used to send password reset links to admin users
+ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
+
+-- Counter that is bumped to invalidate all the existing sessions of a user
+ALTER TABLE users ADD COLUMN session_epoch INTEGER NOT NULL DEFAULT 0;
+
+-- Single-use password reset tokens
+CREATE TABLE password_resets
+(
+    token_hash TEXT        NOT NULL,
+    user_id    uuid        NOT NULL REFERENCES users (user_id),
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    used_at    timestamptz NULL,
+    PRIMARY KEY (token_hash)
+);
@@ -0,0 +1,19 @@
+-- TOTP second factor of admin users, only enforced once the enrolment is confirmed
+CREATE TABLE totp_credentials
+(
+    user_id        uuid        NOT NULL REFERENCES users (user_id),
+    secret         TEXT        NOT NULL,
+    created_at     timestamptz NOT NULL,
+    confirmed_at   timestamptz NULL,
+    last_used_step BIGINT      NULL,
+    PRIMARY KEY (user_id)
+);
+
+-- Single-use recovery codes that replace a TOTP code when the authenticator is lost
+CREATE TABLE totp_recovery_codes
+(
+    user_id   uuid        NOT NULL REFERENCES users (user_id),
+    code_hash TEXT        NOT NULL,
+    used_at   timestamptz NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
@@ -0,0 +1,13 @@
+-- WebAuthn credentials (passkeys) that admin users can log in with instead of a password
+CREATE TABLE passkeys
+(
+    passkey_id    uuid        NOT NULL,
+    user_id       uuid        NOT NULL REFERENCES users (user_id),
+    name          TEXT        NOT NULL,
+    credential_id BYTEA       NOT NULL UNIQUE,
+    passkey       JSONB       NOT NULL,
+    created_at    timestamptz NOT NULL,
+    last_used_at  timestamptz NULL,
+    PRIMARY KEY (passkey_id)
+);
+CREATE INDEX passkeys_user_id_idx ON passkeys (user_id);
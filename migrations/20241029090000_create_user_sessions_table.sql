@@ -0,0 +1,12 @@
+-- Index of the sessions of each admin user, so that they can be listed and revoked remotely
+CREATE TABLE user_sessions
+(
+    session_id   uuid        NOT NULL,
+    user_id      uuid        NOT NULL REFERENCES users (user_id),
+    user_agent   TEXT        NULL,
+    ip           TEXT        NULL,
+    created_at   timestamptz NOT NULL,
+    last_seen_at timestamptz NOT NULL,
+    PRIMARY KEY (session_id)
+);
+CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
@@ -0,0 +1,9 @@
+-- State of the sessions stored in the database, when it is used as session store
+CREATE TABLE sessions
+(
+    session_key TEXT        NOT NULL,
+    state       jsonb       NOT NULL,
+    expires_at  timestamptz NOT NULL,
+    PRIMARY KEY (session_key)
+);
+CREATE INDEX sessions_expires_at_idx ON sessions (expires_at);
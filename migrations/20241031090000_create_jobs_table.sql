@@ -0,0 +1,27 @@
+-- Queue of background jobs, shared by all workers
+CREATE TABLE jobs
+(
+    job_id     uuid        NOT NULL,
+    kind       TEXT        NOT NULL,
+    payload    jsonb       NOT NULL,
+    run_at     timestamptz NOT NULL,
+    attempts   INTEGER     NOT NULL,
+    last_error TEXT        NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (job_id)
+);
+CREATE INDEX jobs_run_at_idx ON jobs (run_at);
+
+-- Move pending newsletter deliveries to the job queue
+INSERT INTO jobs (job_id, kind, payload, run_at, attempts, created_at)
+SELECT gen_random_uuid(),
+       'newsletter_delivery',
+       jsonb_build_object(
+               'newsletter_issue_id', newsletter_issue_id,
+               'subscriber_email', subscriber_email
+       ),
+       now(),
+       0,
+       now()
+FROM issue_delivery_queue;
+DROP TABLE issue_delivery_queue;
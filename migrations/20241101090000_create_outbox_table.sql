@@ -0,0 +1,41 @@
+-- Log of every email sent by the system, with its rendered content and delivery status
+CREATE TABLE outbox
+(
+    message_id          uuid        NOT NULL,
+    kind                TEXT        NOT NULL,
+    recipient           TEXT        NOT NULL,
+    subject             TEXT        NOT NULL,
+    html_body           TEXT        NOT NULL,
+    text_body           TEXT        NOT NULL,
+    newsletter_issue_id uuid        NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    status              TEXT        NOT NULL,
+    attempts            INTEGER     NOT NULL,
+    last_error          TEXT        NULL,
+    created_at          timestamptz NOT NULL,
+    sent_at             timestamptz NULL,
+    PRIMARY KEY (message_id)
+);
+CREATE INDEX outbox_recipient_idx ON outbox (lower(recipient));
+
+-- Move pending newsletter deliveries to the outbox
+INSERT INTO outbox (message_id, kind, recipient, subject, html_body, text_body, newsletter_issue_id, status, attempts,
+                    created_at)
+SELECT j.job_id,
+       'newsletter',
+       j.payload ->> 'subscriber_email',
+       n.title,
+       n.content_html,
+       n.content_text,
+       n.newsletter_issue_id,
+       'pending',
+       0,
+       j.created_at
+FROM jobs j
+         JOIN newsletter_issues n ON n.newsletter_issue_id = (j.payload ->> 'newsletter_issue_id')::uuid
+WHERE j.kind = 'newsletter_delivery';
+
+-- Turn the moved jobs into dispatches of the outbox emails
+UPDATE jobs
+SET kind    = 'outbox_dispatch',
+    payload = jsonb_build_object('message_id', job_id)
+WHERE kind = 'newsletter_delivery';
@@ -0,0 +1,2 @@
+-- Expired idempotency keys are deleted periodically, based on their creation time
+CREATE INDEX idempotency_created_at_idx ON idempotency (created_at);
@@ -0,0 +1,2 @@
+-- Hash of the request a key was first used for, so that it cannot be reused for a different request
+ALTER TABLE idempotency ADD COLUMN request_fingerprint BYTEA;
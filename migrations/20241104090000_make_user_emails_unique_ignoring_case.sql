@@ -0,0 +1,13 @@
+-- Email addresses are looked up ignoring case, so they must also be unique ignoring case
+-- Addresses that only differ in case are kept for the oldest user id, the other users can set theirs again
+UPDATE users u
+SET email = NULL
+WHERE EXISTS (
+    SELECT 1
+    FROM users o
+    WHERE
+        lower(o.email) = lower(u.email) AND
+        o.user_id < u.user_id
+);
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+CREATE UNIQUE INDEX users_email_key ON users (lower(email));
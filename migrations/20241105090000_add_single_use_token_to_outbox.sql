@@ -0,0 +1,2 @@
+-- Single-use token embedded in the content of an email, redacted from it once the email is sent or fails
+ALTER TABLE outbox ADD COLUMN single_use_token TEXT;
@@ -0,0 +1,313 @@
+use std::ops::Deref;
+use std::{fmt, iter};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage};
+use anyhow::Context;
+use chrono::Utc;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{Role, UserId};
+use crate::problem_details::ProblemDetails;
+use crate::utils::e500_internal_server_error;
+
+/// Prefix shared by all API keys, which makes them easy to spot in leaked logs or configuration files
+const API_KEY_PREFIX: &str = "z2p_";
+
+/// Number of random characters in an API key
+const API_KEY_LEN: usize = 40;
+
+/// Number of characters of an API key that are stored in clear to identify it
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+/// API key identifier
+#[derive(Copy, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ApiKeyId(Uuid);
+
+impl ApiKeyId {
+    pub const fn new(uuid: Uuid) -> Self {
+        Self(uuid)
+    }
+}
+
+impl fmt::Display for ApiKeyId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for ApiKeyId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Operation an API key is allowed to perform
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ApiKeyScope {
+    NewslettersPublish,
+}
+
+impl ApiKeyScope {
+    /// Represent scope as a string
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::NewslettersPublish => "newsletters:publish",
+        }
+    }
+
+    /// Parse a scope from its string representation
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "newsletters:publish" => Ok(Self::NewslettersPublish),
+            other => Err(format!("{other} is not a valid API key scope")),
+        }
+    }
+
+    /// Return the role that the owner of an API key needs to use the scope
+    pub const fn required_role(&self) -> Role {
+        match self {
+            Self::NewslettersPublish => Role::Editor,
+        }
+    }
+
+    /// Parse a comma-separated list of scopes, requiring at least one of them
+    pub fn parse_list(s: &str) -> Result<Vec<Self>, String> {
+        let mut scopes = Vec::new();
+        for scope in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let scope = Self::parse(scope)?;
+            if !scopes.contains(&scope) {
+                scopes.push(scope);
+            }
+        }
+        if scopes.is_empty() {
+            return Err("At least one API key scope is required".to_string());
+        }
+        Ok(scopes)
+    }
+}
+
+/// Scopes granted to the API key that authenticated the current request
+#[derive(Clone, Debug)]
+pub struct ApiKeyScopes(Vec<ApiKeyScope>);
+
+impl ApiKeyScopes {
+    /// Check if the API key was granted a scope
+    pub fn contains(&self, scope: ApiKeyScope) -> bool {
+        self.0.contains(&scope)
+    }
+}
+
+/// Generate a new random API key
+fn generate_api_key() -> SecretString {
+    let mut rng = thread_rng();
+    let key: String = iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(API_KEY_LEN)
+        .collect();
+    SecretString::from(format!("{API_KEY_PREFIX}{key}"))
+}
+
+/// Hash an API key before storing or looking it up
+///
+/// API keys are long random strings, so a fast hash is enough to make a leaked table useless.
+fn hash_api_key(api_key: &SecretString) -> String {
+    format!("{:x}", Sha256::digest(api_key.expose_secret().as_bytes()))
+}
+
+/// Create an API key owned by a user and return it, since it cannot be retrieved later on
+#[tracing::instrument(skip(db_pool))]
+pub async fn create_api_key(
+    db_pool: &PgPool,
+    user_id: UserId,
+    name: &str,
+    scopes: &[ApiKeyScope],
+) -> sqlx::Result<(ApiKeyId, SecretString)> {
+    let api_key_id = ApiKeyId::new(Uuid::new_v4());
+    let api_key = generate_api_key();
+    let key_prefix = &api_key.expose_secret()[..DISPLAY_PREFIX_LEN];
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_string()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_keys (
+            api_key_id,
+            user_id,
+            name,
+            key_prefix,
+            key_hash,
+            scopes,
+            created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        *api_key_id,
+        *user_id,
+        name,
+        key_prefix,
+        hash_api_key(&api_key),
+        &scopes[..],
+        Utc::now()
+    )
+    .execute(db_pool)
+    .await?;
+
+    Ok((api_key_id, api_key))
+}
+
+/// Revoke an API key and return `true` if it was active
+#[tracing::instrument(skip(db_pool))]
+pub async fn revoke_api_key(db_pool: &PgPool, api_key_id: ApiKeyId) -> sqlx::Result<bool> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE api_keys
+        SET revoked_at = now()
+        WHERE
+            api_key_id = $1 AND
+            revoked_at IS NULL
+        "#,
+        *api_key_id
+    )
+    .execute(db_pool)
+    .await?
+    .rows_affected();
+
+    Ok(n_updated_rows > 0)
+}
+
+/// Look up an active API key, recording its usage, and return its owner and scopes
+///
+/// Scopes are limited by the current role of the owner, so that a key stops granting them once the owner is demoted.
+#[tracing::instrument(skip_all)]
+async fn validate_api_key(
+    db_pool: &PgPool,
+    api_key: &SecretString,
+) -> anyhow::Result<Option<(UserId, ApiKeyScopes)>> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_keys k
+        SET last_used_at = now()
+        FROM users u
+        WHERE
+            k.key_hash = $1 AND
+            k.revoked_at IS NULL AND
+            u.user_id = k.user_id
+        RETURNING k.user_id, k.scopes, u.role
+        "#,
+        hash_api_key(api_key)
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to perform a query to validate the API key")?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    // Ignore scopes that are no longer known to the application or not allowed for the role of the owner
+    let role = Role::parse(&row.role).map_err(|e| anyhow::anyhow!(e))?;
+    let scopes = row
+        .scopes
+        .iter()
+        .filter_map(|s| ApiKeyScope::parse(s).ok())
+        .filter(|scope| role >= scope.required_role())
+        .collect();
+    Ok(Some((UserId::new(row.user_id), ApiKeyScopes(scopes))))
+}
+
+/// Extract the API key from an `Authorization: Bearer <key>` header
+fn bearer_token(req: &ServiceRequest) -> Option<SecretString> {
+    req.headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|key| SecretString::from(key.trim()))
+}
+
+/// Reject requests that do not carry a valid API key
+#[allow(clippy::future_not_send)]
+pub async fn reject_invalid_api_keys(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> actix_web::Result<ServiceResponse<impl MessageBody>> {
+    // Retrieve the database pool from the application context
+    let db_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .ok_or_else(|| e500_internal_server_error("Database pool not available"))?;
+
+    // Check if the request carries an active API key, otherwise return error
+    let validated = match bearer_token(&req) {
+        Some(api_key) => validate_api_key(&db_pool, &api_key)
+            .await
+            .map_err(e500_internal_server_error)?,
+        None => None,
+    };
+    if let Some((user_id, scopes)) = validated {
+        req.extensions_mut().insert(user_id);
+        req.extensions_mut().insert(scopes);
+        next.call(req).await
+    } else {
+        let detail = "A valid API key is required in the Authorization header";
+        let mut response = ProblemDetails::new(StatusCode::UNAUTHORIZED, detail).into_response();
+        response
+            .headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        let e = anyhow::anyhow!("The request does not carry a valid API key");
+        Err(InternalError::from_response(e, response).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::*;
+
+    #[test]
+    fn generated_api_keys_are_prefixed_and_unique() {
+        let first = generate_api_key();
+        let second = generate_api_key();
+        assert!(first.expose_secret().starts_with(API_KEY_PREFIX));
+        assert_eq!(
+            first.expose_secret().len(),
+            API_KEY_PREFIX.len() + API_KEY_LEN
+        );
+        assert_ne!(first.expose_secret(), second.expose_secret());
+    }
+
+    #[test]
+    fn api_key_hashes_are_deterministic() {
+        let key = SecretString::from("z2p_test");
+        assert_eq!(hash_api_key(&key), hash_api_key(&key));
+        assert_ne!(hash_api_key(&key), key.expose_secret());
+    }
+
+    #[test]
+    fn known_scopes_are_parsed_without_duplicates() {
+        let scopes = ApiKeyScope::parse_list("newsletters:publish, newsletters:publish").unwrap();
+        assert_eq!(scopes, vec![ApiKeyScope::NewslettersPublish]);
+    }
+
+    #[test]
+    fn unknown_scopes_are_rejected() {
+        assert_err!(ApiKeyScope::parse_list("subscribers:delete"));
+    }
+
+    #[test]
+    fn an_empty_scope_list_is_rejected() {
+        assert_err!(ApiKeyScope::parse_list(" , "));
+        assert_ok!(ApiKeyScope::parse_list("newsletters:publish"));
+    }
+}
@@ -2,10 +2,12 @@ use anyhow::Context;
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 use secrecy::{ExposeSecret, SecretString};
-use sqlx::PgPool;
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
 
-use crate::authentication::UserId;
+use crate::authentication::{Role, UserId};
 use crate::telemetry::spawn_blocking_with_tracing;
+use crate::utils::PgTransaction;
 
 /// Fallback hash in case an invalid username is provided during authentication
 const FALLBACK_HASH: &str =
@@ -119,6 +121,94 @@ pub async fn change_password(
     Ok(())
 }
 
+/// Reset the password of a user, invalidating all of their existing sessions
+#[tracing::instrument(name = "Reset password", skip(password, transaction))]
+pub async fn reset_password(
+    user_id: UserId,
+    password: SecretString,
+    transaction: &mut PgTransaction,
+) -> anyhow::Result<()> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(&password))
+        .await?
+        .context("Failed to hash password")?;
+
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE users
+            SET
+                password_hash = $1,
+                session_epoch = session_epoch + 1
+            WHERE user_id = $2
+            "#,
+            password_hash.expose_secret(),
+            *user_id
+        ))
+        .await
+        .context("Failed to reset password")?;
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            DELETE FROM user_sessions
+            WHERE user_id = $1
+            "#,
+            *user_id
+        ))
+        .await
+        .context("Failed to revoke sessions")?;
+
+    Ok(())
+}
+
+/// Check that a new password satisfies the password rules and matches its confirmation
+pub fn validate_new_password(
+    password: &SecretString,
+    password_check: &SecretString,
+) -> Result<(), &'static str> {
+    if password.expose_secret() != password_check.expose_secret() {
+        return Err("New passwords fields must match");
+    }
+    if password.expose_secret().len() < 12 {
+        return Err("The password must be at least 12 characters long");
+    }
+    if password.expose_secret().len() > 128 {
+        return Err("The password must contain a maximum of 128 characters");
+    }
+    Ok(())
+}
+
+/// Create a new user and return its `user_id`, unless the username is already taken
+#[tracing::instrument(name = "Create user", skip(password, transaction))]
+pub async fn create_user(
+    username: &str,
+    password: SecretString,
+    role: Role,
+    transaction: &mut PgTransaction,
+) -> anyhow::Result<Option<UserId>> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(&password))
+        .await?
+        .context("Failed to hash password")?;
+
+    let user_id = UserId::new(Uuid::new_v4());
+    let n_inserted_rows = transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO users (user_id, username, password_hash, role)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (username) DO NOTHING
+            "#,
+            *user_id,
+            username,
+            password_hash.expose_secret(),
+            role.as_str()
+        ))
+        .await
+        .context("Failed to create user")?
+        .rows_affected();
+
+    Ok((n_inserted_rows > 0).then_some(user_id))
+}
+
 /// Compute a password hash based on the provided password
 fn compute_password_hash(password: &SecretString) -> anyhow::Result<SecretString> {
     let salt = SaltString::generate(&mut rand::thread_rng());
@@ -132,3 +222,26 @@ fn compute_password_hash(password: &SecretString) -> anyhow::Result<SecretString
 
     Ok(SecretString::from(password_hash))
 }
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::*;
+
+    #[test]
+    fn new_passwords_must_match() {
+        let password = SecretString::from("a-long-enough-password");
+        let other = SecretString::from("another-long-enough-password");
+        assert_err!(validate_new_password(&password, &other));
+        assert_ok!(validate_new_password(&password, &password));
+    }
+
+    #[test]
+    fn new_passwords_must_have_a_valid_length() {
+        let short = SecretString::from("short");
+        let long = SecretString::from("a".repeat(129));
+        assert_err!(validate_new_password(&short, &short));
+        assert_err!(validate_new_password(&long, &long));
+    }
+}
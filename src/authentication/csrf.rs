@@ -0,0 +1,125 @@
+use std::iter;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+use crate::problem_details::{wants_json, ProblemDetails};
+use crate::session_state::TypedSession;
+use crate::utils::{bytes_to_payload, e500_internal_server_error};
+
+/// Header carrying the CSRF token of requests sent by scripts
+pub const CSRF_TOKEN_HEADER: &str = "X-CSRF-Token";
+
+/// Form field carrying the CSRF token of HTML form submissions
+pub const CSRF_TOKEN_FIELD: &str = "csrf_token";
+
+/// Generate a pseudo-random CSRF token
+pub fn generate_csrf_token() -> String {
+    let mut rng = thread_rng();
+    iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+/// Compare two tokens in constant time, so that their content cannot be guessed from response times
+fn tokens_match(expected: &str, submitted: &str) -> bool {
+    expected.len() == submitted.len()
+        && expected
+            .bytes()
+            .zip(submitted.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Extract the CSRF token of a urlencoded form body
+fn form_token(body: &[u8]) -> Option<String> {
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+        .ok()?
+        .into_iter()
+        .find_map(|(name, value)| (name == CSRF_TOKEN_FIELD).then_some(value))
+}
+
+/// Reject state-changing requests whose CSRF token is missing or does not match the one of the session
+#[allow(clippy::future_not_send)]
+pub async fn reject_forged_requests(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> actix_web::Result<ServiceResponse<impl MessageBody>> {
+    // Safe methods do not change anything, so they cannot be forged
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.call(req).await;
+    }
+
+    // Retrieve the token issued to the session
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let expected = session
+        .get_csrf_token()
+        .map_err(e500_internal_server_error)?;
+
+    // Read the submitted token from the header, or from the form body which is put back for the handler
+    let mut submitted = req
+        .headers()
+        .get(CSRF_TOKEN_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(ToString::to_string);
+    if submitted.is_none() && req.content_type() == "application/x-www-form-urlencoded" {
+        let body = req.extract::<web::Bytes>().await?;
+        submitted = form_token(&body);
+        req.set_payload(bytes_to_payload(body));
+    }
+
+    // Check that both tokens exist and match, otherwise return error
+    if let (Some(expected), Some(submitted)) = (expected, submitted) {
+        if tokens_match(&expected, &submitted) {
+            return next.call(req).await;
+        }
+    }
+    let detail = "The CSRF token is missing or invalid, please reload the page and try again";
+    let response = if wants_json(req.request()) {
+        ProblemDetails::new(StatusCode::FORBIDDEN, detail).into_response()
+    } else {
+        HttpResponse::Forbidden().body(detail)
+    };
+    let e = anyhow::anyhow!("The CSRF token is missing or invalid");
+    Err(InternalError::from_response(e, response).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_tokens_are_random() {
+        let token = generate_csrf_token();
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, generate_csrf_token());
+    }
+
+    #[test]
+    fn only_identical_tokens_match() {
+        assert!(tokens_match("abc123", "abc123"));
+        assert!(!tokens_match("abc123", "abc124"));
+        assert!(!tokens_match("abc123", "abc12"));
+        assert!(!tokens_match("abc123", ""));
+    }
+
+    #[test]
+    fn tokens_are_extracted_from_form_bodies() {
+        assert_eq!(
+            form_token(b"title=Hello&csrf_token=abc123&content=World"),
+            Some("abc123".to_string())
+        );
+        assert_eq!(form_token(b"title=Hello"), None);
+        assert_eq!(form_token(b"not a form"), None);
+    }
+}
@@ -0,0 +1,202 @@
+use std::{fmt, iter};
+
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::SecretString;
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+use crate::authentication::{create_user, Role, UserId};
+use crate::domain::EmailAddress;
+use crate::utils::{error_chain_fmt, PgTransaction};
+
+/// Number of hours after which an invitation can no longer be accepted
+pub const INVITATION_TTL_HOURS: i64 = 72;
+
+/// Invitation acceptance error
+#[derive(thiserror::Error)]
+pub enum InvitationError {
+    #[error("The invitation link is invalid or has expired")]
+    InvalidToken,
+    #[error("The username is already taken")]
+    UsernameTaken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl fmt::Debug for InvitationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Invitation that has not been accepted yet and has not expired
+pub struct PendingInvitation {
+    pub invitation_id: Uuid,
+    pub email: String,
+    pub role: String,
+}
+
+/// Generate a pseudo-random invitation token
+fn generate_invitation_token() -> String {
+    let mut rng = thread_rng();
+    iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+/// Hash an invitation token, so that a leaked table cannot be used to create accounts
+fn hash_invitation_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Create an invitation for a collaborator and return its token, which is only sent by email
+#[tracing::instrument(skip(transaction))]
+pub async fn create_invitation(
+    transaction: &mut PgTransaction,
+    email: &EmailAddress,
+    role: Role,
+    invited_by: UserId,
+) -> sqlx::Result<String> {
+    let token = generate_invitation_token();
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO invitations (
+            invitation_id,
+            email,
+            token_hash,
+            invited_by,
+            created_at,
+            expires_at,
+            role
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        email.as_ref(),
+        hash_invitation_token(&token),
+        *invited_by,
+        now,
+        now + Duration::hours(INVITATION_TTL_HOURS),
+        role.as_str()
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(token)
+}
+
+/// Retrieve the pending invitation associated with a token, if any
+#[tracing::instrument(skip_all)]
+pub async fn get_pending_invitation(
+    db_pool: &PgPool,
+    token: &str,
+) -> sqlx::Result<Option<PendingInvitation>> {
+    sqlx::query_as!(
+        PendingInvitation,
+        r#"
+        SELECT invitation_id, email, role
+        FROM invitations
+        WHERE
+            token_hash = $1 AND
+            accepted_at IS NULL AND
+            expires_at > now()
+        "#,
+        hash_invitation_token(token)
+    )
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// Accept an invitation by creating a user with the chosen credentials, making the invitation unusable
+#[tracing::instrument(skip(db_pool, token, password))]
+pub async fn accept_invitation(
+    db_pool: &PgPool,
+    token: &str,
+    username: &str,
+    password: SecretString,
+) -> Result<UserId, InvitationError> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire database connection to accept an invitation")?;
+
+    // Lock the invitation, so that concurrent requests cannot accept it twice
+    let invitation = lock_pending_invitation(&mut transaction, token)
+        .await
+        .context("Failed to retrieve the invitation")?
+        .ok_or(InvitationError::InvalidToken)?;
+
+    // Create the user with the invited role and mark the invitation as accepted
+    let role = Role::parse(&invitation.role).map_err(|e| anyhow::anyhow!(e))?;
+    let user_id = create_user(username, password, role, &mut transaction)
+        .await?
+        .ok_or(InvitationError::UsernameTaken)?;
+
+    // Use the invited email address for password resets, unless another user already has it
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE users
+            SET email = $2
+            WHERE
+                user_id = $1 AND
+                NOT EXISTS (
+                    SELECT 1
+                    FROM users
+                    WHERE lower(email) = lower($2)
+                )
+            "#,
+            *user_id,
+            invitation.email
+        ))
+        .await
+        .context("Failed to store the user email address")?;
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE invitations
+            SET
+                accepted_at = now(),
+                accepted_user_id = $2
+            WHERE invitation_id = $1
+            "#,
+            invitation.invitation_id,
+            *user_id
+        ))
+        .await
+        .context("Failed to mark the invitation as accepted")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to accept an invitation")?;
+
+    Ok(user_id)
+}
+
+/// Retrieve and lock the pending invitation associated with a token, if any
+async fn lock_pending_invitation(
+    transaction: &mut PgTransaction,
+    token: &str,
+) -> sqlx::Result<Option<PendingInvitation>> {
+    sqlx::query_as!(
+        PendingInvitation,
+        r#"
+        SELECT invitation_id, email, role
+        FROM invitations
+        WHERE
+            token_hash = $1 AND
+            accepted_at IS NULL AND
+            expires_at > now()
+        FOR UPDATE
+        "#,
+        hash_invitation_token(token)
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::{iter, time};
+
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+
+use crate::configuration::LoginThrottleSettings;
+use crate::counter_store::CounterStore;
+
+/// Outcome of the throttling check performed before validating login credentials
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoginAttempt {
+    /// The attempt can proceed after waiting for the specified delay
+    Allowed(time::Duration),
+    /// The username or the client is temporarily locked out
+    LockedOut,
+}
+
+/// Failure counter stored in memory when Redis is not available
+struct MemoryCounter {
+    failures: u64,
+    expires_at: time::Instant,
+}
+
+/// Per-username and per-IP counters of failed login attempts, stored in Redis or in memory if Redis is not available
+///
+/// Counters expire after the lockout duration, which restarts on each failure.
+pub struct LoginThrottle {
+    counters: CounterStore<MemoryCounter>,
+    settings: LoginThrottleSettings,
+}
+
+impl LoginThrottle {
+    /// Build the login throttle, which only uses memory if no Redis connection is available
+    pub fn new(redis: Option<ConnectionManager>, settings: LoginThrottleSettings) -> Self {
+        Self {
+            counters: CounterStore::new(redis),
+            settings,
+        }
+    }
+
+    /// Build the keys of the counters, ignoring case so that usernames cannot be varied to dodge them
+    fn keys(username: &str, ip: Option<&str>) -> (String, Option<String>) {
+        (
+            format!("login_failures:username:{}", username.to_lowercase()),
+            ip.map(|ip| format!("login_failures:ip:{ip}")),
+        )
+    }
+
+    /// Lock the in-memory counters, dropping the expired ones
+    fn memory(&self) -> std::sync::MutexGuard<'_, HashMap<String, MemoryCounter>> {
+        let mut memory = self.counters.memory();
+        let now = time::Instant::now();
+        memory.retain(|_, c| c.expires_at > now);
+        memory
+    }
+
+    /// Check if a login attempt can proceed, and after how long
+    #[tracing::instrument(name = "Check login throttle", skip(self))]
+    pub async fn check(&self, username: &str, ip: Option<&str>) -> LoginAttempt {
+        let (username_key, ip_key) = Self::keys(username, ip);
+        let (username_failures, ip_failures) = self
+            .read_from_redis(&username_key, ip_key.as_deref())
+            .await
+            .unwrap_or_else(|| self.read_from_memory(&username_key, ip_key.as_deref()));
+        evaluate(&self.settings, username_failures, ip_failures)
+    }
+
+    /// Count a failed login attempt against the username and the client
+    #[tracing::instrument(name = "Record login failure", skip(self))]
+    pub async fn record_failure(&self, username: &str, ip: Option<&str>) {
+        let (username_key, ip_key) = Self::keys(username, ip);
+        if self
+            .increment_in_redis(&username_key, ip_key.as_deref())
+            .await
+            .is_some()
+        {
+            return;
+        }
+
+        let expires_at =
+            time::Instant::now() + time::Duration::from_secs(self.settings.lockout_secs);
+        let mut memory = self.memory();
+        for key in iter::once(username_key).chain(ip_key) {
+            let counter = memory.entry(key).or_insert(MemoryCounter {
+                failures: 0,
+                expires_at,
+            });
+            counter.failures += 1;
+            counter.expires_at = expires_at;
+        }
+        drop(memory);
+    }
+
+    /// Reset the failure counter of a username after a successful login
+    #[tracing::instrument(name = "Reset login failures", skip(self))]
+    pub async fn record_success(&self, username: &str) {
+        let (username_key, _) = Self::keys(username, None);
+        self.memory().remove(&username_key);
+        if let Some(mut redis) = self.counters.redis() {
+            if let Err(e) = redis.del::<_, ()>(&username_key).await {
+                tracing::warn!(error = %e, "Failed to reset login failures in Redis");
+            }
+        }
+    }
+
+    /// Read the failure counters stored in Redis, returning `None` if Redis is not available
+    async fn read_from_redis(
+        &self,
+        username_key: &str,
+        ip_key: Option<&str>,
+    ) -> Option<(u64, u64)> {
+        let mut redis = self.counters.redis()?;
+        let mut pipe = redis::pipe();
+        pipe.get(username_key);
+        if let Some(key) = ip_key {
+            pipe.get(key);
+        }
+        let failures: Vec<Option<u64>> = pipe
+            .query_async(&mut redis)
+            .await
+            .inspect_err(|e| tracing::warn!(error = %e, "Failed to read login failures from Redis"))
+            .ok()?;
+        let failures = |i: usize| failures.get(i).copied().flatten().unwrap_or(0);
+        Some((failures(0), failures(1)))
+    }
+
+    /// Read the failure counters stored in memory
+    fn read_from_memory(&self, username_key: &str, ip_key: Option<&str>) -> (u64, u64) {
+        let memory = self.memory();
+        let failures = |key: &str| memory.get(key).map_or(0, |c| c.failures);
+        (failures(username_key), ip_key.map_or(0, failures))
+    }
+
+    /// Increment the failure counters stored in Redis, returning `None` if Redis is not available
+    async fn increment_in_redis(&self, username_key: &str, ip_key: Option<&str>) -> Option<()> {
+        let mut redis = self.counters.redis()?;
+        let lockout_secs = i64::try_from(self.settings.lockout_secs).unwrap_or(i64::MAX);
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .incr(username_key, 1)
+            .ignore()
+            .expire(username_key, lockout_secs)
+            .ignore();
+        if let Some(key) = ip_key {
+            pipe.incr(key, 1)
+                .ignore()
+                .expire(key, lockout_secs)
+                .ignore();
+        }
+        pipe.query_async::<()>(&mut redis)
+            .await
+            .inspect_err(|e| tracing::warn!(error = %e, "Failed to record login failure in Redis"))
+            .ok()
+    }
+}
+
+/// Decide the outcome of an attempt based on the number of previous failures
+fn evaluate(
+    settings: &LoginThrottleSettings,
+    username_failures: u64,
+    ip_failures: u64,
+) -> LoginAttempt {
+    if username_failures >= settings.max_username_failures
+        || ip_failures >= settings.max_ip_failures
+    {
+        return LoginAttempt::LockedOut;
+    }
+
+    // Double the delay with each failure beyond the free attempts
+    let excess = username_failures.saturating_sub(settings.free_attempts);
+    let delay_millis = match excess {
+        0 => 0,
+        n => u32::try_from(n - 1)
+            .ok()
+            .and_then(|shift| 1_u64.checked_shl(shift))
+            .and_then(|factor| settings.base_delay_millis.checked_mul(factor))
+            .map_or(settings.max_delay_millis, |d| {
+                d.min(settings.max_delay_millis)
+            }),
+    };
+    LoginAttempt::Allowed(time::Duration::from_millis(delay_millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> LoginThrottleSettings {
+        LoginThrottleSettings {
+            max_username_failures: 10,
+            max_ip_failures: 50,
+            lockout_secs: 900,
+            free_attempts: 3,
+            base_delay_millis: 500,
+            max_delay_millis: 8000,
+        }
+    }
+
+    fn allowed_after_millis(millis: u64) -> LoginAttempt {
+        LoginAttempt::Allowed(time::Duration::from_millis(millis))
+    }
+
+    #[test]
+    fn the_first_attempts_are_not_delayed() {
+        assert_eq!(evaluate(&settings(), 0, 0), allowed_after_millis(0));
+        assert_eq!(evaluate(&settings(), 3, 0), allowed_after_millis(0));
+    }
+
+    #[test]
+    fn delays_double_up_to_the_maximum() {
+        assert_eq!(evaluate(&settings(), 4, 0), allowed_after_millis(500));
+        assert_eq!(evaluate(&settings(), 5, 0), allowed_after_millis(1000));
+        assert_eq!(evaluate(&settings(), 7, 0), allowed_after_millis(4000));
+        assert_eq!(evaluate(&settings(), 9, 0), allowed_after_millis(8000));
+    }
+
+    #[test]
+    fn too_many_failures_lock_out_the_username_or_the_client() {
+        assert_eq!(evaluate(&settings(), 10, 0), LoginAttempt::LockedOut);
+        assert_eq!(evaluate(&settings(), 0, 50), LoginAttempt::LockedOut);
+        assert_eq!(evaluate(&settings(), 0, 49), allowed_after_millis(0));
+    }
+
+    #[test]
+    fn huge_counters_do_not_overflow_the_delay() {
+        let settings = LoginThrottleSettings {
+            max_username_failures: u64::MAX,
+            ..settings()
+        };
+        assert_eq!(evaluate(&settings, 200, 0), allowed_after_millis(8000));
+    }
+}
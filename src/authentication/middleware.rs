@@ -1,13 +1,18 @@
-use std::fmt;
 use std::ops::Deref;
+use std::{fmt, time};
 
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
-use actix_web::error::InternalError;
 use actix_web::middleware::Next;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::{web, FromRequest, HttpMessage};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
 use uuid::Uuid;
 
+use crate::authentication::{revoke_user_session, touch_user_session};
+use crate::client_info::ClientInfo;
 use crate::session_state::TypedSession;
 use crate::utils::{e303_see_other, e500_internal_server_error};
 
@@ -35,11 +40,57 @@ impl Deref for UserId {
     }
 }
 
-/// Reject users that are not logged in
+/// Lifetime policy of admin sessions
+#[derive(Copy, Clone, Debug)]
+pub struct SessionTimeouts {
+    /// Time without any request after which a session expires
+    pub idle: time::Duration,
+    /// Time after login after which a session expires, regardless of activity
+    pub max_age: time::Duration,
+}
+
+/// Reason why a session expired
+#[derive(Debug, PartialEq, Eq)]
+enum SessionExpiry {
+    Idle,
+    MaxAge,
+}
+
+impl SessionExpiry {
+    /// Explain the expiry to the user
+    const fn message(&self) -> &'static str {
+        match self {
+            Self::Idle => "Your session has expired due to inactivity, please log in again",
+            Self::MaxAge => "Your session has expired, please log in again",
+        }
+    }
+}
+
+/// Check if a session has expired, treating sessions without timestamps as expired
+fn check_expiry(
+    timeouts: &SessionTimeouts,
+    logged_in_at: Option<DateTime<Utc>>,
+    last_activity: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Option<SessionExpiry> {
+    let elapsed_since = |t: Option<DateTime<Utc>>| {
+        t.and_then(|t| (now - t).to_std().ok())
+            .unwrap_or(time::Duration::MAX)
+    };
+    if elapsed_since(logged_in_at) > timeouts.max_age {
+        Some(SessionExpiry::MaxAge)
+    } else if elapsed_since(last_activity) > timeouts.idle {
+        Some(SessionExpiry::Idle)
+    } else {
+        None
+    }
+}
+
+/// Reject users that are not logged in, or whose session has expired
 #[allow(clippy::future_not_send)]
 pub async fn reject_logged_out_users(
     mut req: ServiceRequest,
-    next: Next<impl MessageBody>,
+    next: Next<impl MessageBody + 'static>,
 ) -> actix_web::Result<ServiceResponse<impl MessageBody>> {
     // Retrieve session state
     let session = {
@@ -47,13 +98,149 @@ pub async fn reject_logged_out_users(
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    // Check if the session state contains a `user_id`, otherwise return error
+    // Check if the session state contains a `user_id` whose sessions are still valid and a session that
+    // was not revoked, otherwise return error
     if let Some(user_id) = session.get_user_id().map_err(e500_internal_server_error)? {
-        req.extensions_mut().insert(user_id);
-        next.call(req).await
-    } else {
-        let response = e303_see_other("/login");
-        let e = anyhow::anyhow!("The user is not logged in");
-        Err(InternalError::from_response(e, response).into())
+        let db_pool = req
+            .app_data::<web::Data<PgPool>>()
+            .cloned()
+            .ok_or_else(|| e500_internal_server_error("Database pool not available"))?;
+        let current_epoch = get_session_epoch(user_id, &db_pool)
+            .await
+            .map_err(e500_internal_server_error)?;
+        let session_epoch = session
+            .get_session_epoch()
+            .map_err(e500_internal_server_error)?
+            .unwrap_or_default();
+        let session_id = session
+            .get_session_id()
+            .map_err(e500_internal_server_error)?;
+        let is_active = match session_id {
+            Some(session_id) if current_epoch == Some(session_epoch) => {
+                let client = ClientInfo::from_http_request(req.request());
+                touch_user_session(user_id, session_id, &client, &db_pool)
+                    .await
+                    .map_err(e500_internal_server_error)?
+            }
+            _ => false,
+        };
+
+        // Check if the session has been idle or open for too long, otherwise record the activity
+        if is_active {
+            let timeouts = req
+                .app_data::<web::Data<SessionTimeouts>>()
+                .cloned()
+                .ok_or_else(|| e500_internal_server_error("Session timeouts not available"))?;
+            let now = Utc::now();
+            let expiry = check_expiry(
+                &timeouts,
+                session
+                    .get_logged_in_at()
+                    .map_err(e500_internal_server_error)?,
+                session
+                    .get_last_activity()
+                    .map_err(e500_internal_server_error)?,
+                now,
+            );
+            let Some(expiry) = expiry else {
+                session
+                    .insert_last_activity(now)
+                    .map_err(e500_internal_server_error)?;
+                req.extensions_mut().insert(user_id);
+                return next
+                    .call(req)
+                    .await
+                    .map(ServiceResponse::map_into_left_body);
+            };
+            if let Some(session_id) = session_id {
+                revoke_user_session(user_id, session_id, &db_pool)
+                    .await
+                    .map_err(e500_internal_server_error)?;
+            }
+            FlashMessage::info(expiry.message()).send();
+        }
+        session.logout();
+    }
+
+    // Redirect with a response rather than an error, so that the logout and the flash message are saved
+    Ok(req
+        .into_response(e303_see_other("/login"))
+        .map_into_right_body())
+}
+
+/// Retrieve the current session epoch of a user, if the user exists
+#[tracing::instrument(name = "Get session epoch", skip(db_pool))]
+pub async fn get_session_epoch(user_id: UserId, db_pool: &PgPool) -> anyhow::Result<Option<i32>> {
+    let row = sqlx::query!(
+        r#"
+        SELECT session_epoch
+        FROM users
+        WHERE user_id = $1
+        "#,
+        *user_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to perform a query to fetch the session epoch")?;
+
+    Ok(row.map(|r| r.session_epoch))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn timeouts() -> SessionTimeouts {
+        SessionTimeouts {
+            idle: time::Duration::from_secs(30 * 60),
+            max_age: time::Duration::from_secs(12 * 60 * 60),
+        }
+    }
+
+    #[test]
+    fn recently_active_sessions_have_not_expired() {
+        let now = Utc::now();
+        let logged_in_at = now - Duration::hours(2);
+        let last_activity = now - Duration::minutes(29);
+        assert_eq!(
+            check_expiry(&timeouts(), Some(logged_in_at), Some(last_activity), now),
+            None
+        );
+    }
+
+    #[test]
+    fn idle_sessions_expire() {
+        let now = Utc::now();
+        let logged_in_at = now - Duration::hours(2);
+        let last_activity = now - Duration::minutes(31);
+        assert_eq!(
+            check_expiry(&timeouts(), Some(logged_in_at), Some(last_activity), now),
+            Some(SessionExpiry::Idle)
+        );
+    }
+
+    #[test]
+    fn active_sessions_expire_after_the_maximum_age() {
+        let now = Utc::now();
+        let logged_in_at = now - Duration::hours(13);
+        assert_eq!(
+            check_expiry(&timeouts(), Some(logged_in_at), Some(now), now),
+            Some(SessionExpiry::MaxAge)
+        );
+    }
+
+    #[test]
+    fn sessions_without_timestamps_expire() {
+        let now = Utc::now();
+        assert_eq!(
+            check_expiry(&timeouts(), None, Some(now), now),
+            Some(SessionExpiry::MaxAge)
+        );
+        assert_eq!(
+            check_expiry(&timeouts(), Some(now), None, now),
+            Some(SessionExpiry::Idle)
+        );
     }
 }
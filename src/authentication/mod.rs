@@ -1,5 +1,43 @@
+mod api_key;
 mod credentials;
+mod csrf;
+mod invitation;
+mod login_throttle;
 mod middleware;
+mod passkey;
+mod password_reset;
+mod role;
+mod totp;
+mod user_session;
 
-pub use credentials::{change_password, validate_creds, AuthError, Credentials};
-pub use middleware::{reject_logged_out_users, UserId};
+pub use api_key::{
+    create_api_key, reject_invalid_api_keys, revoke_api_key, ApiKeyId, ApiKeyScope, ApiKeyScopes,
+};
+pub use credentials::{
+    change_password, create_user, reset_password, validate_creds, validate_new_password, AuthError,
+    Credentials,
+};
+pub use csrf::{generate_csrf_token, reject_forged_requests, CSRF_TOKEN_FIELD, CSRF_TOKEN_HEADER};
+pub use invitation::{
+    accept_invitation, create_invitation, get_pending_invitation, InvitationError,
+    PendingInvitation, INVITATION_TTL_HOURS,
+};
+pub use login_throttle::{LoginAttempt, LoginThrottle};
+pub use middleware::{get_session_epoch, reject_logged_out_users, SessionTimeouts, UserId};
+pub use passkey::{
+    delete_passkey, get_passkeys, get_passkeys_by_username, list_passkeys, record_passkey_use,
+    store_passkey, PasskeyError, PasskeySummary,
+};
+pub use password_reset::{
+    complete_password_reset, create_password_reset, is_password_reset_pending, PasswordResetError,
+    PASSWORD_RESET_TTL_MINUTES,
+};
+pub use role::{get_role, reject_unauthorized_users, set_role, Role};
+pub use totp::{
+    confirm_totp_enrolment, disable_totp, is_totp_enabled, start_totp_enrolment,
+    verify_second_factor, TotpEnrolment,
+};
+pub use user_session::{
+    delete_expired_user_sessions, list_user_sessions, revoke_other_user_sessions,
+    revoke_user_session, start_user_session, touch_user_session, UserSession,
+};
@@ -0,0 +1,232 @@
+use std::fmt;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+use webauthn_rs::prelude::{AuthenticationResult, Passkey, WebauthnError};
+
+use crate::authentication::UserId;
+use crate::problem_details::{Problem, ProblemDetails};
+use crate::utils::error_chain_fmt;
+
+/// Passkey registration or authentication error
+#[derive(thiserror::Error)]
+pub enum PasskeyError {
+    #[error("The passkey ceremony was not started or has already been completed")]
+    MissingCeremony,
+    #[error("Passkey login is not available")]
+    Unavailable,
+    #[error("The passkey name must be between 1 and {0} characters long")]
+    InvalidName(usize),
+    #[error("The passkey could not be verified")]
+    InvalidCredential(#[source] WebauthnError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl fmt::Debug for PasskeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PasskeyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::MissingCeremony
+            | Self::Unavailable
+            | Self::InvalidName(_)
+            | Self::InvalidCredential(_) => StatusCode::BAD_REQUEST,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ProblemDetails::new(self.status_code(), self.detail()).into_response()
+    }
+}
+
+impl Problem for PasskeyError {
+    fn detail(&self) -> String {
+        match self {
+            Self::MissingCeremony
+            | Self::Unavailable
+            | Self::InvalidName(_)
+            | Self::InvalidCredential(_) => self.to_string(),
+            Self::UnexpectedError(_) => "Something went wrong".to_string(),
+        }
+    }
+}
+
+/// Passkey registered by a user, as listed in the admin area
+pub struct PasskeySummary {
+    pub passkey_id: Uuid,
+    pub name: String,
+    pub created_at: chrono::DateTime<Utc>,
+    pub last_used_at: Option<chrono::DateTime<Utc>>,
+}
+
+/// Retrieve the passkeys registered by a user, to be used in a `WebAuthn` ceremony
+#[tracing::instrument(skip(db_pool))]
+pub async fn get_passkeys(user_id: UserId, db_pool: &PgPool) -> anyhow::Result<Vec<Passkey>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT passkey
+        FROM passkeys
+        WHERE user_id = $1
+        "#,
+        *user_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve passkeys")?;
+
+    rows.into_iter()
+        .map(|r| serde_json::from_value(r.passkey).context("Failed to deserialize passkey"))
+        .collect()
+}
+
+/// Retrieve the summary of the passkeys registered by a user
+#[tracing::instrument(skip(db_pool))]
+pub async fn list_passkeys(user_id: UserId, db_pool: &PgPool) -> sqlx::Result<Vec<PasskeySummary>> {
+    sqlx::query_as!(
+        PasskeySummary,
+        r#"
+        SELECT passkey_id, name, created_at, last_used_at
+        FROM passkeys
+        WHERE user_id = $1
+        ORDER BY created_at
+        "#,
+        *user_id
+    )
+    .fetch_all(db_pool)
+    .await
+}
+
+/// Retrieve the user that owns a username along with their passkeys, if any
+#[tracing::instrument(skip(db_pool))]
+pub async fn get_passkeys_by_username(
+    username: &str,
+    db_pool: &PgPool,
+) -> anyhow::Result<Option<(UserId, Vec<Passkey>)>> {
+    let Some(row) = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM users
+        WHERE username = $1
+        "#,
+        username
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to perform a query to retrieve the user")?
+    else {
+        return Ok(None);
+    };
+
+    let user_id = UserId::new(row.user_id);
+    let passkeys = get_passkeys(user_id, db_pool).await?;
+    Ok(Some((user_id, passkeys)))
+}
+
+/// Store a passkey registered by a user
+#[tracing::instrument(skip(db_pool, passkey))]
+pub async fn store_passkey(
+    user_id: UserId,
+    name: &str,
+    passkey: &Passkey,
+    db_pool: &PgPool,
+) -> anyhow::Result<()> {
+    let credential_id: &[u8] = passkey.cred_id().as_ref();
+    sqlx::query!(
+        r#"
+        INSERT INTO passkeys (passkey_id, user_id, name, credential_id, passkey, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        *user_id,
+        name,
+        credential_id,
+        serde_json::to_value(passkey).context("Failed to serialize passkey")?,
+        Utc::now()
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store passkey")?;
+
+    Ok(())
+}
+
+/// Record a successful authentication, updating the signature counter of the passkey that was used
+#[tracing::instrument(skip_all, fields(user_id=%user_id))]
+pub async fn record_passkey_use(
+    user_id: UserId,
+    auth_result: &AuthenticationResult,
+    db_pool: &PgPool,
+) -> anyhow::Result<()> {
+    let credential_id: &[u8] = auth_result.cred_id().as_ref();
+    let row = sqlx::query!(
+        r#"
+        SELECT passkey
+        FROM passkeys
+        WHERE
+            user_id = $1 AND
+            credential_id = $2
+        "#,
+        *user_id,
+        credential_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to retrieve the passkey that was used")?;
+    let mut passkey: Passkey =
+        serde_json::from_value(row.passkey).context("Failed to deserialize passkey")?;
+    passkey.update_credential(auth_result);
+
+    sqlx::query!(
+        r#"
+        UPDATE passkeys
+        SET
+            passkey = $3,
+            last_used_at = now()
+        WHERE
+            user_id = $1 AND
+            credential_id = $2
+        "#,
+        *user_id,
+        credential_id,
+        serde_json::to_value(&passkey).context("Failed to serialize passkey")?
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to update the passkey that was used")?;
+
+    Ok(())
+}
+
+/// Delete a passkey owned by a user and return `true` if it existed
+#[tracing::instrument(skip(db_pool))]
+pub async fn delete_passkey(
+    user_id: UserId,
+    passkey_id: Uuid,
+    db_pool: &PgPool,
+) -> sqlx::Result<bool> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM passkeys
+        WHERE
+            passkey_id = $1 AND
+            user_id = $2
+        "#,
+        passkey_id,
+        *user_id
+    )
+    .execute(db_pool)
+    .await?
+    .rows_affected();
+
+    Ok(n_deleted_rows > 0)
+}
@@ -0,0 +1,148 @@
+use std::{fmt, iter};
+
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::SecretString;
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, PgPool};
+
+use crate::authentication::{reset_password, UserId};
+use crate::domain::EmailAddress;
+use crate::utils::{error_chain_fmt, PgTransaction};
+
+/// Number of minutes after which a password reset link can no longer be used
+pub const PASSWORD_RESET_TTL_MINUTES: i64 = 30;
+
+/// Password reset error
+#[derive(thiserror::Error)]
+pub enum PasswordResetError {
+    #[error("The password reset link is invalid or has expired")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl fmt::Debug for PasswordResetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Generate a pseudo-random password reset token
+fn generate_reset_token() -> String {
+    let mut rng = thread_rng();
+    iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+/// Hash a password reset token, so that a leaked table cannot be used to take over accounts
+fn hash_reset_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Create a password reset token for the user with the provided email address, if any
+#[tracing::instrument(skip(transaction, email))]
+pub async fn create_password_reset(
+    transaction: &mut PgTransaction,
+    email: &EmailAddress,
+) -> sqlx::Result<Option<String>> {
+    let token = generate_reset_token();
+    let now = Utc::now();
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO password_resets (token_hash, user_id, created_at, expires_at)
+        SELECT $1, user_id, $2, $3
+        FROM users
+        WHERE lower(email) = lower($4)
+        "#,
+        hash_reset_token(&token),
+        now,
+        now + Duration::minutes(PASSWORD_RESET_TTL_MINUTES),
+        email.as_ref()
+    )
+    .execute(&mut **transaction)
+    .await?
+    .rows_affected();
+
+    Ok((n_inserted_rows > 0).then_some(token))
+}
+
+/// Check if a password reset token can still be used
+#[tracing::instrument(skip_all)]
+pub async fn is_password_reset_pending(db_pool: &PgPool, token: &str) -> sqlx::Result<bool> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM password_resets
+            WHERE
+                token_hash = $1 AND
+                used_at IS NULL AND
+                expires_at > now()
+        ) AS "exists!"
+        "#,
+        hash_reset_token(token)
+    )
+    .fetch_one(db_pool)
+    .await?;
+
+    Ok(row.exists)
+}
+
+/// Use a password reset token to set a new password, logging the user out everywhere
+#[tracing::instrument(skip_all)]
+pub async fn complete_password_reset(
+    db_pool: &PgPool,
+    token: &str,
+    password: SecretString,
+) -> Result<UserId, PasswordResetError> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire database connection to reset a password")?;
+
+    // Consume the token, so that it cannot be used twice
+    let user_id = sqlx::query!(
+        r#"
+        UPDATE password_resets
+        SET used_at = now()
+        WHERE
+            token_hash = $1 AND
+            used_at IS NULL AND
+            expires_at > now()
+        RETURNING user_id
+        "#,
+        hash_reset_token(token)
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to consume the password reset token")?
+    .map(|r| UserId::new(r.user_id))
+    .ok_or(PasswordResetError::InvalidToken)?;
+
+    // Change the password and invalidate any other pending reset link for the same user
+    reset_password(user_id, password, &mut transaction).await?;
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE password_resets
+            SET used_at = now()
+            WHERE
+                user_id = $1 AND
+                used_at IS NULL
+            "#,
+            *user_id
+        ))
+        .await
+        .context("Failed to invalidate pending password reset tokens")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to reset a password")?;
+
+    Ok(user_id)
+}
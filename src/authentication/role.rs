@@ -0,0 +1,161 @@
+use std::fmt;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::authentication::UserId;
+use crate::utils::{e500_internal_server_error, PgTransaction};
+
+/// Role of an admin user, ordered from the least to the most privileged
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    /// Represent role as a string
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Editor => "editor",
+            Self::Owner => "owner",
+        }
+    }
+
+    /// Parse a role from its string representation
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "viewer" => Ok(Self::Viewer),
+            "editor" => Ok(Self::Editor),
+            "owner" => Ok(Self::Owner),
+            other => Err(format!("{other} is not a valid role")),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Retrieve the role of a user from the database
+#[tracing::instrument(name = "Get user role", skip(db_pool))]
+pub async fn get_role(user_id: UserId, db_pool: &PgPool) -> anyhow::Result<Role> {
+    let row = sqlx::query!(
+        r#"
+        SELECT role
+        FROM users
+        WHERE user_id = $1
+        "#,
+        *user_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to perform a query to fetch the user role")?;
+
+    Role::parse(&row.role).map_err(|e| anyhow::anyhow!(e))
+}
+
+/// Change the role of a user within a transaction and return `true` if it was changed
+///
+/// The change is refused if it would leave the application without any owner. Owners are locked until the transaction
+/// completes, so that concurrent demotions cannot remove the last of them.
+#[tracing::instrument(name = "Set user role", skip(transaction))]
+pub async fn set_role(
+    user_id: UserId,
+    role: Role,
+    transaction: &mut PgTransaction,
+) -> anyhow::Result<bool> {
+    let owners = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM users
+        WHERE role = 'owner'
+        FOR UPDATE
+        "#
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to lock the owners")?;
+    if role != Role::Owner && owners.iter().all(|r| r.user_id == *user_id) {
+        return Ok(false);
+    }
+
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE users
+        SET role = $2
+        WHERE user_id = $1
+        "#,
+        *user_id,
+        role.as_str()
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to change the user role")?
+    .rows_affected();
+
+    Ok(n_updated_rows > 0)
+}
+
+/// Reject logged-in users whose role is below the one required by the requested route
+///
+/// Must run after `reject_logged_out_users`, which makes the `user_id` available.
+#[allow(clippy::future_not_send)]
+pub async fn reject_unauthorized_users(
+    required: Role,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> actix_web::Result<ServiceResponse<impl MessageBody>> {
+    // Retrieve the role of the logged-in user
+    let user_id = req
+        .extensions()
+        .get::<UserId>()
+        .copied()
+        .ok_or_else(|| e500_internal_server_error("The user is not logged in"))?;
+    let db_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .ok_or_else(|| e500_internal_server_error("Database pool not available"))?;
+    let role = get_role(user_id, &db_pool)
+        .await
+        .map_err(e500_internal_server_error)?;
+
+    // Check if the role is allowed to access the route, otherwise return error
+    if role >= required {
+        req.extensions_mut().insert(role);
+        next.call(req).await
+    } else {
+        let e = anyhow::anyhow!("The {role} role cannot access this route, {required} is required");
+        Err(InternalError::from_response(e, HttpResponse::Forbidden().finish()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_err;
+
+    use super::*;
+
+    #[test]
+    fn roles_are_ordered_by_privilege() {
+        assert!(Role::Viewer < Role::Editor);
+        assert!(Role::Editor < Role::Owner);
+    }
+
+    #[test]
+    fn roles_round_trip_through_strings() {
+        for role in [Role::Viewer, Role::Editor, Role::Owner] {
+            assert_eq!(Role::parse(role.as_str()), Ok(role));
+        }
+        assert_err!(Role::parse("admin"));
+    }
+}
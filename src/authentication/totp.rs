@@ -0,0 +1,345 @@
+use std::iter;
+
+use anyhow::Context;
+use chrono::Utc;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, PgPool};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::authentication::UserId;
+
+/// Issuer displayed by authenticator apps
+const TOTP_ISSUER: &str = "zero2prod";
+
+/// Duration of a TOTP time step, in seconds
+const TOTP_STEP: u64 = 30;
+
+/// Number of recovery codes generated when the enrolment is confirmed
+const RECOVERY_CODES: usize = 10;
+
+/// Length of each recovery code
+const RECOVERY_CODE_LEN: usize = 10;
+
+/// Data needed to add a TOTP secret to an authenticator app
+pub struct TotpEnrolment {
+    pub secret: String,
+    pub otpauth_uri: String,
+    pub qr_code_png_base64: String,
+}
+
+/// Build a TOTP generator from a base32-encoded secret
+fn build_totp(secret: &str, username: &str) -> anyhow::Result<TOTP> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("Invalid TOTP secret: {e:?}"))?;
+    Ok(TOTP::new_unchecked(
+        Algorithm::SHA1,
+        6,
+        1,
+        TOTP_STEP,
+        secret,
+        Some(TOTP_ISSUER.to_string()),
+        username.to_string(),
+    ))
+}
+
+/// Return the time step matched by a code, allowing one step of clock skew in both directions
+fn matching_step(totp: &TOTP, code: &str) -> Option<i64> {
+    let now = u64::try_from(Utc::now().timestamp()).ok()?;
+    let current = now / TOTP_STEP;
+    [current.saturating_sub(1), current, current + 1]
+        .into_iter()
+        .find(|step| totp.generate(step * TOTP_STEP) == code)
+        .and_then(|step| i64::try_from(step).ok())
+}
+
+/// Hash a recovery code before storing or looking it up
+fn hash_recovery_code(code: &str) -> String {
+    format!(
+        "{:x}",
+        Sha256::digest(code.trim().to_lowercase().as_bytes())
+    )
+}
+
+/// Generate a set of pseudo-random recovery codes
+fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = thread_rng();
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            iter::repeat_with(|| rng.sample(Alphanumeric))
+                .map(char::from)
+                .take(RECOVERY_CODE_LEN)
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .collect()
+}
+
+/// Check if a user has a confirmed TOTP second factor
+#[tracing::instrument(skip(db_pool))]
+pub async fn is_totp_enabled(user_id: UserId, db_pool: &PgPool) -> sqlx::Result<bool> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM totp_credentials
+            WHERE
+                user_id = $1 AND
+                confirmed_at IS NOT NULL
+        ) AS "exists!"
+        "#,
+        *user_id
+    )
+    .fetch_one(db_pool)
+    .await?;
+
+    Ok(row.exists)
+}
+
+/// Generate a new TOTP secret for a user, replacing any enrolment that was not confirmed
+#[tracing::instrument(skip(db_pool))]
+pub async fn start_totp_enrolment(
+    user_id: UserId,
+    username: &str,
+    db_pool: &PgPool,
+) -> anyhow::Result<TotpEnrolment> {
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO totp_credentials (user_id, secret, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT (user_id) DO UPDATE
+        SET
+            secret = EXCLUDED.secret,
+            created_at = EXCLUDED.created_at
+        WHERE totp_credentials.confirmed_at IS NULL
+        "#,
+        *user_id,
+        secret
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store the TOTP secret")?;
+
+    let totp = build_totp(&secret, username)?;
+    let qr_code_png_base64 = totp
+        .get_qr_base64()
+        .map_err(|e| anyhow::anyhow!("Failed to generate the QR code: {e}"))?;
+    Ok(TotpEnrolment {
+        otpauth_uri: totp.get_url(),
+        secret,
+        qr_code_png_base64,
+    })
+}
+
+/// Confirm a pending enrolment with a code from the authenticator app and return the recovery codes
+#[tracing::instrument(skip(db_pool, code))]
+pub async fn confirm_totp_enrolment(
+    user_id: UserId,
+    code: &str,
+    db_pool: &PgPool,
+) -> anyhow::Result<Option<Vec<String>>> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire database connection to confirm the TOTP enrolment")?;
+
+    // Check the code against the pending secret
+    let Some(row) = sqlx::query!(
+        r#"
+        SELECT secret
+        FROM totp_credentials
+        WHERE
+            user_id = $1 AND
+            confirmed_at IS NULL
+        FOR UPDATE
+        "#,
+        *user_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the pending TOTP secret")?
+    else {
+        return Ok(None);
+    };
+    // The account name only appears in the provisioning URI and does not affect the codes
+    let Some(step) = matching_step(&build_totp(&row.secret, "")?, code.trim()) else {
+        return Ok(None);
+    };
+
+    // Enable the second factor and replace any previous recovery codes
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            UPDATE totp_credentials
+            SET
+                confirmed_at = now(),
+                last_used_step = $2
+            WHERE user_id = $1
+            "#,
+            *user_id,
+            step
+        ))
+        .await
+        .context("Failed to confirm the TOTP enrolment")?;
+    transaction
+        .execute(sqlx::query!(
+            "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+            *user_id
+        ))
+        .await
+        .context("Failed to delete previous recovery codes")?;
+    let recovery_codes = generate_recovery_codes();
+    let code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|c| hash_recovery_code(c))
+        .collect();
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO totp_recovery_codes (user_id, code_hash)
+            SELECT $1, code_hash
+            FROM unnest($2::text[]) AS code_hash
+            "#,
+            *user_id,
+            &code_hashes[..]
+        ))
+        .await
+        .context("Failed to store recovery codes")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm the TOTP enrolment")?;
+
+    Ok(Some(recovery_codes))
+}
+
+/// Verify a TOTP code, which cannot be reused, or a single-use recovery code
+#[tracing::instrument(skip(db_pool, code))]
+pub async fn verify_second_factor(
+    user_id: UserId,
+    code: &str,
+    db_pool: &PgPool,
+) -> anyhow::Result<bool> {
+    let code = code.trim();
+    let Some(row) = sqlx::query!(
+        r#"
+        SELECT secret
+        FROM totp_credentials
+        WHERE
+            user_id = $1 AND
+            confirmed_at IS NOT NULL
+        "#,
+        *user_id
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to retrieve the TOTP secret")?
+    else {
+        return Ok(false);
+    };
+
+    // Accept a TOTP code only if its time step is more recent than the last one used
+    if let Some(step) = matching_step(&build_totp(&row.secret, "")?, code) {
+        let n_updated_rows = sqlx::query!(
+            r#"
+            UPDATE totp_credentials
+            SET last_used_step = $2
+            WHERE
+                user_id = $1 AND
+                (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            *user_id,
+            step
+        )
+        .execute(db_pool)
+        .await
+        .context("Failed to record the TOTP time step")?
+        .rows_affected();
+        return Ok(n_updated_rows > 0);
+    }
+
+    // Otherwise, try to consume a recovery code
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE totp_recovery_codes
+        SET used_at = now()
+        WHERE
+            user_id = $1 AND
+            code_hash = $2 AND
+            used_at IS NULL
+        "#,
+        *user_id,
+        hash_recovery_code(code)
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to consume the recovery code")?
+    .rows_affected();
+
+    Ok(n_updated_rows > 0)
+}
+
+/// Disable the TOTP second factor of a user, deleting the secret and the recovery codes
+#[tracing::instrument(skip(db_pool))]
+pub async fn disable_totp(user_id: UserId, db_pool: &PgPool) -> anyhow::Result<()> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire database connection to disable TOTP")?;
+    transaction
+        .execute(sqlx::query!(
+            "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+            *user_id
+        ))
+        .await
+        .context("Failed to delete recovery codes")?;
+    transaction
+        .execute(sqlx::query!(
+            "DELETE FROM totp_credentials WHERE user_id = $1",
+            *user_id
+        ))
+        .await
+        .context("Failed to delete the TOTP secret")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to disable TOTP")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_codes_match_the_current_step() {
+        let secret = Secret::generate_secret().to_encoded().to_string();
+        let totp = build_totp(&secret, "admin").unwrap();
+        let code = totp.generate_current().unwrap();
+        assert!(matching_step(&totp, &code).is_some());
+    }
+
+    #[test]
+    fn codes_are_bound_to_the_username_in_the_uri() {
+        let secret = Secret::generate_secret().to_encoded().to_string();
+        let uri = build_totp(&secret, "admin").unwrap().get_url();
+        assert!(uri.starts_with("otpauth://totp/"));
+        assert!(uri.contains("admin"));
+        assert!(uri.contains(&format!("issuer={TOTP_ISSUER}")));
+    }
+
+    #[test]
+    fn recovery_codes_are_unique_and_case_insensitive() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        assert!(codes.iter().all(|c| c.len() == RECOVERY_CODE_LEN));
+        assert_eq!(
+            hash_recovery_code(&codes[0]),
+            hash_recovery_code(&format!(" {} ", codes[0].to_uppercase()))
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{get_session_epoch, SessionTimeouts, UserId};
+use crate::client_info::ClientInfo;
+use crate::session_state::TypedSession;
+
+/// Session of a user, as listed in the admin area
+pub struct UserSession {
+    pub session_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: chrono::DateTime<Utc>,
+    pub last_seen_at: chrono::DateTime<Utc>,
+}
+
+/// Start an authenticated session for a user and add it to their session index
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(skip(session, db_pool))]
+pub async fn start_user_session(
+    session: &TypedSession,
+    user_id: UserId,
+    client: &ClientInfo,
+    db_pool: &PgPool,
+) -> anyhow::Result<()> {
+    // Record the session epoch, so that the session can be invalidated by a password reset
+    let session_epoch = get_session_epoch(user_id, db_pool)
+        .await?
+        .unwrap_or_default();
+
+    // Index the session, so that it can be listed and revoked from other devices
+    let session_id = Uuid::new_v4();
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (session_id, user_id, user_agent, ip, created_at, last_seen_at)
+        VALUES ($1, $2, $3, $4, $5, $5)
+        "#,
+        session_id,
+        *user_id,
+        client.user_agent,
+        client.ip,
+        now
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store the session")?;
+
+    session
+        .log_in(user_id, session_epoch, session_id)
+        .context("Failed to start the session")
+}
+
+/// Record the activity of a session and return `false` if it was revoked
+#[tracing::instrument(skip(client, db_pool))]
+pub async fn touch_user_session(
+    user_id: UserId,
+    session_id: Uuid,
+    client: &ClientInfo,
+    db_pool: &PgPool,
+) -> anyhow::Result<bool> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET
+            user_agent = $3,
+            ip = $4,
+            last_seen_at = now()
+        WHERE
+            session_id = $1 AND
+            user_id = $2
+        "#,
+        session_id,
+        *user_id,
+        client.user_agent,
+        client.ip
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to update the session")?
+    .rows_affected();
+
+    Ok(n_updated_rows > 0)
+}
+
+/// Retrieve the unexpired sessions of a user, most recently active first
+#[tracing::instrument(skip(db_pool))]
+pub async fn list_user_sessions(
+    user_id: UserId,
+    timeouts: SessionTimeouts,
+    db_pool: &PgPool,
+) -> sqlx::Result<Vec<UserSession>> {
+    sqlx::query_as!(
+        UserSession,
+        r#"
+        SELECT session_id, user_agent, ip, created_at, last_seen_at
+        FROM user_sessions
+        WHERE
+            user_id = $1 AND
+            created_at >= now() - make_interval(secs => $2) AND
+            last_seen_at >= now() - make_interval(secs => $3)
+        ORDER BY last_seen_at DESC
+        "#,
+        *user_id,
+        timeouts.max_age.as_secs_f64(),
+        timeouts.idle.as_secs_f64()
+    )
+    .fetch_all(db_pool)
+    .await
+}
+
+/// Revoke a session of a user and return `true` if it existed
+#[tracing::instrument(skip(db_pool))]
+pub async fn revoke_user_session(
+    user_id: UserId,
+    session_id: Uuid,
+    db_pool: &PgPool,
+) -> sqlx::Result<bool> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE
+            session_id = $1 AND
+            user_id = $2
+        "#,
+        session_id,
+        *user_id
+    )
+    .execute(db_pool)
+    .await?
+    .rows_affected();
+
+    Ok(n_deleted_rows > 0)
+}
+
+/// Revoke all sessions of a user except the current one and return how many were revoked
+#[tracing::instrument(skip(db_pool))]
+pub async fn revoke_other_user_sessions(
+    user_id: UserId,
+    current_session_id: Option<Uuid>,
+    db_pool: &PgPool,
+) -> sqlx::Result<u64> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE
+            user_id = $1 AND
+            session_id IS DISTINCT FROM $2
+        "#,
+        *user_id,
+        current_session_id
+    )
+    .execute(db_pool)
+    .await?
+    .rows_affected();
+
+    Ok(n_deleted_rows)
+}
+
+/// Delete the sessions that are idle or open for too long and return how many were deleted
+#[tracing::instrument(skip(db_pool))]
+pub async fn delete_expired_user_sessions(
+    timeouts: SessionTimeouts,
+    db_pool: &PgPool,
+) -> sqlx::Result<u64> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM user_sessions
+        WHERE
+            created_at < now() - make_interval(secs => $1) OR
+            last_seen_at < now() - make_interval(secs => $2)
+        "#,
+        timeouts.max_age.as_secs_f64(),
+        timeouts.idle.as_secs_f64()
+    )
+    .execute(db_pool)
+    .await?
+    .rows_affected();
+
+    tracing::info!("Deleted {n_deleted_rows} expired user sessions");
+    Ok(n_deleted_rows)
+}
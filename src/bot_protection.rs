@@ -0,0 +1,195 @@
+use std::time;
+
+use reqwest::Url;
+use secrecy::{ExposeSecret, SecretString};
+
+/// Challenge verification request data, shared by hCaptcha and Turnstile
+#[derive(serde::Serialize)]
+struct VerifyRequest<'a> {
+    secret: &'a str,
+    response: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remoteip: Option<&'a str>,
+}
+
+/// Challenge verification response data
+#[derive(serde::Deserialize)]
+struct VerifyResponse {
+    success: bool,
+}
+
+/// Client of a challenge verification server, such as hCaptcha or Cloudflare Turnstile
+#[derive(Clone)]
+pub struct ChallengeVerifier {
+    http_client: reqwest::Client,
+    verify_url: Url,
+    secret_key: SecretString,
+}
+
+impl ChallengeVerifier {
+    pub fn new(verify_url: Url, secret_key: SecretString, timeout: time::Duration) -> Self {
+        let http_client = reqwest::Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            verify_url,
+            secret_key,
+        }
+    }
+
+    /// Verify the response token of a challenge solved by a client
+    /// <https://docs.hcaptcha.com/#verify-the-user-response-server-side>
+    pub async fn verify(&self, response: &str, remote_ip: Option<&str>) -> reqwest::Result<bool> {
+        let request_body = VerifyRequest {
+            secret: self.secret_key.expose_secret(),
+            response,
+            remoteip: remote_ip,
+        };
+
+        let outcome: VerifyResponse = self
+            .http_client
+            .post(self.verify_url.clone())
+            .form(&request_body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(outcome.success)
+    }
+}
+
+/// Outcome of the bot checks performed on a submitted form
+#[derive(Debug, PartialEq, Eq)]
+pub enum BotCheck {
+    /// The form was most likely submitted by a human
+    Passed,
+    /// The honeypot field, hidden from humans, was filled in
+    HoneypotFilled,
+    /// The challenge response is missing or was rejected by the verification server
+    ChallengeFailed,
+}
+
+/// Bot protection of public forms, based on a honeypot field and an optional challenge verification
+pub struct BotProtection {
+    verifier: Option<ChallengeVerifier>,
+}
+
+impl BotProtection {
+    pub const fn new(verifier: Option<ChallengeVerifier>) -> Self {
+        Self { verifier }
+    }
+
+    /// Check the honeypot field and the challenge response of a submitted form
+    #[tracing::instrument(name = "Checking for bots", skip_all)]
+    pub async fn check(
+        &self,
+        honeypot: Option<&str>,
+        challenge_response: Option<&str>,
+        remote_ip: Option<&str>,
+    ) -> reqwest::Result<BotCheck> {
+        // Humans do not see the honeypot field, so only bots fill it in
+        if honeypot.is_some_and(|h| !h.trim().is_empty()) {
+            return Ok(BotCheck::HoneypotFilled);
+        }
+
+        // Challenges are only verified if a verification server is configured
+        let Some(verifier) = &self.verifier else {
+            return Ok(BotCheck::Passed);
+        };
+        let Some(response) = challenge_response.filter(|r| !r.is_empty()) else {
+            return Ok(BotCheck::ChallengeFailed);
+        };
+        if verifier.verify(response, remote_ip).await? {
+            Ok(BotCheck::Passed)
+        } else {
+            Ok(BotCheck::ChallengeFailed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+    use wiremock::matchers::{any, body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    /// Get a bot protection using a test verification server
+    fn bot_protection(mock_server: &MockServer) -> BotProtection {
+        BotProtection::new(Some(ChallengeVerifier::new(
+            format!("{}/siteverify", mock_server.uri()).parse().unwrap(),
+            SecretString::from("secret-key"),
+            time::Duration::from_millis(200),
+        )))
+    }
+
+    #[tokio::test]
+    async fn a_filled_honeypot_is_detected_without_verifying_the_challenge() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = bot_protection(&mock_server)
+            .check(Some("https://spam.example.com"), Some("token"), None)
+            .await;
+        assert_ok_eq!(outcome, BotCheck::HoneypotFilled);
+    }
+
+    #[tokio::test]
+    async fn challenges_are_not_required_without_a_verification_server() {
+        let outcome = BotProtection::new(None).check(Some(""), None, None).await;
+        assert_ok_eq!(outcome, BotCheck::Passed);
+    }
+
+    #[tokio::test]
+    async fn a_missing_challenge_response_fails() {
+        let mock_server = MockServer::start().await;
+        let outcome = bot_protection(&mock_server).check(None, None, None).await;
+        assert_ok_eq!(outcome, BotCheck::ChallengeFailed);
+    }
+
+    #[tokio::test]
+    async fn challenge_responses_are_verified_by_the_server() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/siteverify"))
+            .and(body_string_contains("secret=secret-key"))
+            .and(body_string_contains("response=valid-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": false,
+                "error-codes": ["invalid-input-response"]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let bot_protection = bot_protection(&mock_server);
+        let outcome = bot_protection.check(None, Some("valid-token"), None).await;
+        assert_ok_eq!(outcome, BotCheck::Passed);
+        let outcome = bot_protection.check(None, Some("forged-token"), None).await;
+        assert_ok_eq!(outcome, BotCheck::ChallengeFailed);
+    }
+
+    #[tokio::test]
+    async fn verification_errors_are_reported() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+
+        let outcome = bot_protection(&mock_server)
+            .check(None, Some("token"), None)
+            .await;
+        assert_err!(outcome);
+    }
+}
@@ -0,0 +1,128 @@
+use std::future::{ready, Ready};
+use std::net::IpAddr;
+
+use actix_web::dev::Payload;
+use actix_web::http::header::{HeaderName, USER_AGENT};
+use actix_web::{web, FromRequest, HttpRequest};
+
+/// Maximum length of a stored user agent string
+const MAX_USER_AGENT_LEN: usize = 512;
+
+/// Header in which reverse proxies append the address of the client they forward requests for
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// Reverse proxies whose `X-Forwarded-For` header is trusted to report the client address
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+/// Get the client address of a request
+///
+/// Forwarded addresses are client-supplied unless appended by a trusted proxy, so the header is only read when the
+/// peer is a trusted proxy, from the closest hop to the first address that was not added by a trusted proxy.
+fn client_ip(req: &HttpRequest, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let mut ip = req.peer_addr()?.ip();
+    if !trusted_proxies.contains(&ip) {
+        return Some(ip);
+    }
+    let forwarded: Vec<&str> = req
+        .headers()
+        .get_all(X_FORWARDED_FOR)
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .collect();
+    for hop in forwarded.into_iter().rev() {
+        let Ok(hop) = hop.trim().parse() else {
+            break;
+        };
+        ip = hop;
+        if !trusted_proxies.contains(&ip) {
+            break;
+        }
+    }
+    Some(ip)
+}
+
+/// Client information attached to an incoming request
+#[derive(Clone, Debug, Default)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    /// Extract client information from a request
+    pub fn from_http_request(req: &HttpRequest) -> Self {
+        let trusted_proxies = req
+            .app_data::<web::Data<TrustedProxies>>()
+            .map_or(&[][..], |p| &p.0);
+        let ip = client_ip(req, trusted_proxies).map(|ip| ip.to_string());
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(|ua| ua.chars().take(MAX_USER_AGENT_LEN).collect());
+        Self { ip, user_agent }
+    }
+}
+
+impl FromRequest for ClientInfo {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(Self::from_http_request(req)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn request(peer: &str, forwarded_for: Option<&str>) -> HttpRequest {
+        let mut req = TestRequest::default().peer_addr(format!("{peer}:40000").parse().unwrap());
+        if let Some(forwarded_for) = forwarded_for {
+            req = req.insert_header((X_FORWARDED_FOR, forwarded_for));
+        }
+        req.to_http_request()
+    }
+
+    fn ip(req: &HttpRequest, trusted_proxies: &[&str]) -> Option<String> {
+        let trusted_proxies: Vec<IpAddr> = trusted_proxies
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        client_ip(req, &trusted_proxies).map(|ip| ip.to_string())
+    }
+
+    #[test]
+    fn forwarded_addresses_are_ignored_without_trusted_proxies() {
+        let req = request("203.0.113.7", Some("10.1.2.3"));
+        assert_eq!(ip(&req, &[]).as_deref(), Some("203.0.113.7"));
+    }
+
+    #[test]
+    fn forwarded_addresses_are_ignored_from_untrusted_peers() {
+        let req = request("203.0.113.7", Some("10.1.2.3"));
+        assert_eq!(ip(&req, &["192.0.2.1"]).as_deref(), Some("203.0.113.7"));
+    }
+
+    #[test]
+    fn the_closest_untrusted_hop_is_the_client() {
+        // The leftmost address was supplied by the client, the others were appended by the proxies
+        let req = request("192.0.2.1", Some("10.1.2.3, 198.51.100.9, 192.0.2.2"));
+        assert_eq!(
+            ip(&req, &["192.0.2.1", "192.0.2.2"]).as_deref(),
+            Some("198.51.100.9")
+        );
+    }
+
+    #[test]
+    fn the_trusted_proxy_is_the_client_if_the_header_is_invalid() {
+        let req = request("192.0.2.1", Some("not-an-ip"));
+        assert_eq!(ip(&req, &["192.0.2.1"]).as_deref(), Some("192.0.2.1"));
+        let req = request("192.0.2.1", None);
+        assert_eq!(ip(&req, &["192.0.2.1"]).as_deref(), Some("192.0.2.1"));
+    }
+}
@@ -1,3 +1,4 @@
+use std::net::IpAddr;
 use std::{env, time};
 
 use config::{Config, ConfigError, Environment, File};
@@ -7,7 +8,11 @@ use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
 use tracing::log::LevelFilter;
 use url::ParseError;
+use webauthn_rs::{Webauthn, WebauthnBuilder};
 
+use crate::authentication::SessionTimeouts;
+use crate::bot_protection::ChallengeVerifier;
+use crate::client_info::TrustedProxies;
 use crate::domain::EmailAddress;
 use crate::email_client::EmailClient;
 
@@ -17,7 +22,14 @@ pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
     pub email_client: EmailClientSettings,
-    pub redis_uri: SecretString,
+    pub webauthn: WebauthnSettings,
+    pub login_throttle: LoginThrottleSettings,
+    pub rate_limit: RateLimitSettings,
+    pub idempotency: IdempotencySettings,
+    pub maintenance: MaintenanceSettings,
+    pub bot_challenge: Option<BotChallengeSettings>,
+    pub session_store: SessionStoreBackend,
+    pub redis_uri: Option<SecretString>,
 }
 
 impl Settings {
@@ -53,6 +65,25 @@ pub struct ApplicationSettings {
     pub app_port: u16,
     pub base_url: String,
     pub signing_key: SecretString,
+    pub session_idle_timeout_secs: u64,
+    pub session_max_age_secs: u64,
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl ApplicationSettings {
+    /// Get the lifetime policy of admin sessions
+    pub const fn session_timeouts(&self) -> SessionTimeouts {
+        SessionTimeouts {
+            idle: time::Duration::from_secs(self.session_idle_timeout_secs),
+            max_age: time::Duration::from_secs(self.session_max_age_secs),
+        }
+    }
+
+    /// Get the reverse proxies whose forwarded client addresses are trusted
+    pub fn trusted_proxies(&self) -> TrustedProxies {
+        TrustedProxies(self.trusted_proxies.clone())
+    }
 }
 
 /// Database settings
@@ -123,6 +154,111 @@ impl EmailClientSettings {
     }
 }
 
+/// `WebAuthn` relying party settings
+#[derive(Clone, serde::Deserialize)]
+pub struct WebauthnSettings {
+    pub rp_id: String,
+    pub rp_origin: String,
+}
+
+impl WebauthnSettings {
+    /// Build the `WebAuthn` relying party
+    pub fn webauthn(&self) -> anyhow::Result<Webauthn> {
+        let rp_origin = Url::parse(&self.rp_origin)?;
+        Ok(WebauthnBuilder::new(&self.rp_id, &rp_origin)?
+            .rp_name("zero2prod")
+            .build()?)
+    }
+}
+
+/// Login brute-force protection settings
+#[derive(Clone, serde::Deserialize)]
+pub struct LoginThrottleSettings {
+    pub max_username_failures: u64,
+    pub max_ip_failures: u64,
+    pub lockout_secs: u64,
+    pub free_attempts: u64,
+    pub base_delay_millis: u64,
+    pub max_delay_millis: u64,
+}
+
+/// Rate limiting settings for public endpoints
+#[derive(Clone, serde::Deserialize)]
+pub struct RateLimitSettings {
+    pub ip: TokenBucketSettings,
+    pub email: TokenBucketSettings,
+}
+
+/// Token bucket settings
+#[derive(Clone, serde::Deserialize)]
+pub struct TokenBucketSettings {
+    pub capacity: u32,
+    pub refill_per_minute: u32,
+}
+
+/// Idempotency key settings
+#[derive(Clone, serde::Deserialize)]
+pub struct IdempotencySettings {
+    pub retention_secs: u64,
+    pub wait_timeout_millis: u64,
+}
+
+impl IdempotencySettings {
+    /// Get how long saved responses are replayed before their key can be reused for a new request
+    pub const fn retention(&self) -> time::Duration {
+        time::Duration::from_secs(self.retention_secs)
+    }
+
+    /// Get how long a request waits for another request with the same key to complete
+    pub const fn wait_timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.wait_timeout_millis)
+    }
+}
+
+/// Maintenance worker settings
+#[derive(Clone, serde::Deserialize)]
+pub struct MaintenanceSettings {
+    pub interval_secs: u64,
+}
+
+impl MaintenanceSettings {
+    /// Get the interval between two deletions of the expired data
+    pub const fn interval(&self) -> time::Duration {
+        time::Duration::from_secs(self.interval_secs)
+    }
+}
+
+/// Challenge verification server settings, such as hCaptcha or Cloudflare Turnstile
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct BotChallengeSettings {
+    pub verify_url: Url,
+    pub secret_key: SecretString,
+    pub timeout_millis: u64,
+}
+
+impl BotChallengeSettings {
+    /// Build the challenge verifier
+    pub fn verifier(self) -> ChallengeVerifier {
+        ChallengeVerifier::new(
+            self.verify_url,
+            self.secret_key,
+            time::Duration::from_millis(self.timeout_millis),
+        )
+    }
+}
+
+/// Backends where session state can be stored
+#[derive(Clone, Copy, Debug, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreBackend {
+    /// Redis, at `redis_uri`
+    Redis,
+    /// The `sessions` table of the database
+    Postgres,
+    /// The signed session cookie
+    Cookie,
+}
+
 /// Available runtime environments
 pub enum Env {
     Development,
@@ -152,3 +288,27 @@ impl TryFrom<String> for Env {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::*;
+
+    fn bot_challenge_settings(verify_url: &str) -> Result<BotChallengeSettings, ConfigError> {
+        Config::builder()
+            .set_override("verify_url", verify_url)?
+            .set_override("secret_key", "my_secret_key")?
+            .set_override("timeout_millis", 5000)?
+            .build()?
+            .try_deserialize()
+    }
+
+    #[test]
+    fn invalid_verification_urls_are_rejected_when_reading_settings() {
+        assert_ok!(bot_challenge_settings(
+            "https://api.hcaptcha.com/siteverify"
+        ));
+        assert_err!(bot_challenge_settings("api.hcaptcha.com/siteverify"));
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use redis::aio::ConnectionManager;
+use secrecy::{ExposeSecret, SecretString};
+
+/// Connect to Redis, if given, returning `None` if no connection is available
+pub async fn connect_redis(redis_uri: Option<&SecretString>) -> Option<ConnectionManager> {
+    let redis = match redis_uri.map(|uri| redis::Client::open(uri.expose_secret())) {
+        Some(Ok(client)) => client.get_connection_manager().await.ok(),
+        _ => None,
+    };
+    if redis_uri.is_some() && redis.is_none() {
+        tracing::warn!(
+            "Failed to connect to Redis, rate limits and login failures are only counted per instance"
+        );
+    }
+    redis
+}
+
+/// Counters stored in Redis, so that all instances share them, or in memory if Redis is not available
+///
+/// Users read and update the counters in Redis first, and fall back to memory if Redis fails.
+pub struct CounterStore<T> {
+    redis: Option<ConnectionManager>,
+    memory: Mutex<HashMap<String, T>>,
+}
+
+impl<T> CounterStore<T> {
+    /// Build a store using a Redis connection, if any, which is shared with the other stores
+    pub fn new(redis: Option<ConnectionManager>) -> Self {
+        Self {
+            redis,
+            memory: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the Redis connection, if any
+    pub fn redis(&self) -> Option<ConnectionManager> {
+        self.redis.clone()
+    }
+
+    /// Lock the counters stored in memory
+    pub fn memory(&self) -> MutexGuard<'_, HashMap<String, T>> {
+        self.memory.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
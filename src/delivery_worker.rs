@@ -1,17 +1,20 @@
 use std::time;
 
+use anyhow::Context;
 use sqlx::postgres::PgPoolOptions;
-use sqlx::{Executor, PgPool};
+use sqlx::PgPool;
 use tracing::field::display;
 use tracing::Span;
 
 use crate::configuration::Settings;
 use crate::domain::EmailAddress;
 use crate::email_client::EmailClient;
-use crate::routes::NewsletterIssueId;
-use crate::utils::PgTransaction;
+use crate::job_queue::{dequeue_job, Job, QueuedJob};
+use crate::outbox::{
+    get_pending_message, mark_message_sent, record_message_failure, EmailStatus, OutboxDispatch,
+};
 
-/// Delivery worker
+/// Background job worker, dispatching the emails of the outbox
 pub struct DeliveryWorker {
     db_pool: PgPool,
     email_client: EmailClient,
@@ -40,7 +43,7 @@ impl DeliveryWorker {
         })
     }
 
-    /// Run the newsletter issue delivery worker until it is stopped
+    /// Run the worker until it is stopped
     pub async fn run_until_stopped(self) -> anyhow::Result<()> {
         worker_loop(self.db_pool, self.email_client).await
     }
@@ -52,9 +55,15 @@ pub enum ExecutionResult {
     EmptyQueue,
 }
 
-/// Issue delivery worker loop
-/// TODO: Refine the implementation to distinguish between transient and fatal failures (e.g., invalid subscriber email)
-/// TODO: Improve the delay strategy by introducing exponential backoff with jitter
+/// Failure of a job, which decides whether it is worth retrying
+enum JobError {
+    /// The job may succeed later, e.g. after an outage of the email API
+    Transient(anyhow::Error),
+    /// The job can never succeed, e.g. because of an invalid recipient email
+    Fatal(anyhow::Error),
+}
+
+/// Background job worker loop
 async fn worker_loop(db_pool: PgPool, email_client: EmailClient) -> anyhow::Result<()> {
     loop {
         match try_execute_task(&db_pool, &email_client).await {
@@ -69,12 +78,12 @@ async fn worker_loop(db_pool: PgPool, email_client: EmailClient) -> anyhow::Resu
     }
 }
 
-/// Try executing a task in the newsletter issue delivery queue
+/// Try executing the next due job in the queue
 #[tracing::instrument(
     skip_all,
     fields(
-        newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty
+        job_id=tracing::field::Empty,
+        job_kind=tracing::field::Empty
     ),
     err
 )]
@@ -82,136 +91,113 @@ pub async fn try_execute_task(
     db_pool: &PgPool,
     email_client: &EmailClient,
 ) -> anyhow::Result<ExecutionResult> {
-    // Fetch a task from the queue, with an early return if the queue is empty
-    let task = dequeue_task(db_pool).await?;
-    if task.is_none() {
+    // Fetch a job from the queue, with an early return if no job is due
+    let Some(job) = dequeue_job(db_pool).await? else {
         return Ok(ExecutionResult::EmptyQueue);
-    }
-
-    // Process a task in the newsletter issue delivery queue
-    // TODO: Implement better error handling instead of the `unwrap()` call
-    let (transaction, issue_id, email) = task.unwrap();
+    };
     Span::current()
-        .record("newsletter_issue_id", display(issue_id))
-        .record("subscriber_email", display(&email));
-
-    match EmailAddress::parse(email.clone()) {
-        // Valid email address: try to send the newsletter issue
-        // TODO: Implement a retry in case of a transient email delivery error
-        Ok(email) => {
-            let issue = get_issue(db_pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.content_html,
-                    &issue.content_text,
-                )
-                .await
-            {
-                tracing::error!(
+        .record("job_id", display(job.job_id))
+        .record("job_kind", display(&job.kind));
+
+    // Handle the job based on its kind
+    let outcome = match job.kind.as_str() {
+        OutboxDispatch::KIND => dispatch_email(&job, db_pool, email_client).await,
+        kind => Err(JobError::Fatal(anyhow::anyhow!(
+            "Unknown job kind `{kind}`"
+        ))),
+    };
+
+    // Remove the job from the queue, unless it failed and is worth retrying
+    match outcome {
+        Ok(()) => job.complete().await?,
+        Err(JobError::Transient(e)) => {
+            tracing::warn!(
                 error.cause_chain = ?e,
                 error.message = %e,
-                "Failed to deliver issue to confirmed subscriber {}", email
-                );
-            }
+                "Job failed, it will be retried later"
+            );
+            job.retry(&e.to_string()).await?;
         }
-
-        // Invalid email address: skip this particular subscriber
-        Err(e) => {
+        Err(JobError::Fatal(e)) => {
             tracing::error!(
                 error.cause_chain = ?e,
                 error.message = %e,
-                "Skipping a confirmed subscriber because their stored contact details are invalid"
+                "Job failed permanently, skipping it"
             );
+            job.complete().await?;
         }
     }
-
-    // Remove the task from the queue and return success
-    delete_task(transaction, issue_id, &email).await?;
     Ok(ExecutionResult::TaskCompleted)
 }
 
-/// Fetch a task from the newsletter issue delivery queue
-#[tracing::instrument(skip_all)]
-async fn dequeue_task(
-    db_pool: &PgPool,
-) -> anyhow::Result<Option<(PgTransaction, NewsletterIssueId, String)>> {
-    // Query the database to fetch a task
-    let mut transaction = db_pool.begin().await?;
-    let r = sqlx::query!(
-        r#"
-        SELECT newsletter_issue_id, subscriber_email
-        FROM issue_delivery_queue
-        FOR UPDATE
-        SKIP LOCKED
-        LIMIT 1
-        "#
+/// Send an email of the outbox and record the outcome of the attempt
+#[tracing::instrument(
+    skip_all,
+    fields(
+        message_id=tracing::field::Empty,
+        recipient=tracing::field::Empty
     )
-    .fetch_optional(&mut *transaction)
-    .await?;
-
-    // Return the task data
-    if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            NewsletterIssueId::new(r.newsletter_issue_id),
-            r.subscriber_email,
-        )))
-    } else {
-        Ok(None)
-    }
-}
-
-/// Remove a task from the newsletter issue delivery queue
-#[tracing::instrument(skip_all)]
-async fn delete_task(
-    mut transaction: PgTransaction,
-    issue_id: NewsletterIssueId,
-    email: &str,
-) -> anyhow::Result<()> {
-    // Delete a task from the database
-    transaction
-        .execute(sqlx::query!(
-            r#"
-            DELETE FROM issue_delivery_queue
-            WHERE
-                newsletter_issue_id = $1 AND
-                subscriber_email = $2
-            "#,
-            *issue_id,
-            email
-        ))
-        .await?;
-    transaction.commit().await?;
-    Ok(())
-}
-
-/// Newsletter issue
-struct NewsletterIssue {
-    title: String,
-    content_html: String,
-    content_text: String,
-}
-
-/// Fetch the newsletter content
-#[tracing::instrument(skip_all)]
-async fn get_issue(
+)]
+async fn dispatch_email(
+    job: &QueuedJob,
     db_pool: &PgPool,
-    issue_id: NewsletterIssueId,
-) -> anyhow::Result<NewsletterIssue> {
-    // Get the newsletter content associated with the provided `newsletter_issue_id`
-    let issue = sqlx::query_as!(
-        NewsletterIssue,
-        r#"
-        SELECT title, content_html, content_text
-        FROM newsletter_issues
-        WHERE newsletter_issue_id = $1
-        "#,
-        *issue_id
-    )
-    .fetch_one(db_pool)
-    .await?;
-
-    Ok(issue)
+    email_client: &EmailClient,
+) -> Result<(), JobError> {
+    let OutboxDispatch { message_id } = job
+        .payload()
+        .context("Invalid outbox dispatch payload")
+        .map_err(JobError::Fatal)?;
+    Span::current().record("message_id", display(message_id));
+
+    // Skip emails that have already been sent or erased in the meantime
+    let Some(message) = get_pending_message(db_pool, message_id)
+        .await
+        .context("Failed to read the email from the outbox")
+        .map_err(JobError::Transient)?
+    else {
+        return Ok(());
+    };
+    Span::current().record("recipient", display(&message.recipient));
+
+    // Skip recipients whose stored contact details are invalid
+    let recipient = match EmailAddress::parse(message.recipient) {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            record_message_failure(db_pool, message_id, &e, EmailStatus::Failed)
+                .await
+                .context("Failed to record the email failure")
+                .map_err(JobError::Transient)?;
+            return Err(JobError::Fatal(anyhow::anyhow!(e)));
+        }
+    };
+
+    // Send the email, which stays pending in the outbox until the job gives up on it
+    match email_client
+        .send_email(
+            &recipient,
+            &message.subject,
+            &message.html_body,
+            &message.text_body,
+        )
+        .await
+    {
+        Ok(()) => mark_message_sent(db_pool, message_id)
+            .await
+            .context("Failed to record the email as sent")
+            .map_err(JobError::Transient),
+        Err(e) => {
+            let status = if job.is_last_attempt() {
+                EmailStatus::Failed
+            } else {
+                EmailStatus::Pending
+            };
+            record_message_failure(db_pool, message_id, &e.to_string(), status)
+                .await
+                .context("Failed to record the email failure")
+                .map_err(JobError::Transient)?;
+            Err(JobError::Transient(
+                anyhow::Error::new(e).context("Failed to send the email"),
+            ))
+        }
+    }
 }
@@ -0,0 +1,64 @@
+/// Version of the consent text currently shown on the subscription form
+pub const CURRENT_CONSENT_VERSION: &str = "2024-10-01";
+
+/// Maximum length of a consent text version
+const MAX_VERSION_LEN: usize = 32;
+
+/// Version of the consent text shown to a subscriber
+#[derive(Clone, Debug)]
+pub struct ConsentVersion(String);
+
+impl ConsentVersion {
+    /// Parse consent text version, falling back to the current version if none was provided
+    pub fn parse(version: Option<String>) -> Result<Self, String> {
+        let Some(version) = version else {
+            return Ok(Self(CURRENT_CONSENT_VERSION.to_string()));
+        };
+
+        let is_empty = version.trim().is_empty();
+        let is_too_long = version.chars().count() > MAX_VERSION_LEN;
+        let contains_invalid_chars = !version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+
+        if is_empty || is_too_long || contains_invalid_chars {
+            Err(format!("{version} is not a valid consent text version"))
+        } else {
+            Ok(Self(version))
+        }
+    }
+}
+
+impl AsRef<str> for ConsentVersion {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::*;
+
+    #[test]
+    fn missing_version_defaults_to_the_current_one() {
+        let version = ConsentVersion::parse(None).unwrap();
+        assert_eq!(version.as_ref(), CURRENT_CONSENT_VERSION);
+    }
+
+    #[test]
+    fn a_valid_version_is_parsed_successfully() {
+        assert_ok!(ConsentVersion::parse(Some("v1.2".to_string())));
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(ConsentVersion::parse(Some(String::new())));
+    }
+
+    #[test]
+    fn versions_containing_an_invalid_character_are_rejected() {
+        assert_err!(ConsentVersion::parse(Some("<v1>".to_string())));
+    }
+}
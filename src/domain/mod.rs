@@ -1,7 +1,15 @@
+mod consent_version;
+mod new_segment;
 mod new_subscriber;
+mod subscriber_attributes;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tag;
 
+pub use consent_version::{ConsentVersion, CURRENT_CONSENT_VERSION};
+pub use new_segment::NewSegment;
 pub use new_subscriber::NewSubscriber;
+pub use subscriber_attributes::SubscriberAttributes;
 pub use subscriber_email::EmailAddress;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_tag::SubscriberTag;
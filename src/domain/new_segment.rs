@@ -0,0 +1,72 @@
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::domain::{SubscriberAttributes, SubscriberTag};
+
+/// Maximum length of a segment name
+const MAX_NAME_LEN: usize = 64;
+
+/// New audience segment
+pub struct NewSegment {
+    pub name: String,
+    pub tags: Vec<SubscriberTag>,
+    pub attributes: SubscriberAttributes,
+    pub joined_after: Option<DateTime<Utc>>,
+    pub joined_before: Option<DateTime<Utc>>,
+}
+
+impl NewSegment {
+    /// Parse segment name
+    pub fn parse_name(name: &str) -> Result<String, String> {
+        let name = name.trim().to_string();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+            Err(format!("{name} is not a valid segment name"))
+        } else {
+            Ok(name)
+        }
+    }
+
+    /// Parse an optional `YYYY-MM-DD` date into midnight UTC
+    pub fn parse_date(date: &str) -> Result<Option<DateTime<Utc>>, String> {
+        if date.trim().is_empty() {
+            return Ok(None);
+        }
+        NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|d| Some(d.and_utc()))
+            .ok_or_else(|| format!("{date} is not a valid date"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_none, assert_ok};
+
+    use super::*;
+
+    #[test]
+    fn empty_date_is_parsed_as_none() {
+        assert_none!(NewSegment::parse_date("").unwrap());
+    }
+
+    #[test]
+    fn a_valid_date_is_parsed_as_midnight_utc() {
+        let date = NewSegment::parse_date("2024-10-20").unwrap().unwrap();
+        assert_eq!(date.to_rfc3339(), "2024-10-20T00:00:00+00:00");
+    }
+
+    #[test]
+    fn an_invalid_date_is_rejected() {
+        assert_err!(NewSegment::parse_date("20/10/2024"));
+    }
+
+    #[test]
+    fn whitespace_only_names_are_rejected() {
+        assert_err!(NewSegment::parse_name(" "));
+    }
+
+    #[test]
+    fn a_valid_name_is_parsed_successfully() {
+        assert_ok!(NewSegment::parse_name("Beta testers"));
+    }
+}
@@ -1,7 +1,8 @@
-use crate::domain::{EmailAddress, SubscriberName};
+use crate::domain::{ConsentVersion, EmailAddress, SubscriberName};
 
 /// New subscriber
 pub struct NewSubscriber {
     pub email: EmailAddress,
     pub name: SubscriberName,
+    pub consent_version: ConsentVersion,
 }
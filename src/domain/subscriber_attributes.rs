@@ -0,0 +1,79 @@
+use serde_json::{Map, Value};
+
+use crate::domain::SubscriberTag;
+
+/// Maximum length of a subscriber attribute value
+const MAX_VALUE_LEN: usize = 256;
+
+/// Subscriber custom attributes (e.g., `plan=pro, country=it`)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SubscriberAttributes(Map<String, Value>);
+
+impl SubscriberAttributes {
+    /// Parse a comma-separated list of `key=value` pairs, ignoring empty entries
+    pub fn parse(attributes: &str) -> Result<Self, String> {
+        let mut map = Map::new();
+        for pair in attributes.split(',').filter(|p| !p.trim().is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("{pair} is not a valid `key=value` attribute"))?;
+
+            // Attribute keys follow the same rules as tags
+            let key = SubscriberTag::parse(key)?;
+            let value = value.trim();
+            if value.is_empty() || value.chars().count() > MAX_VALUE_LEN {
+                return Err(format!("{value} is not a valid attribute value"));
+            }
+
+            map.insert(key.as_ref().to_string(), Value::String(value.to_string()));
+        }
+
+        Ok(Self(map))
+    }
+
+    /// Represent the attributes as a JSON object for storage
+    pub fn to_json(&self) -> Value {
+        Value::Object(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::*;
+
+    #[test]
+    fn empty_string_is_parsed_as_no_attributes() {
+        let attributes = SubscriberAttributes::parse("").unwrap();
+        assert_eq!(attributes.to_json(), serde_json::json!({}));
+    }
+
+    #[test]
+    fn valid_attributes_are_parsed_successfully() {
+        let attributes = SubscriberAttributes::parse("plan=pro, Country = it").unwrap();
+        assert_eq!(
+            attributes.to_json(),
+            serde_json::json!({"plan": "pro", "country": "it"})
+        );
+    }
+
+    #[test]
+    fn attributes_without_a_value_are_rejected() {
+        assert_err!(SubscriberAttributes::parse("plan"));
+        assert_err!(SubscriberAttributes::parse("plan="));
+    }
+
+    #[test]
+    fn attributes_with_an_invalid_key_are_rejected() {
+        assert_err!(SubscriberAttributes::parse("my plan=pro"));
+    }
+
+    #[test]
+    fn a_value_of_256_chars_is_valid() {
+        assert_ok!(SubscriberAttributes::parse(&format!(
+            "k={}",
+            "a".repeat(256)
+        )));
+    }
+}
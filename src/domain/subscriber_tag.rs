@@ -0,0 +1,84 @@
+/// Maximum length of a subscriber tag
+const MAX_TAG_LEN: usize = 32;
+
+/// Subscriber tag (e.g., `beta`)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    /// Parse subscriber tag
+    pub fn parse(tag: &str) -> Result<Self, String> {
+        let tag = tag.trim().to_lowercase();
+        let is_empty = tag.is_empty();
+        let is_too_long = tag.chars().count() > MAX_TAG_LEN;
+        let contains_invalid_chars = !tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if is_empty || is_too_long || contains_invalid_chars {
+            Err(format!("{tag} is not a valid subscriber tag"))
+        } else {
+            Ok(Self(tag))
+        }
+    }
+
+    /// Parse a comma-separated list of subscriber tags, ignoring empty entries
+    pub fn parse_list(tags: &str) -> Result<Vec<Self>, String> {
+        tags.split(',')
+            .filter(|t| !t.trim().is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+
+    /// Convert a list of subscriber tags into plain strings for storage
+    pub fn to_strings(tags: &[Self]) -> Vec<String> {
+        tags.iter().map(|t| t.0.clone()).collect()
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::*;
+
+    #[test]
+    fn a_valid_tag_is_parsed_successfully() {
+        assert_ok!(SubscriberTag::parse("beta"));
+    }
+
+    #[test]
+    fn tags_are_normalized_to_lowercase() {
+        let tag = SubscriberTag::parse(" Early-Adopters ").unwrap();
+        assert_eq!(tag.as_ref(), "early-adopters");
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(SubscriberTag::parse(""));
+    }
+
+    #[test]
+    fn a_tag_longer_than_32_chars_is_rejected() {
+        assert_err!(SubscriberTag::parse(&"a".repeat(33)));
+    }
+
+    #[test]
+    fn tags_containing_an_invalid_character_are_rejected() {
+        for tag in ["be ta", "beta'", "<beta>", "beta;"] {
+            assert_err!(SubscriberTag::parse(tag));
+        }
+    }
+
+    #[test]
+    fn a_tag_list_skips_empty_entries() {
+        let tags = SubscriberTag::parse_list("beta, ,vip,").unwrap();
+        assert_eq!(SubscriberTag::to_strings(&tags), vec!["beta", "vip"]);
+    }
+}
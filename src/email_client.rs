@@ -91,7 +91,7 @@ mod tests {
     impl wiremock::Match for SendEmailBodyMatcher {
         fn matches(&self, request: &Request) -> bool {
             let result = serde_json::from_slice::<serde_json::Value>(&request.body);
-            result.map_or(false, |v| {
+            result.is_ok_and(|v| {
                 v.get("From").is_some()
                     && v.get("To").is_some()
                     && v.get("Subject").is_some()
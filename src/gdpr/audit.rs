@@ -0,0 +1,91 @@
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::Executor;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::utils::PgTransaction;
+
+/// Kind of data subject request
+#[derive(Copy, Clone, Debug)]
+pub enum RequestKind {
+    Export,
+    Erasure,
+    Pseudonymisation,
+}
+
+impl RequestKind {
+    /// Represent request kind as a string
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Export => "export",
+            Self::Erasure => "erasure",
+            Self::Pseudonymisation => "pseudonymisation",
+        }
+    }
+}
+
+/// Originator of a data subject request
+#[derive(Copy, Clone, Debug)]
+pub enum Requester {
+    Admin(UserId),
+    Cli,
+}
+
+impl Requester {
+    /// Represent the source of the request as a string
+    pub const fn source(&self) -> &'static str {
+        match self {
+            Self::Admin(_) => "admin",
+            Self::Cli => "cli",
+        }
+    }
+
+    /// Get the `user_id` of the admin who made the request, if any
+    pub fn user_id(&self) -> Option<Uuid> {
+        match self {
+            Self::Admin(user_id) => Some(**user_id),
+            Self::Cli => None,
+        }
+    }
+}
+
+/// Record an audit entry for a data subject request
+///
+/// The subject's email address is stored as a SHA-256 hash, so that the audit trail
+/// survives the erasure without holding on to personal data.
+#[tracing::instrument(skip(transaction, email))]
+pub async fn record_request(
+    transaction: &mut PgTransaction,
+    kind: RequestKind,
+    email: &str,
+    requester: Requester,
+    affected_rows: u64,
+) -> anyhow::Result<()> {
+    let subject_hash = format!("{:x}", Sha256::digest(email.to_lowercase().as_bytes()));
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO data_subject_requests (
+                request_id,
+                kind,
+                subject_hash,
+                source,
+                requested_by,
+                affected_rows,
+                requested_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            Uuid::new_v4(),
+            kind.as_str(),
+            subject_hash,
+            requester.source(),
+            requester.user_id(),
+            i64::try_from(affected_rows)?,
+            Utc::now()
+        ))
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use sqlx::{Executor, PgPool};
+
+use crate::gdpr::{record_request, RequestKind, Requester};
+use crate::job_queue::Job;
+use crate::outbox::OutboxDispatch;
+
+/// Erasure mode
+#[derive(Copy, Clone, Debug, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErasureMode {
+    /// Delete all data about the data subject
+    Erase,
+    /// Keep the subscription row for statistics, but replace all personal data
+    Pseudonymise,
+}
+
+/// Erase or pseudonymise all data held about the data subject identified by `email`, and audit
+/// the request. Return the number of affected rows.
+#[tracing::instrument(skip(db_pool, email))]
+pub async fn erase_subject_data(
+    db_pool: &PgPool,
+    email: &str,
+    mode: ErasureMode,
+    requester: Requester,
+) -> anyhow::Result<u64> {
+    let mut transaction = db_pool.begin().await?;
+
+    // Remove the emails sent or queued for delivery, along with their pending dispatches
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            DELETE FROM jobs
+            WHERE
+                kind = $1 AND
+                (payload->>'message_id')::uuid IN (
+                    SELECT message_id
+                    FROM outbox
+                    WHERE lower(recipient) = lower($2)
+                )
+            "#,
+            OutboxDispatch::KIND,
+            email
+        ))
+        .await?;
+    let mut affected_rows = transaction
+        .execute(sqlx::query!(
+            r#"
+            DELETE FROM outbox
+            WHERE lower(recipient) = lower($1)
+            "#,
+            email
+        ))
+        .await?
+        .rows_affected();
+
+    // Remove subscription tokens
+    affected_rows += transaction
+        .execute(sqlx::query!(
+            r#"
+            DELETE FROM subscription_tokens
+            WHERE subscriber_id IN (
+                SELECT id
+                FROM subscriptions
+                WHERE lower(email) = lower($1)
+            )
+            "#,
+            email
+        ))
+        .await?
+        .rows_affected();
+
+    // Remove or pseudonymise subscriber data
+    let kind = match mode {
+        ErasureMode::Erase => {
+            affected_rows += transaction
+                .execute(sqlx::query!(
+                    r#"
+                    DELETE FROM subscriptions
+                    WHERE lower(email) = lower($1)
+                    "#,
+                    email
+                ))
+                .await?
+                .rows_affected();
+            RequestKind::Erasure
+        }
+        ErasureMode::Pseudonymise => {
+            affected_rows += transaction
+                .execute(sqlx::query!(
+                    r#"
+                    UPDATE subscriptions
+                    SET
+                        email = 'erased-' || id || '@erased.invalid',
+                        name = 'erased',
+                        status = 'erased',
+                        tags = '{}',
+                        attributes = '{}',
+                        subscribe_ip = NULL,
+                        subscribe_user_agent = NULL,
+                        confirm_ip = NULL,
+                        confirm_user_agent = NULL
+                    WHERE lower(email) = lower($1)
+                    "#,
+                    email
+                ))
+                .await?
+                .rows_affected();
+            RequestKind::Pseudonymisation
+        }
+    };
+
+    // Record the request in the audit trail
+    record_request(&mut transaction, kind, email, requester, affected_rows).await?;
+    transaction.commit().await?;
+
+    Ok(affected_rows)
+}
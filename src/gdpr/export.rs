@@ -0,0 +1,143 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::gdpr::{record_request, RequestKind, Requester};
+
+/// Subscription data held about a data subject
+#[derive(Debug, serde::Serialize)]
+pub struct SubscriptionRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub tags: Vec<String>,
+    pub attributes: serde_json::Value,
+    pub consent_version: Option<String>,
+    pub subscribe_ip: Option<String>,
+    pub subscribe_user_agent: Option<String>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub confirm_ip: Option<String>,
+    pub confirm_user_agent: Option<String>,
+}
+
+/// Email sent or queued for delivery to a data subject, as recorded in the outbox
+#[derive(Debug, serde::Serialize)]
+pub struct EmailRecord {
+    pub message_id: Uuid,
+    pub kind: String,
+    pub subject: String,
+    pub newsletter_issue_id: Option<Uuid>,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+/// All data held about a data subject
+#[derive(Debug, serde::Serialize)]
+pub struct SubjectData {
+    pub email: String,
+    pub exported_at: DateTime<Utc>,
+    pub subscriptions: Vec<SubscriptionRecord>,
+    pub subscription_tokens: Vec<String>,
+    pub emails: Vec<EmailRecord>,
+}
+
+impl SubjectData {
+    /// Check if no data is held about the data subject
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+            && self.subscription_tokens.is_empty()
+            && self.emails.is_empty()
+    }
+}
+
+/// Export all data held about the data subject identified by `email`, and audit the request
+#[tracing::instrument(skip(db_pool, email))]
+pub async fn export_subject_data(
+    db_pool: &PgPool,
+    email: &str,
+    requester: Requester,
+) -> anyhow::Result<SubjectData> {
+    let mut transaction = db_pool.begin().await?;
+
+    // Collect subscriber data
+    let subscriptions = sqlx::query_as!(
+        SubscriptionRecord,
+        r#"
+        SELECT
+            id,
+            email,
+            name,
+            status,
+            subscribed_at,
+            tags,
+            attributes,
+            consent_version,
+            subscribe_ip,
+            subscribe_user_agent,
+            confirmed_at,
+            confirm_ip,
+            confirm_user_agent
+        FROM subscriptions
+        WHERE lower(email) = lower($1)
+        "#,
+        email
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+
+    // Collect subscription tokens
+    let subscription_tokens = sqlx::query!(
+        r#"
+        SELECT t.subscription_token
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE lower(s.email) = lower($1)
+        "#,
+        email
+    )
+    .fetch_all(&mut *transaction)
+    .await?
+    .into_iter()
+    .map(|r| r.subscription_token)
+    .collect();
+
+    // Collect the emails sent or queued for delivery
+    let emails = sqlx::query_as!(
+        EmailRecord,
+        r#"
+        SELECT message_id, kind, subject, newsletter_issue_id, status, created_at, sent_at
+        FROM outbox
+        WHERE lower(recipient) = lower($1)
+        ORDER BY created_at
+        "#,
+        email
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+
+    let data = SubjectData {
+        email: email.to_string(),
+        exported_at: Utc::now(),
+        subscriptions,
+        subscription_tokens,
+        emails,
+    };
+
+    // Record the request in the audit trail
+    let affected_rows =
+        data.subscriptions.len() + data.subscription_tokens.len() + data.emails.len();
+    record_request(
+        &mut transaction,
+        RequestKind::Export,
+        email,
+        requester,
+        u64::try_from(affected_rows)?,
+    )
+    .await?;
+    transaction.commit().await?;
+
+    Ok(data)
+}
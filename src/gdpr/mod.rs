@@ -0,0 +1,7 @@
+mod audit;
+mod erasure;
+mod export;
+
+pub use audit::{record_request, RequestKind, Requester};
+pub use erasure::{erase_subject_data, ErasureMode};
+pub use export::{export_subject_data, EmailRecord, SubjectData, SubscriptionRecord};
@@ -0,0 +1,50 @@
+use std::time;
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+/// Get the creation time before which idempotency keys are expired
+pub fn expired_before(retention: time::Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(retention)
+        .ok()
+        .and_then(|retention| Utc::now().checked_sub_signed(retention))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+/// Delete the idempotency keys older than the retention period and return how many were deleted
+#[tracing::instrument(skip(db_pool))]
+pub async fn delete_expired_keys(db_pool: &PgPool, retention: time::Duration) -> sqlx::Result<u64> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE created_at < $1
+        "#,
+        expired_before(retention)
+    )
+    .execute(db_pool)
+    .await?
+    .rows_affected();
+
+    tracing::info!("Deleted {n_deleted_rows} expired idempotency keys");
+    Ok(n_deleted_rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_expire_after_the_retention_period() {
+        let cutoff = expired_before(time::Duration::from_secs(24 * 60 * 60));
+        let expected = Utc::now() - chrono::Duration::hours(24);
+        assert!((expected - cutoff).num_seconds().abs() <= 1);
+    }
+
+    #[test]
+    fn huge_retention_periods_do_not_overflow() {
+        assert_eq!(
+            expired_before(time::Duration::MAX),
+            DateTime::<Utc>::MIN_UTC
+        );
+    }
+}
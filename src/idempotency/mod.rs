@@ -1,5 +1,13 @@
+mod cleanup;
 mod key;
+mod middleware;
 mod persistence;
 
+pub use cleanup::delete_expired_keys;
 pub use key::IdempotencyKey;
-pub use persistence::{get_saved_response, save_response, try_processing, NextAction};
+pub use middleware::{
+    idempotency, IdempotencyError, IdempotentTransaction, ReplayedResponse, IDEMPOTENCY_KEY_HEADER,
+};
+pub use persistence::{
+    get_saved_response, save_response, try_processing, NextAction, RETRY_AFTER_SECS,
+};
@@ -4,9 +4,14 @@ use actix_web::HttpResponse;
 use sqlx::{Executor, PgPool};
 
 use crate::authentication::UserId;
+use crate::configuration::IdempotencySettings;
+use crate::idempotency::cleanup::expired_before;
 use crate::idempotency::IdempotencyKey;
 use crate::utils::PgTransaction;
 
+/// Seconds after which a request should be retried while another request with the same key is being processed
+pub const RETRY_AFTER_SECS: u64 = 1;
+
 /// Header pair record
 #[derive(Debug, sqlx::Type)]
 #[sqlx(type_name = "header_pair")]
@@ -16,6 +21,8 @@ struct HeaderPairRecord {
 }
 
 /// Get saved response from the database
+///
+/// Returns `None` if there is no request with this key, or if it is still being processed.
 pub async fn get_saved_response(
     db_pool: &PgPool,
     idempotency_key: &IdempotencyKey,
@@ -25,9 +32,9 @@ pub async fn get_saved_response(
     let saved_response = sqlx::query!(
         r#"
         SELECT
-            response_status_code AS "response_status_code!",
-            response_headers AS "response_headers!: Vec<HeaderPairRecord>",
-            response_body AS "response_body!"
+            response_status_code,
+            response_headers AS "response_headers: Vec<HeaderPairRecord>",
+            response_body
         FROM idempotency
         WHERE
             user_id = $1 AND
@@ -40,16 +47,21 @@ pub async fn get_saved_response(
     .await?;
 
     // Map the retrieved data (if any) into a proper `HttpResponse`
-    if let Some(r) = saved_response {
-        let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
-        let mut response = HttpResponse::build(status_code);
-        for HeaderPairRecord { name, value } in r.response_headers {
-            response.append_header((name, value));
-        }
-        Ok(Some(response.body(r.response_body)))
-    } else {
-        Ok(None)
+    let Some((status_code, headers, body)) = saved_response.and_then(|r| {
+        Some((
+            r.response_status_code?,
+            r.response_headers?,
+            r.response_body?,
+        ))
+    }) else {
+        return Ok(None);
+    };
+    let status_code = StatusCode::from_u16(status_code.try_into()?)?;
+    let mut response = HttpResponse::build(status_code);
+    for HeaderPairRecord { name, value } in headers {
+        response.append_header((name, value));
     }
+    Ok(Some(response.body(body)))
 }
 
 /// Next action
@@ -57,43 +69,120 @@ pub async fn get_saved_response(
 pub enum NextAction {
     StartProcessing(PgTransaction),
     ReturnSavedResponse(HttpResponse),
+    /// Another request with the same key is still being processed
+    RequestInProgress,
+    /// The key was already used for a different request
+    PayloadMismatch,
 }
 
 /// Try processing the request
+///
+/// Expired keys are handled as fresh requests, even if they have not been cleaned up yet.
+/// A request with the same key as an in-flight one waits for it to complete, so that it can return its response,
+/// until the wait timeout elapses.
+/// Keys are bound to the fingerprint of the request they were first used for.
 pub async fn try_processing(
     db_pool: &PgPool,
     idempotency_key: &IdempotencyKey,
     user_id: UserId,
+    request_fingerprint: &[u8],
+    settings: &IdempotencySettings,
 ) -> anyhow::Result<NextAction> {
-    // Save the initial request fields to the database
+    // The row of an in-flight request stays locked until its transaction completes, so bound the wait for it
+    // (a zero lock timeout would disable the limit)
     let mut transaction = db_pool.begin().await?;
-    let n_inserted_rows = transaction
+    let lock_timeout = format!("{}ms", settings.wait_timeout().as_millis().max(1));
+    transaction
+        .execute(sqlx::query!(
+            "SELECT set_config('lock_timeout', $1, true)",
+            lock_timeout
+        ))
+        .await?;
+
+    // Save the initial request fields to the database, replacing an expired request with the same key
+    let inserted = transaction
         .execute(sqlx::query!(
             r#"
             INSERT INTO idempotency (
                 user_id,
                 idempotency_key,
+                request_fingerprint,
                 created_at
             )
-            VALUES ($1, $2, now())
-            ON CONFLICT DO NOTHING
+            VALUES ($1, $2, $3, now())
+            ON CONFLICT (user_id, idempotency_key) DO UPDATE
+            SET
+                request_fingerprint = EXCLUDED.request_fingerprint,
+                created_at = EXCLUDED.created_at,
+                response_status_code = NULL,
+                response_headers = NULL,
+                response_body = NULL
+            WHERE idempotency.created_at < $4
             "#,
             *user_id,
-            idempotency_key.as_ref()
+            idempotency_key.as_ref(),
+            request_fingerprint,
+            expired_before(settings.retention())
         ))
-        .await?
-        .rows_affected();
+        .await;
+    let n_inserted_rows = match inserted {
+        Ok(r) => r.rows_affected(),
+        Err(e) if is_lock_timeout(&e) => return Ok(NextAction::RequestInProgress),
+        Err(e) => return Err(e.into()),
+    };
 
-    // If the insert was successful, start processing the request
+    // If the insert was successful, start processing the request, without bounding the waits of its own queries
     if n_inserted_rows > 0 {
-        Ok(NextAction::StartProcessing(transaction))
-    } else {
-        // Otherwise, return the saved response
-        let response = get_saved_response(db_pool, idempotency_key, user_id)
-            .await?
-            .ok_or_else(|| anyhow::anyhow!("Saved response not found"))?;
-        Ok(NextAction::ReturnSavedResponse(response))
+        transaction
+            .execute(sqlx::query!("SET LOCAL lock_timeout = DEFAULT"))
+            .await?;
+        return Ok(NextAction::StartProcessing(transaction));
+    }
+
+    // Otherwise, check that the key is not reused for a different request (keys saved without fingerprint match any)
+    let saved_fingerprint = get_request_fingerprint(db_pool, idempotency_key, user_id).await?;
+    if saved_fingerprint.is_some_and(|f| f != request_fingerprint) {
+        return Ok(NextAction::PayloadMismatch);
     }
+
+    // Return the saved response if the other request has completed
+    Ok(get_saved_response(db_pool, idempotency_key, user_id)
+        .await?
+        .map_or(
+            NextAction::RequestInProgress,
+            NextAction::ReturnSavedResponse,
+        ))
+}
+
+/// Get the fingerprint of the request a key was first used for
+async fn get_request_fingerprint(
+    db_pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: UserId,
+) -> sqlx::Result<Option<Vec<u8>>> {
+    let saved_request = sqlx::query!(
+        r#"
+        SELECT request_fingerprint
+        FROM idempotency
+        WHERE
+            user_id = $1 AND
+            idempotency_key = $2
+        "#,
+        *user_id,
+        idempotency_key.as_ref()
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    Ok(saved_request.and_then(|r| r.request_fingerprint))
+}
+
+/// Check if a query failed because it waited longer than the lock timeout
+fn is_lock_timeout(e: &sqlx::Error) -> bool {
+    // Error code `lock_not_available`
+    e.as_database_error()
+        .and_then(sqlx::error::DatabaseError::code)
+        .is_some_and(|code| code == "55P03")
 }
 
 /// Save response to the database
@@ -0,0 +1,218 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::{thread_rng, Rng};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::utils::PgTransaction;
+
+/// Number of attempts after which a failing job is discarded
+const MAX_ATTEMPTS: i32 = 8;
+
+/// Delay before the first retry of a failed job, doubled on each further attempt
+const BASE_RETRY_DELAY_SECS: i64 = 10;
+
+/// Maximum delay between two attempts of a job
+const MAX_RETRY_DELAY_SECS: i64 = 3600;
+
+/// Background job, stored in the queue as a JSON payload along with its kind
+pub trait Job: Serialize + DeserializeOwned + Sync {
+    /// Kind of the job, used by workers to decide how to handle its payload
+    const KIND: &'static str;
+}
+
+/// Add a job to the queue, to be run as soon as a worker is available
+#[tracing::instrument(skip_all, fields(job_kind = J::KIND))]
+pub async fn enqueue_job<J: Job>(
+    transaction: &mut Transaction<'_, Postgres>,
+    job: &J,
+) -> anyhow::Result<Uuid> {
+    enqueue_job_at(transaction, job, Utc::now()).await
+}
+
+/// Add a job to the queue, to be run no earlier than `run_at`
+#[tracing::instrument(skip_all, fields(job_kind = J::KIND))]
+pub async fn enqueue_job_at<J: Job>(
+    transaction: &mut Transaction<'_, Postgres>,
+    job: &J,
+    run_at: DateTime<Utc>,
+) -> anyhow::Result<Uuid> {
+    let job_id = Uuid::new_v4();
+    let payload = serde_json::to_value(job).context("Failed to serialize the job payload")?;
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO jobs (job_id, kind, payload, run_at, attempts, created_at)
+            VALUES ($1, $2, $3, $4, 0, now())
+            "#,
+            job_id,
+            J::KIND,
+            payload,
+            run_at
+        ))
+        .await
+        .context("Failed to enqueue the job")?;
+    Ok(job_id)
+}
+
+/// Add a batch of jobs of the same kind to the queue, to be run as soon as a worker is available
+#[tracing::instrument(skip_all, fields(job_kind = J::KIND, n_jobs = jobs.len()))]
+pub async fn enqueue_jobs<J: Job>(
+    transaction: &mut Transaction<'_, Postgres>,
+    jobs: &[J],
+) -> anyhow::Result<()> {
+    let payloads = jobs
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to serialize the job payloads")?;
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO jobs (job_id, kind, payload, run_at, attempts, created_at)
+            SELECT gen_random_uuid(), $1, payload, now(), 0, now()
+            FROM unnest($2::jsonb[]) AS payload
+            "#,
+            J::KIND,
+            &payloads
+        ))
+        .await
+        .context("Failed to enqueue the jobs")?;
+    Ok(())
+}
+
+/// Job fetched from the queue, which stays locked until it is completed or rescheduled
+pub struct QueuedJob {
+    pub job_id: Uuid,
+    pub kind: String,
+    pub attempts: i32,
+    payload: serde_json::Value,
+    transaction: PgTransaction,
+}
+
+/// Fetch the next due job from the queue, skipping the ones locked by other workers
+#[tracing::instrument(skip_all)]
+pub async fn dequeue_job(db_pool: &PgPool) -> anyhow::Result<Option<QueuedJob>> {
+    let mut transaction = db_pool.begin().await?;
+    let r = sqlx::query!(
+        r#"
+        SELECT job_id, kind, payload, attempts
+        FROM jobs
+        WHERE run_at <= now()
+        ORDER BY run_at
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    Ok(r.map(|r| QueuedJob {
+        job_id: r.job_id,
+        kind: r.kind,
+        attempts: r.attempts,
+        payload: r.payload,
+        transaction,
+    }))
+}
+
+impl QueuedJob {
+    /// Deserialize the payload of the job
+    pub fn payload<J: Job>(&self) -> serde_json::Result<J> {
+        serde_json::from_value(self.payload.clone())
+    }
+
+    /// Check if a failure of the job would get it discarded instead of retried
+    pub const fn is_last_attempt(&self) -> bool {
+        self.attempts + 1 >= MAX_ATTEMPTS
+    }
+
+    /// Remove the job from the queue once it has been handled
+    #[tracing::instrument(skip_all, fields(job_id = %self.job_id))]
+    pub async fn complete(mut self) -> anyhow::Result<()> {
+        self.transaction
+            .execute(sqlx::query!(
+                r#"
+                DELETE FROM jobs
+                WHERE job_id = $1
+                "#,
+                self.job_id
+            ))
+            .await?;
+        self.transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Reschedule the job after a transient failure, or discard it if it failed too many times
+    #[tracing::instrument(skip_all, fields(job_id = %self.job_id))]
+    pub async fn retry(mut self, error: &str) -> anyhow::Result<()> {
+        let attempts = self.attempts + 1;
+        if self.is_last_attempt() {
+            tracing::error!(
+                job_kind = %self.kind,
+                error.message = %error,
+                "Discarding a job that failed {attempts} times"
+            );
+            return self.complete().await;
+        }
+
+        let run_at = Utc::now() + retry_delay(attempts);
+        self.transaction
+            .execute(sqlx::query!(
+                r#"
+                UPDATE jobs
+                SET
+                    attempts = $2,
+                    run_at = $3,
+                    last_error = $4
+                WHERE job_id = $1
+                "#,
+                self.job_id,
+                attempts,
+                run_at,
+                error
+            ))
+            .await?;
+        self.transaction.commit().await?;
+        Ok(())
+    }
+}
+
+/// Compute the delay before the next attempt of a job, with exponential backoff and jitter
+///
+/// Jitter spreads out the retries of jobs that failed together, e.g. during an outage of the email API.
+fn retry_delay(attempts: i32) -> chrono::Duration {
+    let backoff_secs = u32::try_from(attempts - 1)
+        .ok()
+        .and_then(|shift| 1_i64.checked_shl(shift))
+        .and_then(|factor| BASE_RETRY_DELAY_SECS.checked_mul(factor))
+        .map_or(MAX_RETRY_DELAY_SECS, |d| d.min(MAX_RETRY_DELAY_SECS));
+    let jitter_secs = thread_rng().gen_range(0..=backoff_secs / 4);
+    chrono::Duration::seconds(backoff_secs + jitter_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delays_double_up_to_the_maximum() {
+        for (attempts, backoff_secs) in [(1, 10), (2, 20), (3, 40), (9, 2560), (10, 3600)] {
+            let delay = retry_delay(attempts).num_seconds();
+            assert!(delay >= backoff_secs, "{attempts}: {delay}");
+            assert!(
+                delay <= backoff_secs + backoff_secs / 4,
+                "{attempts}: {delay}"
+            );
+        }
+    }
+
+    #[test]
+    fn huge_attempt_counts_do_not_overflow_the_delay() {
+        let delay = retry_delay(i32::MAX).num_seconds();
+        assert!((3600..=4500).contains(&delay));
+    }
+}
@@ -1,11 +1,22 @@
 pub mod authentication;
+pub mod bot_protection;
+pub mod client_info;
 pub mod configuration;
+pub mod counter_store;
 pub mod delivery_worker;
 pub mod domain;
 pub mod email_client;
+pub mod gdpr;
 pub mod idempotency;
+pub mod job_queue;
+pub mod maintenance_worker;
+pub mod openapi;
+pub mod outbox;
+pub mod problem_details;
+pub mod rate_limit;
 pub mod routes;
 pub mod session_state;
+pub mod session_store;
 pub mod startup;
 pub mod telemetry;
 pub mod utils;
@@ -1,16 +1,28 @@
 use std::fmt::{Debug, Display};
-use std::io;
+use std::{env, io, process, time};
 
+use sqlx::postgres::PgPoolOptions;
 use tokio::task::JoinError;
 
 use zero2prod::configuration::Settings;
 use zero2prod::delivery_worker::DeliveryWorker;
+use zero2prod::gdpr::{erase_subject_data, export_subject_data, ErasureMode, Requester};
+use zero2prod::maintenance_worker::MaintenanceWorker;
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 #[tokio::main]
 #[allow(clippy::redundant_pub_crate)]
 async fn main() -> anyhow::Result<()> {
+    // Handle data subject requests from the command line, logging to stderr to keep stdout clean
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "gdpr") {
+        let subscriber = get_subscriber("zero2prod".into(), "info".into(), io::stderr);
+        init_subscriber(subscriber);
+        let config = Settings::get_config().expect("Failed to load configuration");
+        return gdpr(&args, config).await;
+    }
+
     // Initialize logging
     let subscriber = get_subscriber("zero2prod".into(), "info".into(), io::stdout);
     init_subscriber(subscriber);
@@ -18,18 +30,20 @@ async fn main() -> anyhow::Result<()> {
     // Retrieve settings
     let config = Settings::get_config().expect("Failed to load configuration");
 
-    // Prepare the application and the delivery worker tasks
+    // Prepare the application, the delivery worker and the maintenance worker tasks
     let task_app = tokio::spawn(
         Application::build(config.clone())
             .await?
             .run_until_stopped(),
     );
-    let task_wrk = tokio::spawn(DeliveryWorker::build(config)?.run_until_stopped());
+    let task_wrk = tokio::spawn(DeliveryWorker::build(config.clone())?.run_until_stopped());
+    let task_mnt = tokio::spawn(MaintenanceWorker::build(config).run_until_stopped());
 
-    // Run both tasks concurrently
+    // Run all tasks concurrently
     tokio::select! {
         o = task_app => report_exit("Application", o),
         o = task_wrk => report_exit("Delivery Worker", o),
+        o = task_mnt => report_exit("Maintenance Worker", o),
     }
     Ok(())
 }
@@ -61,3 +75,49 @@ fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>
         }
     }
 }
+
+/// Handle a GDPR data subject request from the command line
+async fn gdpr(args: &[String], config: Settings) -> anyhow::Result<()> {
+    // Connect to the database
+    let db_pool = PgPoolOptions::new()
+        .acquire_timeout(time::Duration::from_secs(2))
+        .connect_lazy_with(config.database.db_options());
+
+    // Decide the course of action based on the provided subcommand
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args[2..] {
+        // Export all data held about the subject as JSON
+        ["export", email] => {
+            let data = export_subject_data(&db_pool, email, Requester::Cli).await?;
+            println!("{}", serde_json::to_string_pretty(&data)?);
+        }
+
+        // Erase all data held about the subject
+        ["erase", email] => {
+            let n = erase_subject_data(&db_pool, email, ErasureMode::Erase, Requester::Cli).await?;
+            println!("Erased {n} records held about {email}");
+        }
+
+        // Pseudonymise all personal data held about the subject
+        ["pseudonymise", email] => {
+            let n = erase_subject_data(&db_pool, email, ErasureMode::Pseudonymise, Requester::Cli)
+                .await?;
+            println!("Pseudonymised {n} records held about {email}");
+        }
+
+        // Print usage and exit
+        _ => gdpr_usage(args[0]),
+    }
+
+    Ok(())
+}
+
+/// Print GDPR subcommand usage information and exit
+fn gdpr_usage(prog: &str) {
+    println!("Usage:");
+    println!("{prog} gdpr export <email>");
+    println!("{prog} gdpr erase <email>");
+    println!("{prog} gdpr pseudonymise <email>");
+
+    process::exit(1);
+}
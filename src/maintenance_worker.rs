@@ -0,0 +1,70 @@
+use std::time;
+
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+
+use crate::authentication::{delete_expired_user_sessions, SessionTimeouts};
+use crate::configuration::{MaintenanceSettings, Settings};
+use crate::idempotency::delete_expired_keys;
+use crate::session_store::delete_expired_session_states;
+
+/// Background worker, periodically deleting the expired idempotency keys, user sessions and session states
+pub struct MaintenanceWorker {
+    db_pool: PgPool,
+    settings: MaintenanceSettings,
+    idempotency_retention: time::Duration,
+    session_timeouts: SessionTimeouts,
+}
+
+impl MaintenanceWorker {
+    /// Build a worker based on settings
+    pub fn build(config: Settings) -> Self {
+        // Connect to the database
+        let db_pool = PgPoolOptions::new()
+            .acquire_timeout(time::Duration::from_secs(2))
+            .connect_lazy_with(config.database.db_options());
+
+        // Build the worker
+        Self::build_with_db_pool(config, &db_pool)
+    }
+
+    /// Build a worker based on settings and database pool
+    pub fn build_with_db_pool(config: Settings, db_pool: &PgPool) -> Self {
+        Self {
+            db_pool: db_pool.clone(),
+            settings: config.maintenance,
+            idempotency_retention: config.idempotency.retention(),
+            session_timeouts: config.application.session_timeouts(),
+        }
+    }
+
+    /// Run the worker until it is stopped
+    pub async fn run_until_stopped(self) -> anyhow::Result<()> {
+        loop {
+            // Errors are only logged, the expired data will be deleted at the next run
+            if let Err(e) = delete_expired_keys(&self.db_pool, self.idempotency_retention).await {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to delete the expired idempotency keys"
+                );
+            }
+            if let Err(e) = delete_expired_user_sessions(self.session_timeouts, &self.db_pool).await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to delete the expired user sessions"
+                );
+            }
+            if let Err(e) = delete_expired_session_states(&self.db_pool).await {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to delete the expired session states"
+                );
+            }
+            tokio::time::sleep(self.settings.interval()).await;
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use actix_web::http::header::ContentType;
+use actix_web::HttpResponse;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_redoc::Redoc;
+
+use crate::routes;
+
+/// `OpenAPI` specification of the JSON endpoints, generated from the route handlers
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "zero2prod",
+        description = "Newsletter delivery service from \"Zero To Production In Rust\"."
+    ),
+    paths(
+        routes::healthcheck,
+        routes::subscriptions,
+        routes::confirm,
+        routes::publish_newsletter,
+    ),
+    modifiers(&ApiKeySecurity),
+    tags(
+        (name = "health", description = "Service health"),
+        (name = "subscriptions", description = "Newsletter subscriptions"),
+        (name = "newsletters", description = "Newsletter publication, authenticated with API keys"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Register the API key bearer authentication scheme
+struct ApiKeySecurity;
+
+impl Modify for ApiKeySecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+    }
+}
+
+/// `OpenAPI` specification handler
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+/// `OpenAPI` documentation page handler, rendered with Redoc
+pub async fn openapi_docs() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(Redoc::new(ApiDoc::openapi()).to_html())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_operation_documents_its_responses() {
+        let spec = ApiDoc::openapi();
+        for (path, item) in &spec.paths.paths {
+            for operation in [&item.get, &item.post].into_iter().flatten() {
+                assert!(
+                    !operation.responses.responses.is_empty(),
+                    "{path} does not document any response"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn the_api_key_security_scheme_is_registered() {
+        let spec = ApiDoc::openapi();
+        assert!(spec
+            .components
+            .is_some_and(|c| c.security_schemes.contains_key("api_key")));
+    }
+}
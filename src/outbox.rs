@@ -0,0 +1,206 @@
+use anyhow::Context;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::EmailAddress;
+use crate::job_queue::{enqueue_job, Job};
+
+/// Placeholder replacing single-use tokens in the content of the emails logged in the outbox
+const REDACTED_TOKEN: &str = "[redacted]";
+
+/// Kind of email written to the outbox
+#[derive(Copy, Clone, Debug)]
+pub enum EmailKind {
+    Confirmation,
+    Newsletter,
+    PasswordReset,
+    Invitation,
+}
+
+impl EmailKind {
+    /// Represent the kind of email as a string
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Confirmation => "confirmation",
+            Self::Newsletter => "newsletter",
+            Self::PasswordReset => "password_reset",
+            Self::Invitation => "invitation",
+        }
+    }
+}
+
+/// Delivery status of an email in the outbox
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EmailStatus {
+    /// The email has not been sent yet, or it will be retried
+    Pending,
+    /// The email has been accepted by the email API
+    Sent,
+    /// The email could not be sent and will not be retried
+    Failed,
+}
+
+impl EmailStatus {
+    /// Represent the status as a string
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Sent => "sent",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// Rendered email to be written to the outbox
+pub struct OutboxEmail<'a> {
+    pub kind: EmailKind,
+    pub recipient: &'a EmailAddress,
+    pub subject: &'a str,
+    pub html_body: &'a str,
+    pub text_body: &'a str,
+    /// Token of a single-use link in the content, which is only kept in the log until the email is sent or fails
+    pub single_use_token: Option<&'a str>,
+}
+
+/// Job dispatching an email from the outbox
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct OutboxDispatch {
+    pub message_id: Uuid,
+}
+
+impl Job for OutboxDispatch {
+    const KIND: &'static str = "outbox_dispatch";
+}
+
+/// Email read from the outbox to be dispatched
+pub struct OutboxMessage {
+    pub recipient: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+/// Write an email to the outbox and enqueue its dispatch, so that it is only sent if the transaction commits
+#[tracing::instrument(skip_all, fields(email_kind = email.kind.as_str()))]
+pub async fn enqueue_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &OutboxEmail<'_>,
+) -> anyhow::Result<Uuid> {
+    let message_id = Uuid::new_v4();
+    transaction
+        .execute(sqlx::query!(
+            r#"
+            INSERT INTO outbox (
+                message_id,
+                kind,
+                recipient,
+                subject,
+                html_body,
+                text_body,
+                single_use_token,
+                status,
+                attempts,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 0, now())
+            "#,
+            message_id,
+            email.kind.as_str(),
+            email.recipient.as_ref(),
+            email.subject,
+            email.html_body,
+            email.text_body,
+            email.single_use_token,
+            EmailStatus::Pending.as_str()
+        ))
+        .await
+        .context("Failed to write the email to the outbox")?;
+    enqueue_job(transaction, &OutboxDispatch { message_id }).await?;
+
+    Ok(message_id)
+}
+
+/// Retrieve an email of the outbox that still has to be sent
+#[tracing::instrument(skip(db_pool))]
+pub async fn get_pending_message(
+    db_pool: &PgPool,
+    message_id: Uuid,
+) -> sqlx::Result<Option<OutboxMessage>> {
+    sqlx::query_as!(
+        OutboxMessage,
+        r#"
+        SELECT recipient, subject, html_body, text_body
+        FROM outbox
+        WHERE
+            message_id = $1 AND
+            status = $2
+        "#,
+        message_id,
+        EmailStatus::Pending.as_str()
+    )
+    .fetch_optional(db_pool)
+    .await
+}
+
+/// Record that an email of the outbox has been sent, redacting its single-use token from the content
+#[tracing::instrument(skip(db_pool))]
+pub async fn mark_message_sent(db_pool: &PgPool, message_id: Uuid) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE outbox
+        SET
+            status = $2,
+            attempts = attempts + 1,
+            sent_at = now(),
+            html_body = coalesce(replace(html_body, single_use_token, $3), html_body),
+            text_body = coalesce(replace(text_body, single_use_token, $3), text_body),
+            single_use_token = NULL
+        WHERE message_id = $1
+        "#,
+        message_id,
+        EmailStatus::Sent.as_str(),
+        REDACTED_TOKEN
+    )
+    .execute(db_pool)
+    .await?;
+    Ok(())
+}
+
+/// Record a failed attempt to send an email of the outbox, along with its new status
+///
+/// The single-use token is redacted from the content once the email will not be retried anymore.
+#[tracing::instrument(skip(db_pool))]
+pub async fn record_message_failure(
+    db_pool: &PgPool,
+    message_id: Uuid,
+    error: &str,
+    status: EmailStatus,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE outbox
+        SET
+            status = $2,
+            attempts = attempts + 1,
+            last_error = $3,
+            html_body = CASE
+                WHEN $2 = $4 THEN html_body
+                ELSE coalesce(replace(html_body, single_use_token, $5), html_body)
+            END,
+            text_body = CASE
+                WHEN $2 = $4 THEN text_body
+                ELSE coalesce(replace(text_body, single_use_token, $5), text_body)
+            END,
+            single_use_token = CASE WHEN $2 = $4 THEN single_use_token END
+        WHERE message_id = $1
+        "#,
+        message_id,
+        status.as_str(),
+        error,
+        EmailStatus::Pending.as_str(),
+        REDACTED_TOKEN
+    )
+    .execute(db_pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+
+use actix_web::http::header::{ACCEPT, CONTENT_TYPE};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+
+/// Media type of problem details responses
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Problem details response body
+/// <https://www.rfc-editor.org/rfc/rfc9457>
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    #[schema(example = "about:blank")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+}
+
+impl ProblemDetails {
+    /// Build problem details for a status code and a human-readable explanation
+    pub fn new(status: StatusCode, detail: impl Into<String>) -> Self {
+        Self {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: detail.into(),
+        }
+    }
+
+    /// Convert problem details into an HTTP response
+    pub fn into_response(self) -> HttpResponse {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        HttpResponse::build(status)
+            .content_type(PROBLEM_JSON)
+            .json(self)
+    }
+}
+
+/// Error that can be described to API clients without leaking internal details
+pub trait Problem: ResponseError {
+    /// Return a human-readable explanation specific to this occurrence of the problem
+    fn detail(&self) -> String;
+}
+
+/// Check if the client sent or accepts JSON, in which case responses should be JSON as well
+pub fn wants_json(req: &HttpRequest) -> bool {
+    let header_contains_json = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .is_some_and(|h| h.contains("application/json") || h.contains(PROBLEM_JSON))
+    };
+    header_contains_json(CONTENT_TYPE) || header_contains_json(ACCEPT)
+}
+
+/// Error wrapper that renders problem details for JSON clients and an empty body otherwise
+pub struct Negotiated<E> {
+    error: E,
+    json: bool,
+}
+
+impl<E: Problem> Negotiated<E> {
+    /// Wrap an error, rendering it as JSON if requested by the client
+    pub const fn new(error: E, json: bool) -> Self {
+        Self { error, json }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Debug for Negotiated<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for Negotiated<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<E: Problem> ResponseError for Negotiated<E> {
+    fn status_code(&self) -> StatusCode {
+        self.error.status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.json {
+            ProblemDetails::new(self.status_code(), self.error.detail()).into_response()
+        } else {
+            HttpResponse::new(self.status_code())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn form_requests_do_not_want_json() {
+        let req = TestRequest::default()
+            .insert_header((CONTENT_TYPE, "application/x-www-form-urlencoded"))
+            .to_http_request();
+        assert!(!wants_json(&req));
+    }
+
+    #[test]
+    fn json_requests_want_json() {
+        let req = TestRequest::default()
+            .insert_header((CONTENT_TYPE, "application/json"))
+            .to_http_request();
+        assert!(wants_json(&req));
+    }
+
+    #[test]
+    fn clients_accepting_problem_details_want_json() {
+        let req = TestRequest::default()
+            .insert_header((ACCEPT, PROBLEM_JSON))
+            .to_http_request();
+        assert!(wants_json(&req));
+    }
+
+    #[test]
+    fn problem_details_use_the_canonical_reason_as_title() {
+        let problem = ProblemDetails::new(StatusCode::BAD_REQUEST, "Invalid email");
+        assert_eq!(problem.title, "Bad Request");
+        assert_eq!(problem.status, 400);
+        assert_eq!(problem.problem_type, "about:blank");
+    }
+}
@@ -0,0 +1,312 @@
+use std::time;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use redis::aio::ConnectionManager;
+
+use crate::client_info::ClientInfo;
+use crate::configuration::{RateLimitSettings, TokenBucketSettings};
+use crate::counter_store::CounterStore;
+use crate::problem_details::{wants_json, ProblemDetails};
+use crate::utils::{bytes_to_payload, e500_internal_server_error};
+
+/// Number of bucket units in a token, so that refilling `refill_per_minute` units per millisecond is exact
+const UNITS_PER_TOKEN: u64 = 60_000;
+
+/// Number of in-memory buckets above which expired ones are pruned
+const MAX_MEMORY_BUCKETS: usize = 10_000;
+
+/// Token bucket update performed atomically by Redis, mirroring `take_token`
+const TAKE_TOKEN_SCRIPT: &str = r"
+local capacity = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+local token = tonumber(ARGV[4])
+local bucket = redis.call('HMGET', KEYS[1], 'units', 'updated_at')
+local units = tonumber(bucket[1]) or capacity
+local updated_at = tonumber(bucket[2]) or now
+units = math.min(capacity, units + math.max(0, now - updated_at) * rate)
+local wait = 0
+if units >= token then
+    units = units - token
+else
+    wait = math.ceil((token - units) / rate)
+end
+redis.call('HSET', KEYS[1], 'units', units, 'updated_at', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / rate))
+return wait
+";
+
+/// Rate-limited route, identified by its method and path
+struct LimitedRoute {
+    method: Method,
+    path: &'static str,
+    name: &'static str,
+    by_email: bool,
+}
+
+/// Return the rate-limited route matching a request, if any
+///
+/// Subscriptions are also limited per target email, so that an address cannot be flooded with confirmation emails.
+fn limited_route(method: &Method, path: &str) -> Option<LimitedRoute> {
+    let routes = [
+        LimitedRoute {
+            method: Method::POST,
+            path: "/subscriptions",
+            name: "subscriptions",
+            by_email: true,
+        },
+        LimitedRoute {
+            method: Method::GET,
+            path: "/subscriptions/confirm",
+            name: "subscriptions_confirm",
+            by_email: false,
+        },
+        LimitedRoute {
+            method: Method::POST,
+            path: "/login",
+            name: "login",
+            by_email: false,
+        },
+    ];
+    routes
+        .into_iter()
+        .find(|r| r.method == method && r.path == path)
+}
+
+/// Token bucket stored in memory when Redis is not available
+struct MemoryBucket {
+    units: u64,
+    updated_at: u64,
+    expires_at: u64,
+}
+
+/// Token bucket rate limiter backed by Redis, falling back to memory if Redis is not available
+pub struct RateLimiter {
+    buckets: CounterStore<MemoryBucket>,
+    settings: RateLimitSettings,
+}
+
+impl RateLimiter {
+    /// Build the rate limiter, which only uses memory if no Redis connection is available
+    pub fn new(redis: Option<ConnectionManager>, settings: RateLimitSettings) -> Self {
+        Self {
+            buckets: CounterStore::new(redis),
+            settings,
+        }
+    }
+
+    /// Take a token from a bucket and return how long to wait before retrying if it was empty
+    pub async fn take(&self, key: &str, bucket: &TokenBucketSettings) -> Option<time::Duration> {
+        let now = u64::try_from(Utc::now().timestamp_millis()).unwrap_or_default();
+        let wait_millis = self
+            .take_from_redis(key, bucket, now)
+            .await
+            .unwrap_or_else(|| self.take_from_memory(key, bucket, now));
+        (wait_millis > 0).then(|| time::Duration::from_millis(wait_millis))
+    }
+
+    /// Take a token from a bucket stored in Redis, returning `None` if Redis is not available
+    async fn take_from_redis(
+        &self,
+        key: &str,
+        bucket: &TokenBucketSettings,
+        now: u64,
+    ) -> Option<u64> {
+        let mut redis = self.buckets.redis()?;
+        redis::Script::new(TAKE_TOKEN_SCRIPT)
+            .key(key)
+            .arg(bucket.capacity_units())
+            .arg(bucket.refill_rate())
+            .arg(now)
+            .arg(UNITS_PER_TOKEN)
+            .invoke_async(&mut redis)
+            .await
+            .inspect_err(|e| tracing::warn!(error = %e, "Failed to update rate limit in Redis"))
+            .ok()
+    }
+
+    /// Take a token from a bucket stored in memory
+    fn take_from_memory(&self, key: &str, bucket: &TokenBucketSettings, now: u64) -> u64 {
+        let mut memory = self.buckets.memory();
+        if memory.len() > MAX_MEMORY_BUCKETS {
+            memory.retain(|_, b| b.expires_at > now);
+        }
+        let stored = memory.get(key).map(|b| (b.units, b.updated_at));
+        let (units, wait_millis) = take_token(stored, now, bucket);
+        memory.insert(
+            key.to_string(),
+            MemoryBucket {
+                units,
+                updated_at: now,
+                expires_at: now + bucket.capacity_units() / bucket.refill_rate(),
+            },
+        );
+        wait_millis
+    }
+}
+
+impl TokenBucketSettings {
+    /// Capacity of the bucket in units
+    fn capacity_units(&self) -> u64 {
+        u64::from(self.capacity.max(1)) * UNITS_PER_TOKEN
+    }
+
+    /// Number of units added to the bucket each millisecond
+    fn refill_rate(&self) -> u64 {
+        u64::from(self.refill_per_minute.max(1))
+    }
+}
+
+/// Refill a bucket for the elapsed time and take a token from it
+///
+/// Return the remaining units and the number of milliseconds to wait if the bucket was empty.
+fn take_token(stored: Option<(u64, u64)>, now: u64, bucket: &TokenBucketSettings) -> (u64, u64) {
+    let capacity = bucket.capacity_units();
+    let rate = bucket.refill_rate();
+    let units = stored.map_or(capacity, |(units, updated_at)| {
+        let refill = now.saturating_sub(updated_at).saturating_mul(rate);
+        units.saturating_add(refill).min(capacity)
+    });
+    if units >= UNITS_PER_TOKEN {
+        (units - UNITS_PER_TOKEN, 0)
+    } else {
+        (units, (UNITS_PER_TOKEN - units).div_ceil(rate))
+    }
+}
+
+/// Extract the target email address from a subscription form or JSON body
+fn target_email(body: &[u8]) -> Option<String> {
+    #[derive(serde::Deserialize)]
+    struct Target {
+        email: String,
+    }
+
+    serde_urlencoded::from_bytes::<Target>(body)
+        .ok()
+        .or_else(|| serde_json::from_slice::<Target>(body).ok())
+        .map(|t| t.email.trim().to_lowercase())
+}
+
+/// Reject requests to public endpoints that exceed their rate limits, per client IP and per target email
+#[allow(clippy::future_not_send)]
+pub async fn rate_limit(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> actix_web::Result<ServiceResponse<impl MessageBody>> {
+    let Some(route) = limited_route(req.method(), req.path()) else {
+        return next.call(req).await;
+    };
+    let limiter = req
+        .app_data::<web::Data<RateLimiter>>()
+        .cloned()
+        .ok_or_else(|| e500_internal_server_error("Rate limiter not available"))?;
+
+    // Take a token from the bucket of the client IP
+    let ip = ClientInfo::from_http_request(req.request())
+        .ip
+        .unwrap_or_default();
+    let mut retry_after = limiter
+        .take(
+            &format!("rate_limit:{}:ip:{ip}", route.name),
+            &limiter.settings.ip,
+        )
+        .await;
+
+    // Take a token from the bucket of the target email, putting the body back for the handler
+    if retry_after.is_none() && route.by_email {
+        let body = req.extract::<web::Bytes>().await?;
+        if let Some(email) = target_email(&body) {
+            retry_after = limiter
+                .take(
+                    &format!("rate_limit:{}:email:{email}", route.name),
+                    &limiter.settings.email,
+                )
+                .await;
+        }
+        req.set_payload(bytes_to_payload(body));
+    }
+
+    // Return error with the number of seconds to wait if a bucket was empty
+    let Some(retry_after) = retry_after else {
+        return next.call(req).await;
+    };
+    let retry_after_secs = retry_after.as_millis().div_ceil(1000).to_string();
+    let detail = "Too many requests, please try again later";
+    let mut response = if wants_json(req.request()) {
+        ProblemDetails::new(StatusCode::TOO_MANY_REQUESTS, detail).into_response()
+    } else {
+        HttpResponse::TooManyRequests().body(detail)
+    };
+    response.headers_mut().insert(
+        RETRY_AFTER,
+        HeaderValue::from_str(&retry_after_secs).map_err(e500_internal_server_error)?,
+    );
+    let e = anyhow::anyhow!("The rate limit of {} was exceeded", route.name);
+    Err(InternalError::from_response(e, response).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket() -> TokenBucketSettings {
+        TokenBucketSettings {
+            capacity: 2,
+            refill_per_minute: 6,
+        }
+    }
+
+    #[test]
+    fn a_new_bucket_is_full() {
+        let (units, wait) = take_token(None, 0, &bucket());
+        assert_eq!(units, UNITS_PER_TOKEN);
+        assert_eq!(wait, 0);
+    }
+
+    #[test]
+    fn an_empty_bucket_returns_the_time_until_the_next_token() {
+        let (units, _) = take_token(None, 0, &bucket());
+        let (units, _) = take_token(Some((units, 0)), 0, &bucket());
+        let (units, wait) = take_token(Some((units, 0)), 0, &bucket());
+        assert_eq!(units, 0);
+        // 6 tokens per minute means one token every 10 seconds
+        assert_eq!(wait, 10_000);
+    }
+
+    #[test]
+    fn buckets_refill_over_time_up_to_their_capacity() {
+        let (units, wait) = take_token(Some((0, 0)), 10_000, &bucket());
+        assert_eq!((units, wait), (0, 0));
+        let (units, wait) = take_token(Some((0, 0)), 3_600_000, &bucket());
+        assert_eq!((units, wait), (UNITS_PER_TOKEN, 0));
+    }
+
+    #[test]
+    fn target_emails_are_extracted_from_forms_and_json() {
+        assert_eq!(
+            target_email(b"name=le%20guin&email=Ursula%40Example.com"),
+            Some("ursula@example.com".to_string())
+        );
+        assert_eq!(
+            target_email(br#"{"name": "le guin", "email": "ursula@example.com"}"#),
+            Some("ursula@example.com".to_string())
+        );
+        assert_eq!(target_email(b"name=le%20guin"), None);
+    }
+
+    #[test]
+    fn only_public_endpoints_are_limited() {
+        assert!(limited_route(&Method::POST, "/subscriptions").is_some_and(|r| r.by_email));
+        assert!(limited_route(&Method::GET, "/subscriptions/confirm").is_some());
+        assert!(limited_route(&Method::POST, "/login").is_some());
+        assert!(limited_route(&Method::GET, "/login").is_none());
+        assert!(limited_route(&Method::POST, "/admin/newsletters").is_none());
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::session_state::TypedSession;
+use crate::utils::{e500_internal_server_error, escape_html};
+
+/// API keys GET handler
+#[allow(clippy::future_not_send)]
+pub async fn api_keys_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> actix_web::Result<HttpResponse> {
+    // Process incoming flash messages
+    let mut msg = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Retrieve the CSRF token to be embedded in forms
+    let csrf_token = session.csrf_token().map_err(e500_internal_server_error)?;
+
+    // Render the list of API keys, only showing the prefix of each key
+    let api_keys = sqlx::query!(
+        r#"
+        SELECT
+            k.api_key_id,
+            k.name,
+            k.key_prefix,
+            k.scopes,
+            k.created_at,
+            k.last_used_at,
+            k.revoked_at,
+            u.username
+        FROM api_keys k
+        JOIN users u ON u.user_id = k.user_id
+        ORDER BY k.created_at
+        "#
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .map_err(e500_internal_server_error)?;
+    let format_timestamp = |t: Option<DateTime<Utc>>| {
+        t.map_or_else(
+            || "-".to_string(),
+            |t| t.format("%Y-%m-%d %H:%M").to_string(),
+        )
+    };
+    let mut rows = String::new();
+    for k in api_keys {
+        let action = if k.revoked_at.is_some() {
+            format!("Revoked on {}", format_timestamp(k.revoked_at))
+        } else {
+            format!(
+                "<form action=\"/admin/api-keys/{}/revoke\" method=\"post\"><input hidden type=\"text\" name=\"csrf_token\" value=\"{}\"><button type=\"submit\">Revoke</button></form>",
+                k.api_key_id, csrf_token
+            )
+        };
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td><code>{}...</code></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(&k.name),
+            escape_html(&k.key_prefix),
+            escape_html(&k.scopes.join(", ")),
+            escape_html(&k.username),
+            k.created_at.format("%Y-%m-%d %H:%M"),
+            format_timestamp(k.last_used_at),
+            action
+        )
+        .unwrap();
+    }
+
+    // Display API keys page with any flash message
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("api_keys_form.html"),
+            msg, rows, csrf_token
+        )))
+}
@@ -0,0 +1,87 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{create_api_key, revoke_api_key, ApiKeyId, ApiKeyScope, UserId};
+use crate::utils::{e303_see_other, e500_internal_server_error, escape_html};
+
+/// Maximum length of an API key name
+const MAX_NAME_LEN: usize = 64;
+
+/// Web form
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    scopes: String,
+}
+
+/// API keys POST handler, which displays the new key once
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(
+    name = "Create an API key",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn api_keys(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> actix_web::Result<HttpResponse> {
+    // Return error in flash message and redirect back to API keys page if the form is invalid
+    let name = form.0.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        FlashMessage::error(format!(
+            "The API key name must be between 1 and {MAX_NAME_LEN} characters long"
+        ))
+        .send();
+        return Ok(e303_see_other("/admin/api-keys"));
+    }
+    let scopes = match ApiKeyScope::parse_list(&form.0.scopes) {
+        Ok(scopes) => scopes,
+        Err(e) => {
+            FlashMessage::error(escape_html(&e)).send();
+            return Ok(e303_see_other("/admin/api-keys"));
+        }
+    };
+
+    // Store the hashed API key in the database
+    let (_, api_key) = create_api_key(&db_pool, user_id.into_inner(), name, &scopes)
+        .await
+        .context("Failed to store API key in the database")
+        .map_err(e500_internal_server_error)?;
+
+    // Display the API key, which cannot be retrieved afterwards
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("api_key_created.html"),
+            escape_html(name),
+            api_key.expose_secret()
+        )))
+}
+
+/// API key revocation POST handler
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(name = "Revoke an API key", skip_all)]
+pub async fn api_key_revocation(
+    api_key_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Revoke the API key, so that it can no longer be used
+    let revoked = revoke_api_key(&db_pool, ApiKeyId::new(api_key_id.into_inner()))
+        .await
+        .context("Failed to revoke API key")
+        .map_err(e500_internal_server_error)?;
+
+    // Redirect back to API keys page and display flash message
+    if revoked {
+        FlashMessage::info("The API key has been revoked").send();
+    } else {
+        FlashMessage::error("The API key does not exist or has already been revoked").send();
+    }
+    Ok(e303_see_other("/admin/api-keys"))
+}
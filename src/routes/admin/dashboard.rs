@@ -2,15 +2,17 @@ use actix_web::http::header::ContentType;
 use actix_web::{web, HttpResponse};
 use sqlx::PgPool;
 
-use crate::authentication::UserId;
+use crate::authentication::{Role, UserId};
+use crate::session_state::TypedSession;
 use crate::utils::{e500_internal_server_error, get_username};
 
-// TODO: Implement a login-protected admin functionality to invite other admins/collaborators
-
 /// Admin dashboard handler
+#[allow(clippy::future_not_send)]
 pub async fn dashboard(
     db_pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+    session: TypedSession,
 ) -> actix_web::Result<HttpResponse> {
     // Validate session and retrieve associated `user_id` and `username`
     let user_id = user_id.into_inner();
@@ -18,8 +20,16 @@ pub async fn dashboard(
         .await
         .map_err(e500_internal_server_error)?;
 
+    // Retrieve the CSRF token to be embedded in forms
+    let csrf_token = session.csrf_token().map_err(e500_internal_server_error)?;
+
     // Display admin dashboard containing the retrieved `username`
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(format!(include_str!("dashboard.html"), username)))
+        .body(format!(
+            include_str!("dashboard.html"),
+            username,
+            role.into_inner(),
+            csrf_token
+        )))
 }
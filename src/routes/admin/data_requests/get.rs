@@ -0,0 +1,32 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::HttpResponse;
+use actix_web_flash_messages::IncomingFlashMessages;
+
+use crate::session_state::TypedSession;
+use crate::utils::e500_internal_server_error;
+
+/// Data subject requests GET handler
+#[allow(clippy::future_not_send)]
+pub async fn data_requests_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+) -> actix_web::Result<HttpResponse> {
+    // Process incoming flash messages
+    let mut msg = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Retrieve the CSRF token to be embedded in forms
+    let csrf_token = session.csrf_token().map_err(e500_internal_server_error)?;
+
+    // Display data subject requests form with any flash message
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("data_requests_form.html"),
+            msg, csrf_token, csrf_token
+        )))
+}
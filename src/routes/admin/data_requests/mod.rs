@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::data_requests_form;
+pub use post::{data_erasure, data_export};
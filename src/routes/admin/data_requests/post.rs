@@ -0,0 +1,86 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::authentication::UserId;
+use crate::gdpr::{erase_subject_data, export_subject_data, ErasureMode, Requester};
+use crate::utils::{e303_see_other, e400_bad_request, e500_internal_server_error, escape_html};
+
+/// Export web form
+#[derive(serde::Deserialize)]
+pub struct ExportFormData {
+    email: String,
+}
+
+/// Erasure web form
+#[derive(serde::Deserialize)]
+pub struct ErasureFormData {
+    email: String,
+    mode: ErasureMode,
+}
+
+/// Data subject access request handler, which returns all data held about a subject as JSON
+#[tracing::instrument(name = "Export data subject data", skip_all, fields(user_id=%&*user_id))]
+pub async fn data_export(
+    form: web::Form<ExportFormData>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> actix_web::Result<HttpResponse> {
+    // Return early if no email address was provided
+    let email = form.0.email.trim().to_string();
+    if email.is_empty() {
+        return Err(e400_bad_request("The email address cannot be empty"));
+    }
+
+    // Collect all data held about the subject
+    let data = export_subject_data(&db_pool, &email, Requester::Admin(user_id.into_inner()))
+        .await
+        .context("Failed to export data subject data")
+        .map_err(e500_internal_server_error)?;
+
+    // Return the data as a downloadable JSON document
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(
+                "data-subject-export.json".to_string(),
+            )],
+        })
+        .json(data))
+}
+
+/// Data subject erasure request handler
+#[tracing::instrument(name = "Erase data subject data", skip_all, fields(user_id=%&*user_id))]
+pub async fn data_erasure(
+    form: web::Form<ErasureFormData>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> actix_web::Result<HttpResponse> {
+    // Return error in flash message and redirect back to the form if no email address was provided
+    let email = form.0.email.trim().to_string();
+    if email.is_empty() {
+        FlashMessage::error("The email address cannot be empty").send();
+        return Ok(e303_see_other("/admin/data-requests"));
+    }
+
+    // Erase or pseudonymise all data held about the subject
+    let affected_rows = erase_subject_data(
+        &db_pool,
+        &email,
+        form.0.mode,
+        Requester::Admin(user_id.into_inner()),
+    )
+    .await
+    .context("Failed to erase data subject data")
+    .map_err(e500_internal_server_error)?;
+
+    // Redirect back to the form and display flash message
+    FlashMessage::info(format!(
+        "The data held about {} has been processed ({affected_rows} records affected)",
+        escape_html(&email)
+    ))
+    .send();
+    Ok(e303_see_other("/admin/data-requests"))
+}
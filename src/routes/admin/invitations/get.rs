@@ -0,0 +1,73 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use chrono::Utc;
+use sqlx::PgPool;
+
+use crate::session_state::TypedSession;
+use crate::utils::{e500_internal_server_error, escape_html};
+
+/// Invitations GET handler
+#[allow(clippy::future_not_send)]
+pub async fn invitations_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> actix_web::Result<HttpResponse> {
+    // Process incoming flash messages
+    let mut msg = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Retrieve the CSRF token to be embedded in forms
+    let csrf_token = session.csrf_token().map_err(e500_internal_server_error)?;
+
+    // Render the list of invitations along with their status
+    let invitations = sqlx::query!(
+        r#"
+        SELECT
+            i.email,
+            i.role,
+            i.created_at,
+            i.expires_at,
+            i.accepted_at,
+            u.username AS invited_by
+        FROM invitations i
+        JOIN users u ON u.user_id = i.invited_by
+        ORDER BY i.created_at
+        "#
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .map_err(e500_internal_server_error)?;
+    let now = Utc::now();
+    let mut rows = String::new();
+    for i in invitations {
+        let status = match i.accepted_at {
+            Some(t) => format!("Accepted on {}", t.format("%Y-%m-%d %H:%M")),
+            None if i.expires_at <= now => "Expired".to_string(),
+            None => format!("Pending until {}", i.expires_at.format("%Y-%m-%d %H:%M")),
+        };
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(&i.email),
+            escape_html(&i.role),
+            escape_html(&i.invited_by),
+            i.created_at.format("%Y-%m-%d %H:%M"),
+            status
+        )
+        .unwrap();
+    }
+
+    // Display invitations page with any flash message
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("invitations_form.html"),
+            msg, rows, csrf_token
+        )))
+}
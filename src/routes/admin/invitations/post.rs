@@ -0,0 +1,110 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::authentication::{create_invitation, Role, UserId, INVITATION_TTL_HOURS};
+use crate::domain::EmailAddress;
+use crate::outbox::{enqueue_email, EmailKind, OutboxEmail};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e303_see_other, e500_internal_server_error, escape_html, PgTransaction};
+
+/// Web form
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+    role: String,
+}
+
+/// Invitations POST handler
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(
+    name = "Invite a collaborator",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn invitations(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    user_id: web::ReqData<UserId>,
+) -> actix_web::Result<HttpResponse> {
+    // Return error in flash message and redirect back to invitations page if the email is invalid
+    let FormData { email, role } = form.0;
+    let email = match EmailAddress::parse(email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(escape_html(&e)).send();
+            return Ok(e303_see_other("/admin/invitations"));
+        }
+    };
+
+    // Return error in flash message and redirect back to invitations page if the role is invalid
+    let role = match Role::parse(&role) {
+        Ok(role) => role,
+        Err(e) => {
+            FlashMessage::error(escape_html(&e)).send();
+            return Ok(e303_see_other("/admin/invitations"));
+        }
+    };
+
+    // Store the invitation and write the email with the invitation link to the outbox
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500_internal_server_error)?;
+    let token = create_invitation(&mut transaction, &email, role, user_id.into_inner())
+        .await
+        .context("Failed to store invitation in the database")
+        .map_err(e500_internal_server_error)?;
+    enqueue_invitation_email(&mut transaction, &email, &base_url.0, &token)
+        .await
+        .context("Failed to enqueue the invitation email")
+        .map_err(e500_internal_server_error)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store the invitation")
+        .map_err(e500_internal_server_error)?;
+
+    // Redirect back to invitations page and display flash message
+    FlashMessage::info(format!(
+        "An invitation has been sent to {}",
+        escape_html(email.as_ref())
+    ))
+    .send();
+    Ok(e303_see_other("/admin/invitations"))
+}
+
+/// Write an email containing the single-use invitation link to the outbox
+#[tracing::instrument(name = "Enqueue an invitation email", skip(transaction, email, token))]
+async fn enqueue_invitation_email(
+    transaction: &mut PgTransaction,
+    email: &EmailAddress,
+    base_url: &str,
+    token: &str,
+) -> anyhow::Result<()> {
+    let invitation_link = format!("{base_url}/invitations/accept?invitation_token={token}");
+    let html_body = &format!(
+        "You have been invited to help manage our newsletter!<br />\
+        Click <a href=\"{invitation_link}\">here</a> to choose your username and password. \
+        The link expires in {INVITATION_TTL_HOURS} hours."
+    );
+    let text_body = &format!(
+        "You have been invited to help manage our newsletter!\n\
+        Visit {invitation_link} to choose your username and password. \
+        The link expires in {INVITATION_TTL_HOURS} hours."
+    );
+
+    let email = OutboxEmail {
+        kind: EmailKind::Invitation,
+        recipient: email,
+        subject: "You have been invited!",
+        html_body,
+        text_body,
+        single_use_token: Some(token),
+    };
+    enqueue_email(transaction, &email).await?;
+    Ok(())
+}
@@ -1,12 +1,28 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
+use crate::authentication::{revoke_user_session, UserId};
 use crate::session_state::TypedSession;
-use crate::utils::e303_see_other;
+use crate::utils::{e303_see_other, e500_internal_server_error};
 
 /// Logout handler
 #[allow(clippy::future_not_send)]
-pub async fn logout(session: TypedSession) -> actix_web::Result<HttpResponse> {
+pub async fn logout(
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> actix_web::Result<HttpResponse> {
+    // Remove the session from the user's session index
+    if let Some(session_id) = session
+        .get_session_id()
+        .map_err(e500_internal_server_error)?
+    {
+        revoke_user_session(user_id.into_inner(), session_id, &db_pool)
+            .await
+            .map_err(e500_internal_server_error)?;
+    }
+
     // Perform logout, redirect to login form, and display flash message
     session.logout();
     FlashMessage::info("You have successfully logged out").send();
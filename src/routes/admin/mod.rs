@@ -1,9 +1,27 @@
+mod api_keys;
 mod dashboard;
+mod data_requests;
+mod invitations;
 mod logout;
 mod newsletters;
+mod passkeys;
 mod password;
+mod segments;
+mod sessions;
+mod subscribers;
+mod two_factor;
+mod users;
 
+pub use api_keys::*;
 pub use dashboard::*;
+pub use data_requests::*;
+pub use invitations::*;
 pub use logout::*;
 pub use newsletters::*;
+pub use passkeys::*;
 pub use password::*;
+pub use segments::*;
+pub use sessions::*;
+pub use subscribers::*;
+pub use two_factor::*;
+pub use users::*;
@@ -1,24 +1,77 @@
 use std::fmt::Write;
 
-use crate::idempotency::IdempotencyKey;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+use crate::idempotency::IdempotencyKey;
+use crate::routes::list_segments;
+use crate::session_state::TypedSession;
+use crate::utils::{e500_internal_server_error, escape_html};
 
 /// Newsletters GET handler
-pub async fn newsletters_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+#[allow(clippy::future_not_send)]
+pub async fn newsletters_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> actix_web::Result<HttpResponse> {
     // Process incoming flash messages
     let mut msg = String::new();
     for m in flash_messages.iter() {
         writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
     }
 
+    // Retrieve the CSRF token to be embedded in forms
+    let csrf_token = session.csrf_token().map_err(e500_internal_server_error)?;
+
+    // Preview the number of subscribers reached by each audience option
+    let n_confirmed = count_confirmed_subscribers(&db_pool)
+        .await
+        .map_err(e500_internal_server_error)?;
+    let mut options = String::new();
+    writeln!(
+        options,
+        "<option value=\"\">All confirmed subscribers ({n_confirmed})</option>"
+    )
+    .unwrap();
+    for s in list_segments(&db_pool)
+        .await
+        .map_err(e500_internal_server_error)?
+    {
+        writeln!(
+            options,
+            "<option value=\"{}\">{} ({})</option>",
+            s.segment_id,
+            escape_html(&s.name),
+            s.subscriber_count
+        )
+        .unwrap();
+    }
+
     // Display newsletters form with any flash message
     let idempotency_key = IdempotencyKey::generate();
-    HttpResponse::Ok()
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
             include_str!("newsletters_form.html"),
-            msg, idempotency_key
-        ))
+            msg, options, idempotency_key, csrf_token
+        )))
+}
+
+/// Count all confirmed subscribers
+#[tracing::instrument(skip_all)]
+async fn count_confirmed_subscribers(db_pool: &PgPool) -> sqlx::Result<i64> {
+    let row = sqlx::query!(
+        r#"
+        SELECT count(*) AS "count!"
+        FROM subscriptions
+        WHERE status = 'confirmed'
+        "#
+    )
+    .fetch_one(db_pool)
+    .await?;
+
+    Ok(row.count)
 }
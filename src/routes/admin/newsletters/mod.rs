@@ -2,4 +2,7 @@ mod get;
 mod post;
 
 pub use get::newsletters_form;
-pub use post::{newsletters, NewsletterIssueId};
+pub use post::{
+    enqueue_delivery_task, insert_newsletter_issue, newsletters, replay_success_message,
+    NewsletterIssueId,
+};
@@ -1,6 +1,9 @@
 use std::fmt;
 use std::ops::Deref;
 
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
@@ -8,7 +11,10 @@ use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::authentication::UserId;
-use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::idempotency::{IdempotentTransaction, ReplayedResponse};
+use crate::job_queue::enqueue_jobs;
+use crate::outbox::{EmailKind, EmailStatus, OutboxDispatch};
+use crate::routes::{segment_exists, SegmentId};
 use crate::utils::{e303_see_other, e400_bad_request, e500_internal_server_error};
 
 /// Web form
@@ -17,10 +23,13 @@ pub struct FormData {
     title: String,
     content_html: String,
     content_text: String,
-    idempotency_key: String,
+    #[serde(default)]
+    segment_id: String,
 }
 
 /// Newsletters handler
+///
+/// The idempotency middleware replays the saved response of retried submissions.
 #[allow(clippy::future_not_send)]
 #[tracing::instrument(
     name = "Publish a newsletter issue",
@@ -30,47 +39,74 @@ pub struct FormData {
 pub async fn newsletters(
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
+    mut transaction: IdempotentTransaction,
     user_id: web::ReqData<UserId>,
 ) -> actix_web::Result<HttpResponse> {
-    // Return early if we have a saved response in the database, otherwise start processing the request
-    let user_id = user_id.into_inner();
     let FormData {
         title,
         content_html,
         content_text,
-        idempotency_key,
+        segment_id,
     } = form.0;
-    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400_bad_request)?;
-    let mut transaction = match try_processing(&db_pool, &idempotency_key, user_id)
-        .await
-        .map_err(e500_internal_server_error)?
-    {
-        NextAction::StartProcessing(t) => t,
-        NextAction::ReturnSavedResponse(response) => {
-            success_message().send();
-            return Ok(response);
+    let segment_id = parse_segment_id(&segment_id).map_err(e400_bad_request)?;
+    if let Some(segment_id) = segment_id {
+        if !segment_exists(&db_pool, segment_id)
+            .await
+            .context("Failed to look up the audience segment")
+            .map_err(e500_internal_server_error)?
+        {
+            return Err(e400_bad_request(format!(
+                "There is no audience segment with id {segment_id}"
+            )));
         }
-    };
+    }
 
-    // Store newsletter issue in the database and create a task in the issue delivery queue
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &content_html, &content_text)
-        .await
-        .context("Failed to store newsletter issue in the database")
-        .map_err(e500_internal_server_error)?;
-    enqueue_delivery_task(&mut transaction, issue_id)
+    // Store newsletter issue in the database and write its emails to the outbox
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &content_html,
+        &content_text,
+        segment_id,
+    )
+    .await
+    .context("Failed to store newsletter issue in the database")
+    .map_err(e500_internal_server_error)?;
+    enqueue_delivery_task(&mut transaction, issue_id, segment_id)
         .await
         .context("Failed to enqueue delivery task")
         .map_err(e500_internal_server_error)?;
 
-    // Save response for idempotency, redirect back to the endpoint, and display flash message
-    let response = e303_see_other("/admin/newsletters");
-    let response = save_response(transaction, &idempotency_key, user_id, response)
-        .await
-        .map_err(e500_internal_server_error)?;
+    // Redirect back to the endpoint, and display flash message
     success_message().send();
+    Ok(e303_see_other("/admin/newsletters"))
+}
+
+/// Display the flash message again when a retried submission gets the saved response of the first one
+#[allow(clippy::future_not_send)]
+pub async fn replay_success_message(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> actix_web::Result<ServiceResponse<impl MessageBody>> {
+    let response = next.call(req).await?;
+    if response
+        .response()
+        .extensions()
+        .contains::<ReplayedResponse>()
+    {
+        success_message().send();
+    }
     Ok(response)
 }
 
+/// Parse the optional audience segment selected in the form (empty means all confirmed subscribers)
+fn parse_segment_id(segment_id: &str) -> Result<Option<SegmentId>, uuid::Error> {
+    if segment_id.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(SegmentId::new(Uuid::parse_str(segment_id)?)))
+}
+
 /// Return a flash message in case of successful newsletter publication
 fn success_message() -> FlashMessage {
     FlashMessage::info("The newsletter issue has been accepted, emails will go out shortly")
@@ -102,11 +138,12 @@ impl Deref for NewsletterIssueId {
 
 /// Store newsletter issue in the database
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
     content_html: &str,
     content_text: &str,
+    segment_id: Option<SegmentId>,
 ) -> sqlx::Result<NewsletterIssueId> {
     // Save newsletter issue to the database
     let newsletter_issue_id = NewsletterIssueId::new(Uuid::new_v4());
@@ -118,14 +155,16 @@ async fn insert_newsletter_issue(
                 title,
                 content_html,
                 content_text,
+                segment_id,
                 published_at
             )
-            VALUES ($1, $2, $3, $4, now())
+            VALUES ($1, $2, $3, $4, $5, now())
             "#,
             *newsletter_issue_id,
             title,
             content_html,
             content_text,
+            segment_id.map(|s| *s),
         ))
         .await?;
 
@@ -133,27 +172,67 @@ async fn insert_newsletter_issue(
     Ok(newsletter_issue_id)
 }
 
-/// Create a task in the issue delivery queue
+/// Write the issue to the outbox for each confirmed subscriber in the audience segment, and enqueue its dispatch
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_task(
+pub async fn enqueue_delivery_task(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: NewsletterIssueId,
-) -> sqlx::Result<()> {
-    // Create a task in issue delivery queue table stored in the database
-    transaction
-        .execute(sqlx::query!(
-            r#"
-            INSERT INTO issue_delivery_queue (
-                newsletter_issue_id,
-                subscriber_email
+    segment_id: Option<SegmentId>,
+) -> anyhow::Result<()> {
+    // Write an email for each confirmed subscriber in the audience, evaluating the segment definition
+    let dispatches: Vec<OutboxDispatch> = sqlx::query!(
+        r#"
+        INSERT INTO outbox (
+            message_id,
+            kind,
+            recipient,
+            subject,
+            html_body,
+            text_body,
+            newsletter_issue_id,
+            status,
+            attempts,
+            created_at
+        )
+        SELECT
+            gen_random_uuid(),
+            $3,
+            s.email,
+            n.title,
+            n.content_html,
+            n.content_text,
+            n.newsletter_issue_id,
+            $4,
+            0,
+            now()
+        FROM subscriptions s, newsletter_issues n
+        WHERE
+            n.newsletter_issue_id = $1 AND
+            s.status = 'confirmed' AND (
+                $2::uuid IS NULL OR EXISTS (
+                    SELECT 1
+                    FROM segments g
+                    WHERE
+                        g.segment_id = $2 AND
+                        subscriber_in_segment(s, g)
+                )
             )
-            SELECT $1, email
-            FROM subscriptions
-            WHERE status = 'confirmed'
-            "#,
-            *newsletter_issue_id,
-        ))
-        .await?;
-
-    Ok(())
+        RETURNING message_id
+        "#,
+        *newsletter_issue_id,
+        segment_id.map(|s| *s),
+        EmailKind::Newsletter.as_str(),
+        EmailStatus::Pending.as_str()
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to write the newsletter issue to the outbox")?
+    .into_iter()
+    .map(|r| OutboxDispatch {
+        message_id: r.message_id,
+    })
+    .collect();
+
+    // Enqueue the dispatch of the emails in the same transaction as the issue
+    enqueue_jobs(transaction, &dispatches).await
 }
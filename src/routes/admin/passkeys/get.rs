@@ -0,0 +1,61 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::authentication::{list_passkeys, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::{e500_internal_server_error, escape_html};
+
+/// Passkeys GET handler
+#[allow(clippy::future_not_send)]
+pub async fn passkeys_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> actix_web::Result<HttpResponse> {
+    // Process incoming flash messages
+    let mut msg = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Retrieve the CSRF token to be embedded in forms
+    let csrf_token = session.csrf_token().map_err(e500_internal_server_error)?;
+
+    // Render the list of passkeys registered by the logged-in user
+    let passkeys = list_passkeys(user_id.into_inner(), &db_pool)
+        .await
+        .map_err(e500_internal_server_error)?;
+    let format_timestamp = |t: Option<DateTime<Utc>>| {
+        t.map_or_else(
+            || "-".to_string(),
+            |t| t.format("%Y-%m-%d %H:%M").to_string(),
+        )
+    };
+    let mut rows = String::new();
+    for p in passkeys {
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td><form action=\"/admin/passkeys/{}/delete\" method=\"post\"><input hidden type=\"text\" name=\"csrf_token\" value=\"{}\"><button type=\"submit\">Delete</button></form></td></tr>",
+            escape_html(&p.name),
+            p.created_at.format("%Y-%m-%d %H:%M"),
+            format_timestamp(p.last_used_at),
+            p.passkey_id,
+            csrf_token
+        )
+        .unwrap();
+    }
+
+    // Display passkeys page with any flash message
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("passkeys_form.html"),
+            msg, rows, csrf_token
+        )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::passkeys_form;
+pub use post::{passkey_deletion, passkey_registration_finish, passkey_registration_start};
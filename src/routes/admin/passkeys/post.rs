@@ -0,0 +1,115 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+use webauthn_rs::prelude::RegisterPublicKeyCredential;
+use webauthn_rs::Webauthn;
+
+use crate::authentication::{delete_passkey, get_passkeys, store_passkey, PasskeyError, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::{e303_see_other, e500_internal_server_error, get_username};
+
+/// Maximum length of a passkey name
+const MAX_NAME_LEN: usize = 64;
+
+/// Passkey registration request body
+#[derive(serde::Deserialize)]
+pub struct FinishData {
+    name: String,
+    credential: RegisterPublicKeyCredential,
+}
+
+/// Passkey registration POST handler, which starts the registration ceremony
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(
+    name = "Start a passkey registration",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn passkey_registration_start(
+    db_pool: web::Data<PgPool>,
+    webauthn: web::Data<Webauthn>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, PasskeyError> {
+    // Prevent the same authenticator from being registered twice
+    let user_id = user_id.into_inner();
+    let username = get_username(user_id, &db_pool).await?;
+    let exclude_credentials = get_passkeys(user_id, &db_pool)
+        .await?
+        .iter()
+        .map(|p| p.cred_id().clone())
+        .collect();
+
+    // Ask the browser to create a new credential bound to the user
+    let (challenge, state) = webauthn
+        .start_passkey_registration(*user_id, &username, &username, Some(exclude_credentials))
+        .context("Failed to start the passkey registration")?;
+    session
+        .insert_passkey_registration(&state)
+        .context("Failed to store the passkey registration state")?;
+    Ok(HttpResponse::Ok().json(challenge))
+}
+
+/// Passkey registration POST handler, which completes the registration ceremony
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(
+    name = "Register a passkey",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn passkey_registration_finish(
+    body: web::Json<FinishData>,
+    db_pool: web::Data<PgPool>,
+    webauthn: web::Data<Webauthn>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, PasskeyError> {
+    // Validate the name before consuming the ceremony, so that the user can retry
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(PasskeyError::InvalidName(MAX_NAME_LEN));
+    }
+
+    // Verify the new credential against the challenge stored in the session
+    let state = session
+        .take_passkey_registration()
+        .context("Failed to retrieve the passkey registration state")?
+        .ok_or(PasskeyError::MissingCeremony)?;
+    let passkey = webauthn
+        .finish_passkey_registration(&body.credential, &state)
+        .map_err(PasskeyError::InvalidCredential)?;
+
+    // Store the passkey, so that it can be used to login
+    store_passkey(user_id.into_inner(), name, &passkey, &db_pool).await?;
+    FlashMessage::info("The passkey has been registered").send();
+    Ok(HttpResponse::Created().finish())
+}
+
+/// Passkey deletion POST handler
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(
+    name = "Delete a passkey",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn passkey_deletion(
+    passkey_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> actix_web::Result<HttpResponse> {
+    // Delete the passkey, only if it belongs to the logged-in user
+    let deleted = delete_passkey(user_id.into_inner(), passkey_id.into_inner(), &db_pool)
+        .await
+        .context("Failed to delete passkey")
+        .map_err(e500_internal_server_error)?;
+
+    // Redirect back to passkeys page and display flash message
+    if deleted {
+        FlashMessage::info("The passkey has been deleted").send();
+    } else {
+        FlashMessage::error("The passkey does not exist").send();
+    }
+    Ok(e303_see_other("/admin/passkeys"))
+}
@@ -4,9 +4,14 @@ use actix_web::http::header::ContentType;
 use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
 
+use crate::session_state::TypedSession;
+use crate::utils::e500_internal_server_error;
+
 /// Admin password GET handler
+#[allow(clippy::future_not_send)]
 pub async fn password_form(
     flash_messages: IncomingFlashMessages,
+    session: TypedSession,
 ) -> actix_web::Result<HttpResponse> {
     // Process incoming flash messages
     let mut msg = String::new();
@@ -14,8 +19,11 @@ pub async fn password_form(
         writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
     }
 
+    // Retrieve the CSRF token to be embedded in forms
+    let csrf_token = session.csrf_token().map_err(e500_internal_server_error)?;
+
     // Display password form with any flash message
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(format!(include_str!("password_form.html"), msg)))
+        .body(format!(include_str!("password_form.html"), msg, csrf_token)))
 }
@@ -1,9 +1,13 @@
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
-use secrecy::{ExposeSecret, SecretString};
+use secrecy::SecretString;
 use sqlx::PgPool;
 
-use crate::authentication::{change_password, validate_creds, AuthError, Credentials, UserId};
+use crate::authentication::{
+    change_password, revoke_other_user_sessions, validate_creds, validate_new_password, AuthError,
+    Credentials, UserId,
+};
+use crate::session_state::TypedSession;
 use crate::utils::{e303_see_other, e500_internal_server_error, get_username};
 
 /// Web form
@@ -15,10 +19,12 @@ pub struct FormData {
 }
 
 /// Admin password POST handler
+#[allow(clippy::future_not_send)]
 pub async fn password(
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    session: TypedSession,
 ) -> actix_web::Result<HttpResponse> {
     // Validate session and retrieve associated `user_id` and `username`
     let user_id = user_id.into_inner();
@@ -26,21 +32,9 @@ pub async fn password(
         .await
         .map_err(e500_internal_server_error)?;
 
-    // Return error in flash message and redirect back to password form if new password fields do not match
-    if form.new_password.expose_secret() != form.new_password2.expose_secret() {
-        FlashMessage::error("New passwords fields must match").send();
-        return Ok(e303_see_other("/admin/password"));
-    }
-
-    // Return error in flash message and redirect back to password form if new password is too short
-    if form.new_password.expose_secret().len() < 12 {
-        FlashMessage::error("The password must be at least 12 characters long").send();
-        return Ok(e303_see_other("/admin/password"));
-    }
-
-    // Return error in flash message and redirect back to password form if new password is too long
-    if form.new_password.expose_secret().len() > 128 {
-        FlashMessage::error("The password must contain a maximum of 128 characters").send();
+    // Return error in flash message and redirect back to password form if the new password is invalid
+    if let Err(e) = validate_new_password(&form.new_password, &form.new_password2) {
+        FlashMessage::error(e).send();
         return Ok(e303_see_other("/admin/password"));
     }
 
@@ -60,10 +54,17 @@ pub async fn password(
         };
     }
 
-    // Change the password and display flash message
+    // Change the password, log out the other sessions that may have been opened with the old one,
+    // and display flash message
     change_password(user_id, form.0.new_password, &db_pool)
         .await
         .map_err(e500_internal_server_error)?;
+    let current_session_id = session
+        .get_session_id()
+        .map_err(e500_internal_server_error)?;
+    revoke_other_user_sessions(user_id, current_session_id, &db_pool)
+        .await
+        .map_err(e500_internal_server_error)?;
     FlashMessage::info("Your password has been changed").send();
     Ok(e303_see_other("/admin/password"))
 }
@@ -0,0 +1,109 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::routes::SegmentId;
+use crate::session_state::TypedSession;
+use crate::utils::{e500_internal_server_error, escape_html};
+
+/// Segment definition along with the number of confirmed subscribers it matches
+pub struct SegmentSummary {
+    pub segment_id: SegmentId,
+    pub name: String,
+    pub tags: Vec<String>,
+    pub attributes: serde_json::Value,
+    pub joined_after: Option<DateTime<Utc>>,
+    pub joined_before: Option<DateTime<Utc>>,
+    pub subscriber_count: i64,
+}
+
+/// Segments GET handler
+#[allow(clippy::future_not_send)]
+pub async fn segments_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> actix_web::Result<HttpResponse> {
+    // Process incoming flash messages
+    let mut msg = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Retrieve the CSRF token to be embedded in forms
+    let csrf_token = session.csrf_token().map_err(e500_internal_server_error)?;
+
+    // Render the stored segments along with a preview of the matching subscribers
+    let segments = list_segments(&db_pool)
+        .await
+        .map_err(e500_internal_server_error)?;
+    let format_date = |d: Option<DateTime<Utc>>| {
+        d.map_or_else(|| "-".to_string(), |d| d.format("%Y-%m-%d").to_string())
+    };
+    let mut rows = String::new();
+    for s in segments {
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(&s.name),
+            escape_html(&s.tags.join(", ")),
+            escape_html(&s.attributes.to_string()),
+            format_date(s.joined_after),
+            format_date(s.joined_before),
+            s.subscriber_count
+        )
+        .unwrap();
+    }
+
+    // Display segments page with any flash message
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("segments_form.html"),
+            msg, rows, csrf_token
+        )))
+}
+
+/// Retrieve all stored segments and count the confirmed subscribers matching each of them
+#[tracing::instrument(skip_all)]
+pub async fn list_segments(db_pool: &PgPool) -> anyhow::Result<Vec<SegmentSummary>> {
+    let segments = sqlx::query!(
+        r#"
+        SELECT
+            g.segment_id,
+            g.name,
+            g.tags,
+            g.attributes,
+            g.joined_after,
+            g.joined_before,
+            (
+                SELECT count(*)
+                FROM subscriptions s
+                WHERE
+                    s.status = 'confirmed' AND
+                    subscriber_in_segment(s, g)
+            ) AS "subscriber_count!"
+        FROM segments g
+        ORDER BY g.name
+        "#
+    )
+    .fetch_all(db_pool)
+    .await?
+    .into_iter()
+    .map(|r| SegmentSummary {
+        segment_id: SegmentId::new(r.segment_id),
+        name: r.name,
+        tags: r.tags,
+        attributes: r.attributes,
+        joined_after: r.joined_after,
+        joined_before: r.joined_before,
+        subscriber_count: r.subscriber_count,
+    })
+    .collect();
+
+    Ok(segments)
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{list_segments, segments_form, SegmentSummary};
+pub use post::{segment_exists, segments, SegmentId};
@@ -0,0 +1,153 @@
+use std::fmt;
+use std::ops::Deref;
+
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{NewSegment, SubscriberAttributes, SubscriberTag};
+use crate::utils::{e303_see_other, e500_internal_server_error, escape_html};
+
+/// Web form
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    attributes: String,
+    #[serde(default)]
+    joined_after: String,
+    #[serde(default)]
+    joined_before: String,
+}
+
+impl TryFrom<FormData> for NewSegment {
+    type Error = String;
+
+    fn try_from(value: FormData) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name: Self::parse_name(&value.name)?,
+            tags: SubscriberTag::parse_list(&value.tags)?,
+            attributes: SubscriberAttributes::parse(&value.attributes)?,
+            joined_after: Self::parse_date(&value.joined_after)?,
+            joined_before: Self::parse_date(&value.joined_before)?,
+        })
+    }
+}
+
+/// Segment identifier
+#[derive(Copy, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SegmentId(Uuid);
+
+impl SegmentId {
+    pub const fn new(uuid: Uuid) -> Self {
+        Self(uuid)
+    }
+}
+
+impl fmt::Display for SegmentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for SegmentId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Segments POST handler
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(name = "Create an audience segment", skip_all)]
+pub async fn segments(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Return error in flash message and redirect back to segments page if the definition is invalid
+    let new_segment: NewSegment = match form.0.try_into() {
+        Ok(segment) => segment,
+        Err(e) => {
+            FlashMessage::error(escape_html(&e)).send();
+            return Ok(e303_see_other("/admin/segments"));
+        }
+    };
+
+    // Store the segment definition, unless another segment with the same name already exists
+    let name = escape_html(&new_segment.name);
+    if insert_segment(&new_segment, &db_pool)
+        .await
+        .context("Failed to store segment in the database")
+        .map_err(e500_internal_server_error)?
+        .is_none()
+    {
+        FlashMessage::error(format!("A segment named `{name}` already exists")).send();
+        return Ok(e303_see_other("/admin/segments"));
+    }
+
+    // Redirect back to segments page and display flash message
+    FlashMessage::info(format!("The segment `{name}` has been created")).send();
+    Ok(e303_see_other("/admin/segments"))
+}
+
+/// Store segment definition in the database and return its `segment_id` if it was created
+#[tracing::instrument(skip_all)]
+async fn insert_segment(
+    new_segment: &NewSegment,
+    db_pool: &PgPool,
+) -> sqlx::Result<Option<SegmentId>> {
+    let segment_id = SegmentId::new(Uuid::new_v4());
+    let tags = SubscriberTag::to_strings(&new_segment.tags);
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO segments (
+            segment_id,
+            name,
+            tags,
+            attributes,
+            joined_after,
+            joined_before,
+            created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        *segment_id,
+        new_segment.name,
+        &tags[..],
+        new_segment.attributes.to_json(),
+        new_segment.joined_after,
+        new_segment.joined_before,
+        Utc::now()
+    )
+    .execute(db_pool)
+    .await?
+    .rows_affected();
+
+    Ok((n_inserted_rows > 0).then_some(segment_id))
+}
+
+/// Check if an audience segment exists
+#[tracing::instrument(skip(db_pool))]
+pub async fn segment_exists(db_pool: &PgPool, segment_id: SegmentId) -> sqlx::Result<bool> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM segments
+            WHERE segment_id = $1
+        ) AS "exists!"
+        "#,
+        *segment_id
+    )
+    .fetch_one(db_pool)
+    .await?;
+
+    Ok(row.exists)
+}
@@ -0,0 +1,66 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+use crate::authentication::{list_user_sessions, SessionTimeouts, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::{e500_internal_server_error, escape_html};
+
+/// Sessions GET handler
+#[allow(clippy::future_not_send)]
+pub async fn sessions_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    session_timeouts: web::Data<SessionTimeouts>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> actix_web::Result<HttpResponse> {
+    // Process incoming flash messages
+    let mut msg = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Retrieve the CSRF token to be embedded in forms
+    let csrf_token = session.csrf_token().map_err(e500_internal_server_error)?;
+
+    // Render the sessions of the logged-in user, highlighting the current one
+    let current_session_id = session
+        .get_session_id()
+        .map_err(e500_internal_server_error)?;
+    let sessions = list_user_sessions(user_id.into_inner(), **session_timeouts, &db_pool)
+        .await
+        .map_err(e500_internal_server_error)?;
+    let mut rows = String::new();
+    for s in sessions {
+        let action = if Some(s.session_id) == current_session_id {
+            "This session".to_string()
+        } else {
+            format!(
+                "<form action=\"/admin/sessions/{}/revoke\" method=\"post\"><input hidden type=\"text\" name=\"csrf_token\" value=\"{}\"><button type=\"submit\">Log out</button></form>",
+                s.session_id, csrf_token
+            )
+        };
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(s.user_agent.as_deref().unwrap_or("Unknown device")),
+            escape_html(s.ip.as_deref().unwrap_or("-")),
+            s.created_at.format("%Y-%m-%d %H:%M"),
+            s.last_seen_at.format("%Y-%m-%d %H:%M"),
+            action
+        )
+        .unwrap();
+    }
+
+    // Display sessions page with any flash message
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("sessions_form.html"),
+            msg, rows, csrf_token
+        )))
+}
@@ -0,0 +1,59 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{revoke_other_user_sessions, revoke_user_session, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::{e303_see_other, e500_internal_server_error};
+
+/// Session revocation POST handler
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(
+    name = "Revoke a session",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn session_revocation(
+    session_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> actix_web::Result<HttpResponse> {
+    // Revoke the session, only if it belongs to the logged-in user
+    let revoked = revoke_user_session(user_id.into_inner(), session_id.into_inner(), &db_pool)
+        .await
+        .map_err(e500_internal_server_error)?;
+
+    // Redirect back to sessions page and display flash message
+    if revoked {
+        FlashMessage::info("The session has been logged out").send();
+    } else {
+        FlashMessage::error("The session does not exist").send();
+    }
+    Ok(e303_see_other("/admin/sessions"))
+}
+
+/// Other sessions revocation POST handler, which keeps the current session
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(
+    name = "Revoke other sessions",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn other_sessions_revocation(
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> actix_web::Result<HttpResponse> {
+    // Revoke all sessions of the logged-in user except the current one
+    let current_session_id = session
+        .get_session_id()
+        .map_err(e500_internal_server_error)?;
+    let n_revoked = revoke_other_user_sessions(user_id.into_inner(), current_session_id, &db_pool)
+        .await
+        .map_err(e500_internal_server_error)?;
+
+    // Redirect back to sessions page and display flash message
+    FlashMessage::info(format!("{n_revoked} other session(s) have been logged out")).send();
+    Ok(e303_see_other("/admin/sessions"))
+}
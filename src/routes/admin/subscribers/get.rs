@@ -0,0 +1,123 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::session_state::TypedSession;
+use crate::utils::{e500_internal_server_error, escape_html};
+
+/// Subscribers GET handler
+#[allow(clippy::future_not_send)]
+pub async fn subscribers_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> actix_web::Result<HttpResponse> {
+    // Process incoming flash messages
+    let mut msg = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Retrieve the CSRF token to be embedded in forms
+    let csrf_token = session.csrf_token().map_err(e500_internal_server_error)?;
+
+    // Render the list of subscribers along with their tags and attributes
+    let subscribers = sqlx::query!(
+        r#"
+        SELECT id, email, name, status, subscribed_at, tags, attributes
+        FROM subscriptions
+        ORDER BY subscribed_at
+        "#
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .map_err(e500_internal_server_error)?;
+    let mut rows = String::new();
+    for s in subscribers {
+        writeln!(
+            rows,
+            "<tr><td><a href=\"/admin/subscribers/{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            s.id,
+            escape_html(&s.email),
+            escape_html(&s.name),
+            escape_html(&s.status),
+            s.subscribed_at.format("%Y-%m-%d %H:%M"),
+            escape_html(&s.tags.join(", ")),
+            escape_html(&s.attributes.to_string())
+        )
+        .unwrap();
+    }
+
+    // Display subscribers page with any flash message
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("subscribers_form.html"),
+            msg, rows, csrf_token
+        )))
+}
+
+/// Subscriber details GET handler, which also displays the consent evidence
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Retrieve the subscriber, if it exists
+    let Some(s) = sqlx::query!(
+        r#"
+        SELECT
+            email,
+            name,
+            status,
+            subscribed_at,
+            tags,
+            attributes,
+            consent_version,
+            subscribe_ip,
+            subscribe_user_agent,
+            confirmed_at,
+            confirm_ip,
+            confirm_user_agent
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id.into_inner()
+    )
+    .fetch_optional(db_pool.get_ref())
+    .await
+    .map_err(e500_internal_server_error)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    // Display subscriber details
+    let format_time = |d: Option<DateTime<Utc>>| {
+        d.map_or_else(
+            || "-".to_string(),
+            |d| d.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        )
+    };
+    let format_text = |t: Option<String>| t.map_or_else(|| "-".to_string(), |t| escape_html(&t));
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("subscriber_details.html"),
+            escape_html(&s.email),
+            escape_html(&s.name),
+            escape_html(&s.status),
+            escape_html(&s.tags.join(", ")),
+            escape_html(&s.attributes.to_string()),
+            format_text(s.consent_version),
+            format_time(Some(s.subscribed_at)),
+            format_text(s.subscribe_ip),
+            format_text(s.subscribe_user_agent),
+            format_time(s.confirmed_at),
+            format_text(s.confirm_ip),
+            format_text(s.confirm_user_agent),
+        )))
+}
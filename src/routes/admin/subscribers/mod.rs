@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{subscriber_details, subscribers_form};
+pub use post::subscribers;
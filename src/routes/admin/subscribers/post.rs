@@ -0,0 +1,73 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::domain::{EmailAddress, SubscriberAttributes, SubscriberTag};
+use crate::utils::{e303_see_other, e500_internal_server_error, escape_html};
+
+/// Web form
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    attributes: String,
+}
+
+/// Subscribers POST handler, which replaces the tags and attributes of a subscriber
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(name = "Update subscriber tags and attributes", skip_all)]
+pub async fn subscribers(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Return error in flash message and redirect back to subscribers page if the input is invalid
+    let FormData {
+        email,
+        tags,
+        attributes,
+    } = form.0;
+    let parsed = EmailAddress::parse(email).and_then(|email| {
+        Ok((
+            email,
+            SubscriberTag::parse_list(&tags)?,
+            SubscriberAttributes::parse(&attributes)?,
+        ))
+    });
+    let (email, tags, attributes) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(escape_html(&e)).send();
+            return Ok(e303_see_other("/admin/subscribers"));
+        }
+    };
+
+    // Update the subscriber, if it exists
+    let tags = SubscriberTag::to_strings(&tags);
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET tags = $1, attributes = $2
+        WHERE email = $3
+        "#,
+        &tags[..],
+        attributes.to_json(),
+        email.as_ref()
+    )
+    .execute(db_pool.get_ref())
+    .await
+    .context("Failed to update subscriber tags and attributes")
+    .map_err(e500_internal_server_error)?
+    .rows_affected();
+
+    // Redirect back to subscribers page and display flash message
+    let email = escape_html(email.as_ref());
+    if n_updated_rows == 0 {
+        FlashMessage::error(format!("There is no subscriber with email {email}")).send();
+    } else {
+        FlashMessage::info(format!("The subscriber {email} has been updated")).send();
+    }
+    Ok(e303_see_other("/admin/subscribers"))
+}
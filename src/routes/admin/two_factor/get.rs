@@ -0,0 +1,62 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+use crate::authentication::{is_totp_enabled, start_totp_enrolment, UserId};
+use crate::session_state::TypedSession;
+use crate::utils::{e500_internal_server_error, escape_html, get_username};
+
+/// Two-factor authentication settings GET handler
+#[allow(clippy::future_not_send)]
+pub async fn two_factor_settings_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> actix_web::Result<HttpResponse> {
+    // Process incoming flash messages
+    let mut msg = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Retrieve the CSRF token to be embedded in forms
+    let csrf_token = session.csrf_token().map_err(e500_internal_server_error)?;
+
+    // Offer to disable the second factor if it is already enabled
+    let user_id = user_id.into_inner();
+    if is_totp_enabled(user_id, &db_pool)
+        .await
+        .map_err(e500_internal_server_error)?
+    {
+        return Ok(HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(format!(
+                include_str!("two_factor_disable_form.html"),
+                msg, csrf_token
+            )));
+    }
+
+    // Otherwise, generate a new secret to be added to an authenticator app
+    let username = get_username(user_id, &db_pool)
+        .await
+        .map_err(e500_internal_server_error)?;
+    let enrolment = start_totp_enrolment(user_id, &username, &db_pool)
+        .await
+        .map_err(e500_internal_server_error)?;
+
+    // Display enrolment form with any flash message
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("two_factor_enrolment_form.html"),
+            msg,
+            enrolment.qr_code_png_base64,
+            enrolment.secret,
+            escape_html(&enrolment.otpauth_uri),
+            csrf_token
+        )))
+}
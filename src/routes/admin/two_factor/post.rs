@@ -0,0 +1,81 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::authentication::{confirm_totp_enrolment, disable_totp, verify_second_factor, UserId};
+use crate::utils::{e303_see_other, e500_internal_server_error};
+
+/// Web form
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+/// Two-factor enrolment POST handler, which displays the recovery codes once
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(
+    name = "Enable two-factor authentication",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn two_factor_enrolment(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> actix_web::Result<HttpResponse> {
+    // Return error in flash message and redirect back to the form if the code is invalid
+    let Some(recovery_codes) = confirm_totp_enrolment(user_id.into_inner(), &form.0.code, &db_pool)
+        .await
+        .context("Failed to confirm the TOTP enrolment")
+        .map_err(e500_internal_server_error)?
+    else {
+        FlashMessage::error(
+            "The authentication code is invalid, scan the new QR code and try again",
+        )
+        .send();
+        return Ok(e303_see_other("/admin/2fa"));
+    };
+
+    // Display the recovery codes, which cannot be retrieved afterwards
+    let mut codes = String::new();
+    for code in recovery_codes {
+        writeln!(codes, "<li><code>{code}</code></li>").unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(include_str!("recovery_codes.html"), codes)))
+}
+
+/// Two-factor removal POST handler
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(
+    name = "Disable two-factor authentication",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn two_factor_disable(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> actix_web::Result<HttpResponse> {
+    // Require a valid code, so that a hijacked session cannot remove the second factor
+    let user_id = user_id.into_inner();
+    if !verify_second_factor(user_id, &form.0.code, &db_pool)
+        .await
+        .map_err(e500_internal_server_error)?
+    {
+        FlashMessage::error("The authentication code is invalid").send();
+        return Ok(e303_see_other("/admin/2fa"));
+    }
+
+    // Remove the second factor and redirect back to the form
+    disable_totp(user_id, &db_pool)
+        .await
+        .map_err(e500_internal_server_error)?;
+    FlashMessage::info("Two-factor authentication has been disabled").send();
+    Ok(e303_see_other("/admin/2fa"))
+}
@@ -0,0 +1,75 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+use crate::authentication::Role;
+use crate::session_state::TypedSession;
+use crate::utils::{e500_internal_server_error, escape_html};
+
+/// Users GET handler
+#[allow(clippy::future_not_send)]
+pub async fn users_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> actix_web::Result<HttpResponse> {
+    // Process incoming flash messages
+    let mut msg = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Retrieve the CSRF token to be embedded in forms
+    let csrf_token = session.csrf_token().map_err(e500_internal_server_error)?;
+
+    // Render the list of users along with a form to change their role
+    let users = sqlx::query!(
+        r#"
+        SELECT user_id, username, email, role
+        FROM users
+        ORDER BY username
+        "#
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .map_err(e500_internal_server_error)?;
+    let mut rows = String::new();
+    for u in users {
+        let mut options = String::new();
+        for role in [Role::Viewer, Role::Editor, Role::Owner] {
+            let selected = if role.as_str() == u.role {
+                " selected"
+            } else {
+                ""
+            };
+            write!(
+                options,
+                "<option value=\"{role}\"{selected}>{role}</option>"
+            )
+            .unwrap();
+        }
+        writeln!(
+            rows,
+            "<tr><td>{}</td><td><form action=\"/admin/users\" method=\"post\">\
+            <input type=\"hidden\" name=\"user_id\" value=\"{}\">\
+            <input type=\"hidden\" name=\"csrf_token\" value=\"{}\">\
+            <input type=\"text\" name=\"email\" value=\"{}\" placeholder=\"Email for password resets\">\
+            <select name=\"role\">{}</select> <button type=\"submit\">Save</button>\
+            </form></td></tr>",
+            escape_html(&u.username),
+            u.user_id,
+            csrf_token,
+            escape_html(u.email.as_deref().unwrap_or_default()),
+            options
+        )
+        .unwrap();
+    }
+
+    // Display users page with any flash message
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(include_str!("users_form.html"), msg, rows)))
+}
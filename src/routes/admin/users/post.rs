@@ -0,0 +1,112 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{set_role, Role, UserId};
+use crate::domain::EmailAddress;
+use crate::utils::{e303_see_other, e500_internal_server_error, escape_html, PgTransaction};
+
+/// Web form
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    user_id: Uuid,
+    role: String,
+    #[serde(default)]
+    email: String,
+}
+
+/// Users POST handler, which changes the email address and the role of a user
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(name = "Change the role of a user", skip_all)]
+pub async fn users(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Return error in flash message and redirect back to users page if the role is invalid
+    let role = match Role::parse(&form.role) {
+        Ok(role) => role,
+        Err(e) => {
+            FlashMessage::error(escape_html(&e)).send();
+            return Ok(e303_see_other("/admin/users"));
+        }
+    };
+
+    // Return error in flash message and redirect back to users page if the email address is invalid
+    let email = if form.email.trim().is_empty() {
+        None
+    } else {
+        match EmailAddress::parse(form.email.trim().to_string()) {
+            Ok(email) => Some(email),
+            Err(e) => {
+                FlashMessage::error(escape_html(&e)).send();
+                return Ok(e303_see_other("/admin/users"));
+            }
+        }
+    };
+
+    // Change both the email address and the role, or neither of them
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a database connection to change the user")
+        .map_err(e500_internal_server_error)?;
+
+    // Change the email address, unless another user already has it
+    let user_id = UserId::new(form.user_id);
+    if !set_email(user_id, email.as_ref(), &mut transaction)
+        .await
+        .context("Failed to change the user email address")
+        .map_err(e500_internal_server_error)?
+    {
+        FlashMessage::error("The email address is already used by another user").send();
+        return Ok(e303_see_other("/admin/users"));
+    }
+
+    // Change the role, unless it would leave the application without any owner
+    if !set_role(user_id, role, &mut transaction)
+        .await
+        .map_err(e500_internal_server_error)?
+    {
+        FlashMessage::error("The role cannot be changed, at least one owner is required").send();
+        return Ok(e303_see_other("/admin/users"));
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change the user")
+        .map_err(e500_internal_server_error)?;
+
+    FlashMessage::info(format!("The role has been changed to {role}")).send();
+    Ok(e303_see_other("/admin/users"))
+}
+
+/// Store the email address used to send password reset links, returning `false` if it is taken
+///
+/// Addresses are unique ignoring case, which is enforced by the database so that concurrent changes cannot both
+/// succeed. The transaction cannot be used anymore if the address is taken.
+#[tracing::instrument(skip(transaction, email))]
+async fn set_email(
+    user_id: UserId,
+    email: Option<&EmailAddress>,
+    transaction: &mut PgTransaction,
+) -> sqlx::Result<bool> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE users
+        SET email = $2
+        WHERE user_id = $1
+        "#,
+        *user_id,
+        email.map(AsRef::as_ref)
+    )
+    .execute(&mut **transaction)
+    .await;
+
+    match updated {
+        Ok(_) => Ok(true),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
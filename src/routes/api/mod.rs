@@ -0,0 +1,3 @@
+mod newsletters;
+
+pub use newsletters::{__path_publish_newsletter, publish_newsletter, PublishedIssue};
@@ -0,0 +1,148 @@
+use std::fmt;
+
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{ApiKeyScope, ApiKeyScopes, UserId};
+use crate::idempotency::IdempotentTransaction;
+use crate::problem_details::{Problem, ProblemDetails};
+use crate::routes::{enqueue_delivery_task, insert_newsletter_issue, segment_exists, SegmentId};
+use crate::utils::error_chain_fmt;
+
+/// JSON request body
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = NewsletterIssue)]
+pub struct BodyData {
+    title: String,
+    content_html: String,
+    content_text: String,
+    /// Audience segment to deliver the issue to, defaults to all confirmed subscribers
+    #[serde(default)]
+    segment_id: Option<Uuid>,
+}
+
+/// JSON response body returned once a newsletter issue has been accepted
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct PublishedIssue {
+    pub newsletter_issue_id: Uuid,
+}
+
+/// Newsletter publication error
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The API key is not allowed to publish newsletter issues")]
+    MissingScope,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PublishError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::MissingScope => StatusCode::FORBIDDEN,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        ProblemDetails::new(self.status_code(), self.detail()).into_response()
+    }
+}
+
+impl Problem for PublishError {
+    fn detail(&self) -> String {
+        match self {
+            Self::ValidationError(_) | Self::MissingScope => self.to_string(),
+            Self::UnexpectedError(_) => "Something went wrong".to_string(),
+        }
+    }
+}
+
+/// Newsletters API handler
+#[utoipa::path(
+    post,
+    path = "/api/v1/newsletters",
+    tag = "newsletters",
+    request_body = BodyData,
+    params(
+        ("Idempotency-Key" = String, Header, description = "Client-generated key that makes retries safe")
+    ),
+    responses(
+        (status = 202, description = "The newsletter issue has been accepted, emails will go out shortly", body = PublishedIssue),
+        (status = 400, description = "The newsletter issue or the idempotency key is invalid", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "A valid API key is required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "The API key is not allowed to publish newsletter issues", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "A request with the same idempotency key is still being processed, retry after the delay given in the Retry-After header", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The idempotency key was already used for a different request", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Something went wrong", body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security(("api_key" = []))
+)]
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(
+    name = "Publish a newsletter issue through the API",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn publish_newsletter(
+    body: web::Bytes,
+    db_pool: web::Data<PgPool>,
+    mut transaction: IdempotentTransaction,
+    user_id: web::ReqData<UserId>,
+    scopes: web::ReqData<ApiKeyScopes>,
+) -> Result<HttpResponse, PublishError> {
+    // Check that the API key was granted the permission to publish newsletter issues
+    if !scopes.contains(ApiKeyScope::NewslettersPublish) {
+        return Err(PublishError::MissingScope);
+    }
+
+    // Parse the request body
+    let BodyData {
+        title,
+        content_html,
+        content_text,
+        segment_id,
+    } = serde_json::from_slice(&body).map_err(|e| PublishError::ValidationError(e.to_string()))?;
+    let segment_id = segment_id.map(SegmentId::new);
+    if let Some(segment_id) = segment_id {
+        if !segment_exists(&db_pool, segment_id)
+            .await
+            .context("Failed to look up the audience segment")?
+        {
+            return Err(PublishError::ValidationError(format!(
+                "There is no audience segment with id {segment_id}"
+            )));
+        }
+    }
+
+    // Store newsletter issue in the database and write its emails to the outbox
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &content_html,
+        &content_text,
+        segment_id,
+    )
+    .await
+    .context("Failed to store newsletter issue in the database")?;
+    enqueue_delivery_task(&mut transaction, issue_id, segment_id)
+        .await
+        .context("Failed to enqueue delivery task")?;
+
+    // The idempotency middleware saves the response along with the changes
+    Ok(HttpResponse::Accepted().json(PublishedIssue {
+        newsletter_issue_id: *issue_id,
+    }))
+}
@@ -0,0 +1,8 @@
+use actix_web::HttpResponse;
+
+/// Passkeys script GET handler, shared by the login and admin pages that run `WebAuthn` ceremonies
+pub async fn passkeys_script() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/javascript; charset=utf-8")
+        .body(include_str!("passkeys.js"))
+}
@@ -0,0 +1,87 @@
+// Run WebAuthn ceremonies using the JSON options produced by the server, which encodes binary fields as base64url
+
+const base64UrlToBuffer = (s) => {
+    const base64 = s.replace(/-/g, "+").replace(/_/g, "/").padEnd(Math.ceil(s.length / 4) * 4, "=");
+    return Uint8Array.from(atob(base64), (c) => c.charCodeAt(0)).buffer;
+};
+
+const bufferToBase64Url = (b) =>
+    btoa(String.fromCharCode(...new Uint8Array(b))).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+
+async function postJson(url, body, csrfToken) {
+    const headers = {"Content-Type": "application/json"};
+    if (csrfToken) {
+        headers["X-CSRF-Token"] = csrfToken;
+    }
+    const response = await fetch(url, {
+        method: "POST",
+        headers,
+        body: JSON.stringify(body ?? {}),
+    });
+    const data = await response.json().catch(() => ({}));
+    if (!response.ok) {
+        throw new Error(data.detail || "Something went wrong");
+    }
+    return data;
+}
+
+async function registerPasskey(name, csrfToken) {
+    const options = (await postJson("/admin/passkeys/register/start", {}, csrfToken)).publicKey;
+    options.challenge = base64UrlToBuffer(options.challenge);
+    options.user.id = base64UrlToBuffer(options.user.id);
+    (options.excludeCredentials || []).forEach((c) => c.id = base64UrlToBuffer(c.id));
+
+    const credential = await navigator.credentials.create({publicKey: options});
+    await postJson("/admin/passkeys/register/finish", {
+        name,
+        credential: {
+            id: credential.id,
+            rawId: bufferToBase64Url(credential.rawId),
+            type: credential.type,
+            response: {
+                attestationObject: bufferToBase64Url(credential.response.attestationObject),
+                clientDataJSON: bufferToBase64Url(credential.response.clientDataJSON),
+            },
+            extensions: credential.getClientExtensionResults(),
+        },
+    }, csrfToken);
+}
+
+async function loginWithPasskey(username) {
+    const options = (await postJson("/login/passkey/start", {username})).publicKey;
+    options.challenge = base64UrlToBuffer(options.challenge);
+    (options.allowCredentials || []).forEach((c) => c.id = base64UrlToBuffer(c.id));
+
+    const credential = await navigator.credentials.get({publicKey: options});
+    const userHandle = credential.response.userHandle;
+    return postJson("/login/passkey/finish", {
+        id: credential.id,
+        rawId: bufferToBase64Url(credential.rawId),
+        type: credential.type,
+        response: {
+            authenticatorData: bufferToBase64Url(credential.response.authenticatorData),
+            clientDataJSON: bufferToBase64Url(credential.response.clientDataJSON),
+            signature: bufferToBase64Url(credential.response.signature),
+            userHandle: userHandle ? bufferToBase64Url(userHandle) : null,
+        },
+        extensions: credential.getClientExtensionResults(),
+    });
+}
+
+// Attach the ceremonies to the forms of the current page, displaying any error next to them
+const showError = (e) => document.getElementById("passkey-error").textContent = e.message;
+
+document.getElementById("passkey-login-form")?.addEventListener("submit", (event) => {
+    event.preventDefault();
+    loginWithPasskey(event.target.elements.username.value)
+        .then((result) => window.location.assign(result.redirect_to))
+        .catch(showError);
+});
+
+document.getElementById("passkey-register-form")?.addEventListener("submit", (event) => {
+    event.preventDefault();
+    const {name, csrf_token} = event.target.elements;
+    registerPasskey(name.value, csrf_token.value)
+        .then(() => window.location.reload())
+        .catch(showError);
+});
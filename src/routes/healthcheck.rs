@@ -1,6 +1,12 @@
 use actix_web::HttpResponse;
 
 /// Health check handler
+#[utoipa::path(
+    get,
+    path = "/healthcheck",
+    tag = "health",
+    responses((status = 200, description = "The application is up and running"))
+)]
 pub async fn healthcheck() -> HttpResponse {
     HttpResponse::Ok().finish()
 }
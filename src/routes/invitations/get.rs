@@ -0,0 +1,49 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use sqlx::PgPool;
+
+use crate::authentication::get_pending_invitation;
+use crate::utils::{e303_see_other, e500_internal_server_error, escape_html};
+
+/// Web query parameters
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    #[serde(default)]
+    invitation_token: String,
+}
+
+/// Invitation acceptance GET handler
+pub async fn accept_invitation_form(
+    parameters: web::Query<Parameters>,
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Return error in flash message and redirect to login if the invitation is not pending anymore
+    let token = parameters.0.invitation_token;
+    let Some(invitation) = get_pending_invitation(&db_pool, &token)
+        .await
+        .map_err(e500_internal_server_error)?
+    else {
+        FlashMessage::error("The invitation link is invalid or has expired").send();
+        return Ok(e303_see_other("/login"));
+    };
+
+    // Process incoming flash messages
+    let mut msg = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Display invitation acceptance form with any flash message
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("accept_invitation_form.html"),
+            msg,
+            escape_html(&invitation.email),
+            escape_html(&token)
+        )))
+}
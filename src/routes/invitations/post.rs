@@ -0,0 +1,73 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::SecretString;
+use sqlx::PgPool;
+
+use crate::authentication::{accept_invitation as accept, validate_new_password, InvitationError};
+use crate::utils::{e303_see_other, e500_internal_server_error};
+
+/// Maximum length of a username
+const MAX_USERNAME_LEN: usize = 64;
+
+/// Web form
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    invitation_token: String,
+    username: String,
+    password: SecretString,
+    password_check: SecretString,
+}
+
+/// Invitation acceptance POST handler
+#[tracing::instrument(
+    name = "Accept an invitation",
+    skip_all,
+    fields(username=%form.username)
+)]
+pub async fn accept_invitation(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let query = serde_urlencoded::to_string([("invitation_token", &form.invitation_token)])
+        .map_err(e500_internal_server_error)?;
+    let form_location = format!("/invitations/accept?{query}");
+
+    // Return error in flash message and redirect back to the form if the username is invalid
+    let username = form.username.trim();
+    if username.is_empty() || username.chars().count() > MAX_USERNAME_LEN {
+        FlashMessage::error(format!(
+            "The username must be between 1 and {MAX_USERNAME_LEN} characters long"
+        ))
+        .send();
+        return Ok(e303_see_other(&form_location));
+    }
+
+    // Return error in flash message and redirect back to the form if the password is invalid
+    if let Err(e) = validate_new_password(&form.password, &form.password_check) {
+        FlashMessage::error(e).send();
+        return Ok(e303_see_other(&form_location));
+    }
+
+    // Create the user and consume the invitation, then redirect to login and display flash message
+    let username = username.to_string();
+    let FormData {
+        invitation_token,
+        password,
+        ..
+    } = form.0;
+    match accept(&db_pool, &invitation_token, &username, password).await {
+        Ok(_) => {
+            FlashMessage::info("Your account has been created, you can now log in").send();
+            Ok(e303_see_other("/login"))
+        }
+        Err(InvitationError::InvalidToken) => {
+            FlashMessage::error(InvitationError::InvalidToken.to_string()).send();
+            Ok(e303_see_other("/login"))
+        }
+        Err(InvitationError::UsernameTaken) => {
+            FlashMessage::error(InvitationError::UsernameTaken.to_string()).send();
+            Ok(e303_see_other(&form_location))
+        }
+        Err(e) => Err(e500_internal_server_error(e)),
+    }
+}
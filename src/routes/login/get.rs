@@ -4,6 +4,9 @@ use actix_web::http::header::ContentType;
 use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
 
+use crate::session_state::TypedSession;
+use crate::utils::{e303_see_other, e500_internal_server_error};
+
 /// Login GET handler
 pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
     // Process incoming flash messages
@@ -17,3 +20,30 @@ pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
         .content_type(ContentType::html())
         .body(format!(include_str!("login_form.html"), msg))
 }
+
+/// Second authentication factor GET handler
+#[allow(clippy::future_not_send)]
+pub async fn two_factor_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+) -> actix_web::Result<HttpResponse> {
+    // Redirect to login if the user has not passed the first authentication factor
+    if session
+        .get_pending_user_id()
+        .map_err(e500_internal_server_error)?
+        .is_none()
+    {
+        return Ok(e303_see_other("/login"));
+    }
+
+    // Process incoming flash messages
+    let mut msg = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Display second factor form with any flash message
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(include_str!("two_factor_form.html"), msg)))
+}
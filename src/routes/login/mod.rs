@@ -1,5 +1,7 @@
 mod get;
+mod passkey;
 mod post;
 
-pub use get::login_form;
-pub use post::login;
+pub use get::{login_form, two_factor_form};
+pub use passkey::{passkey_login_finish, passkey_login_start};
+pub use post::{login, two_factor};
@@ -0,0 +1,76 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use webauthn_rs::prelude::PublicKeyCredential;
+use webauthn_rs::Webauthn;
+
+use crate::authentication::{
+    get_passkeys_by_username, record_passkey_use, start_user_session, PasskeyError,
+};
+use crate::client_info::ClientInfo;
+use crate::session_state::TypedSession;
+
+/// Passkey login request body
+#[derive(serde::Deserialize)]
+pub struct StartData {
+    username: String,
+}
+
+/// Passkey login response body
+#[derive(serde::Serialize)]
+struct LoggedIn {
+    redirect_to: &'static str,
+}
+
+/// Passkey login POST handler, which starts the authentication ceremony
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(skip_all, fields(username=%body.username))]
+pub async fn passkey_login_start(
+    body: web::Json<StartData>,
+    db_pool: web::Data<PgPool>,
+    webauthn: web::Data<Webauthn>,
+    session: TypedSession,
+) -> Result<HttpResponse, PasskeyError> {
+    // Return the same error for unknown users and users without passkeys
+    let (user_id, passkeys) = get_passkeys_by_username(&body.username, &db_pool)
+        .await?
+        .filter(|(_, passkeys)| !passkeys.is_empty())
+        .ok_or(PasskeyError::Unavailable)?;
+
+    // Challenge the browser to sign with one of the user's passkeys
+    let (challenge, state) = webauthn
+        .start_passkey_authentication(&passkeys)
+        .context("Failed to start the passkey authentication")?;
+    session
+        .insert_passkey_authentication(user_id, &state)
+        .context("Failed to store the passkey authentication state")?;
+    Ok(HttpResponse::Ok().json(challenge))
+}
+
+/// Passkey login POST handler, which completes the authentication ceremony and starts a session
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(skip_all, fields(user_id=tracing::field::Empty))]
+pub async fn passkey_login_finish(
+    credential: web::Json<PublicKeyCredential>,
+    db_pool: web::Data<PgPool>,
+    webauthn: web::Data<Webauthn>,
+    client: ClientInfo,
+    session: TypedSession,
+) -> Result<HttpResponse, PasskeyError> {
+    // Verify the signature against the challenge stored in the session
+    let (user_id, state) = session
+        .take_passkey_authentication()
+        .context("Failed to retrieve the passkey authentication state")?
+        .ok_or(PasskeyError::MissingCeremony)?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    let auth_result = webauthn
+        .finish_passkey_authentication(&credential, &state)
+        .map_err(PasskeyError::InvalidCredential)?;
+    record_passkey_use(user_id, &auth_result, &db_pool).await?;
+
+    // A passkey already proves possession of a device, so it replaces both the password and the TOTP code
+    start_user_session(&session, user_id, &client, &db_pool).await?;
+    Ok(HttpResponse::Ok().json(LoggedIn {
+        redirect_to: "/admin/dashboard",
+    }))
+}
@@ -6,9 +6,13 @@ use actix_web_flash_messages::FlashMessage;
 use secrecy::SecretString;
 use sqlx::PgPool;
 
-use crate::authentication::{validate_creds, AuthError, Credentials};
+use crate::authentication::{
+    is_totp_enabled, start_user_session, validate_creds, verify_second_factor, AuthError,
+    Credentials, LoginAttempt, LoginThrottle,
+};
+use crate::client_info::ClientInfo;
 use crate::session_state::TypedSession;
-use crate::utils::{e303_see_other, error_chain_fmt};
+use crate::utils::{e303_see_other, error_chain_fmt, get_username};
 
 /// Web form
 #[derive(serde::Deserialize)]
@@ -17,11 +21,19 @@ pub struct FormData {
     password: SecretString,
 }
 
+/// Web form for the second authentication factor
+#[derive(serde::Deserialize)]
+pub struct TwoFactorFormData {
+    code: String,
+}
+
 /// Login error
 #[derive(thiserror::Error)]
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("Too many failed login attempts, please try again later")]
+    TooManyAttempts,
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -41,32 +53,55 @@ impl fmt::Debug for LoginError {
 pub async fn login(
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
+    login_throttle: web::Data<LoginThrottle>,
+    client: ClientInfo,
     session: TypedSession,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
     // Extract authentication credentials from form data
+    let username = form.0.username;
     let creds = Credentials {
-        username: form.0.username,
+        username: username.clone(),
         password: form.0.password,
     };
+    tracing::Span::current().record("username", tracing::field::display(&username));
+
+    // Slow down or reject the attempt, without checking the password, after repeated failures
+    throttle_login_attempt(&login_throttle, &username, client.ip.as_deref()).await?;
 
     // Validate authentication credentials
-    tracing::Span::current().record("username", tracing::field::display(&creds.username));
     match validate_creds(creds, &db_pool).await {
         // Valid credentials: start a session and redirect to dashboard
         Ok(user_id) => {
             tracing::Span::current().record("user_id", tracing::field::display(&user_id));
-            // Prevent session fixation attacks
-            session.renew();
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| redirect_to_login_with_error(LoginError::UnexpectedError(e.into())))?;
+
+            // Ask for the second factor before starting the session, if the user enabled it
+            if is_totp_enabled(user_id, &db_pool)
+                .await
+                .map_err(|e| redirect_to_login_with_error(LoginError::UnexpectedError(e.into())))?
+            {
+                session.renew();
+                session.insert_pending_user_id(user_id).map_err(|e| {
+                    redirect_to_login_with_error(LoginError::UnexpectedError(e.into()))
+                })?;
+                return Ok(e303_see_other("/login/two-factor"));
+            }
+
+            start_user_session(&session, user_id, &client, &db_pool)
+                .await
+                .map_err(|e| redirect_to_login_with_error(LoginError::UnexpectedError(e)))?;
+            login_throttle.record_success(&username).await;
             Ok(e303_see_other("/admin/dashboard"))
         }
 
-        // Invalid credentials: return error in flash message and redirect to login
+        // Invalid credentials: count the failure, return error in flash message and redirect to login
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    login_throttle
+                        .record_failure(&username, client.ip.as_deref())
+                        .await;
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
             Err(redirect_to_login_with_error(e))
@@ -74,6 +109,72 @@ pub async fn login(
     }
 }
 
+/// Second authentication factor POST handler
+#[allow(clippy::future_not_send)]
+#[tracing::instrument(skip_all, fields(user_id=tracing::field::Empty))]
+pub async fn two_factor(
+    form: web::Form<TwoFactorFormData>,
+    db_pool: web::Data<PgPool>,
+    login_throttle: web::Data<LoginThrottle>,
+    client: ClientInfo,
+    session: TypedSession,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    // Redirect to login if the user has not passed the first authentication factor
+    let Some(user_id) = session
+        .get_pending_user_id()
+        .map_err(|e| redirect_to_login_with_error(LoginError::UnexpectedError(e.into())))?
+    else {
+        return Ok(e303_see_other("/login"));
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    // Codes are short, so guessing them is throttled like guessing passwords
+    let username = get_username(user_id, &db_pool)
+        .await
+        .map_err(|e| redirect_to_login_with_error(LoginError::UnexpectedError(e)))?;
+    throttle_login_attempt(&login_throttle, &username, client.ip.as_deref()).await?;
+
+    // Return error in flash message and redirect back to the form if the code is invalid
+    if !verify_second_factor(user_id, &form.0.code, &db_pool)
+        .await
+        .map_err(|e| redirect_to_login_with_error(LoginError::UnexpectedError(e)))?
+    {
+        login_throttle
+            .record_failure(&username, client.ip.as_deref())
+            .await;
+        let e = LoginError::AuthError(anyhow::anyhow!("Invalid second factor code"));
+        FlashMessage::error(e.to_string()).send();
+        return Err(InternalError::from_response(
+            e,
+            e303_see_other("/login/two-factor"),
+        ));
+    }
+
+    // Valid code: start a session and redirect to dashboard
+    start_user_session(&session, user_id, &client, &db_pool)
+        .await
+        .map_err(|e| redirect_to_login_with_error(LoginError::UnexpectedError(e)))?;
+    login_throttle.record_success(&username).await;
+    Ok(e303_see_other("/admin/dashboard"))
+}
+
+/// Wait for the delay imposed by previous failures, or reject the attempt if the user or client is locked out
+///
+/// The same message is displayed whether the username exists or not, since failures are counted for any username.
+async fn throttle_login_attempt(
+    login_throttle: &LoginThrottle,
+    username: &str,
+    ip: Option<&str>,
+) -> Result<(), InternalError<LoginError>> {
+    match login_throttle.check(username, ip).await {
+        LoginAttempt::Allowed(delay) => {
+            tokio::time::sleep(delay).await;
+            Ok(())
+        }
+        LoginAttempt::LockedOut => Err(redirect_to_login_with_error(LoginError::TooManyAttempts)),
+    }
+}
+
 /// Redirect to the login page with an error message
 fn redirect_to_login_with_error(e: LoginError) -> InternalError<LoginError> {
     FlashMessage::error(e.to_string()).send();
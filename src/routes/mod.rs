@@ -1,11 +1,19 @@
 mod admin;
+mod api;
+mod assets;
 mod healthcheck;
 mod home;
+mod invitations;
 mod login;
+mod password_reset;
 mod subscriptions;
 
 pub use admin::*;
+pub use api::*;
+pub use assets::*;
 pub use healthcheck::*;
 pub use home::*;
+pub use invitations::*;
 pub use login::*;
+pub use password_reset::*;
 pub use subscriptions::*;
@@ -0,0 +1,62 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use sqlx::PgPool;
+
+use crate::authentication::is_password_reset_pending;
+use crate::utils::{e303_see_other, e500_internal_server_error, escape_html};
+
+/// Web query parameters
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    #[serde(default)]
+    reset_token: String,
+}
+
+/// Password reset request GET handler
+pub async fn password_reset_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+    // Process incoming flash messages
+    let mut msg = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Display password reset request form with any flash message
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(include_str!("password_reset_form.html"), msg))
+}
+
+/// New password GET handler, reached through the link sent by email
+pub async fn password_reset_confirm_form(
+    parameters: web::Query<Parameters>,
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Return error in flash message and redirect to login if the reset link cannot be used anymore
+    let token = parameters.0.reset_token;
+    if !is_password_reset_pending(&db_pool, &token)
+        .await
+        .map_err(e500_internal_server_error)?
+    {
+        FlashMessage::error("The password reset link is invalid or has expired").send();
+        return Ok(e303_see_other("/login"));
+    }
+
+    // Process incoming flash messages
+    let mut msg = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Display new password form with any flash message
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            include_str!("password_reset_confirm_form.html"),
+            msg,
+            escape_html(&token)
+        )))
+}
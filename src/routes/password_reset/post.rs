@@ -0,0 +1,137 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::SecretString;
+use sqlx::PgPool;
+
+use crate::authentication::{
+    complete_password_reset, create_password_reset, validate_new_password, PasswordResetError,
+    PASSWORD_RESET_TTL_MINUTES,
+};
+use crate::domain::EmailAddress;
+use crate::outbox::{enqueue_email, EmailKind, OutboxEmail};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e303_see_other, e500_internal_server_error, PgTransaction};
+
+/// Web form to request a password reset
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+/// Web form to choose a new password
+#[derive(serde::Deserialize)]
+pub struct ConfirmFormData {
+    reset_token: String,
+    new_password: SecretString,
+    new_password2: SecretString,
+}
+
+/// Password reset request POST handler
+#[tracing::instrument(name = "Request a password reset", skip_all)]
+pub async fn password_reset(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> actix_web::Result<HttpResponse> {
+    // Send the reset link if a user has this email address, without disclosing whether it exists
+    if let Ok(email) = EmailAddress::parse(form.0.email) {
+        let mut transaction = db_pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")
+            .map_err(e500_internal_server_error)?;
+        let token = create_password_reset(&mut transaction, &email)
+            .await
+            .context("Failed to store password reset token in the database")
+            .map_err(e500_internal_server_error)?;
+        if let Some(token) = token {
+            enqueue_password_reset_email(&mut transaction, &email, &base_url.0, &token)
+                .await
+                .context("Failed to enqueue the password reset email")
+                .map_err(e500_internal_server_error)?;
+        }
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to store the password reset token")
+            .map_err(e500_internal_server_error)?;
+    }
+
+    // Redirect to login and display flash message
+    FlashMessage::info(
+        "If an account is associated with this email address, a password reset link has been sent to it",
+    )
+    .send();
+    Ok(e303_see_other("/login"))
+}
+
+/// New password POST handler
+#[tracing::instrument(name = "Reset a password", skip_all)]
+pub async fn password_reset_confirm(
+    form: web::Form<ConfirmFormData>,
+    db_pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Return error in flash message and redirect back to the form if the new password is invalid
+    if let Err(e) = validate_new_password(&form.new_password, &form.new_password2) {
+        let query = serde_urlencoded::to_string([("reset_token", &form.reset_token)])
+            .map_err(e500_internal_server_error)?;
+        FlashMessage::error(e).send();
+        return Ok(e303_see_other(&format!("/password-reset/confirm?{query}")));
+    }
+
+    // Change the password, logging the user out of all their sessions, and redirect to login
+    let ConfirmFormData {
+        reset_token,
+        new_password,
+        ..
+    } = form.0;
+    match complete_password_reset(&db_pool, &reset_token, new_password).await {
+        Ok(_) => {
+            FlashMessage::info("Your password has been reset, you can now log in").send();
+            Ok(e303_see_other("/login"))
+        }
+        Err(PasswordResetError::InvalidToken) => {
+            FlashMessage::error(PasswordResetError::InvalidToken.to_string()).send();
+            Ok(e303_see_other("/login"))
+        }
+        Err(e) => Err(e500_internal_server_error(e)),
+    }
+}
+
+/// Write an email containing the single-use password reset link to the outbox
+#[tracing::instrument(
+    name = "Enqueue a password reset email",
+    skip(transaction, email, token)
+)]
+async fn enqueue_password_reset_email(
+    transaction: &mut PgTransaction,
+    email: &EmailAddress,
+    base_url: &str,
+    token: &str,
+) -> anyhow::Result<()> {
+    let reset_link = format!("{base_url}/password-reset/confirm?reset_token={token}");
+    let html_body = &format!(
+        "Somebody asked to reset the password of your account.<br />\
+        Click <a href=\"{reset_link}\">here</a> to choose a new password. \
+        The link expires in {PASSWORD_RESET_TTL_MINUTES} minutes. \
+        If you did not ask for it, you can safely ignore this email."
+    );
+    let text_body = &format!(
+        "Somebody asked to reset the password of your account.\n\
+        Visit {reset_link} to choose a new password. \
+        The link expires in {PASSWORD_RESET_TTL_MINUTES} minutes. \
+        If you did not ask for it, you can safely ignore this email."
+    );
+
+    let email = OutboxEmail {
+        kind: EmailKind::PasswordReset,
+        recipient: email,
+        subject: "Reset your password",
+        html_body,
+        text_body,
+        single_use_token: Some(token),
+    };
+    enqueue_email(transaction, &email).await?;
+    Ok(())
+}
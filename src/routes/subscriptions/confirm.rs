@@ -1,22 +1,28 @@
 use std::fmt;
 
 use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
 use sqlx::PgPool;
 
-use crate::routes::SubscriberId;
+use crate::client_info::ClientInfo;
+use crate::problem_details::{wants_json, Negotiated, Problem, ProblemDetails};
+use crate::routes::{SubscriberId, SubscriptionStatus};
 use crate::utils::error_chain_fmt;
 
 /// Web query parameters
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Parameters {
-    subscription_token: String,
+    /// Token embedded in the confirmation link sent by email
+    subscription_token: Option<String>,
 }
 
 /// Subscription confirmation error
 #[derive(thiserror::Error)]
 pub enum ConfirmError {
+    #[error("The subscription token is missing")]
+    MissingToken,
     #[error("There is no subscriber associated with the provided token")]
     UnknownToken,
     #[error(transparent)]
@@ -32,31 +38,80 @@ impl fmt::Debug for ConfirmError {
 impl ResponseError for ConfirmError {
     fn status_code(&self) -> StatusCode {
         match self {
+            Self::MissingToken => StatusCode::BAD_REQUEST,
             Self::UnknownToken => StatusCode::UNAUTHORIZED,
             Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
+impl Problem for ConfirmError {
+    fn detail(&self) -> String {
+        match self {
+            Self::MissingToken | Self::UnknownToken => self.to_string(),
+            Self::UnexpectedError(_) => "Something went wrong".to_string(),
+        }
+    }
+}
+
 /// Subscription confirmation handler
 /// TODO: What happens if a user clicks on a confirmation link twice?
+#[utoipa::path(
+    get,
+    path = "/subscriptions/confirm",
+    tag = "subscriptions",
+    params(Parameters),
+    responses(
+        (status = 200, description = "The subscription has been confirmed", body = SubscriptionStatus),
+        (status = 400, description = "The subscription token is missing", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "There is no subscriber associated with the provided token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Something went wrong", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+#[allow(clippy::future_not_send)]
 #[tracing::instrument(name = "Confirm a pending subscriber", skip_all)]
 pub async fn confirm(
+    req: HttpRequest,
     parameters: web::Query<Parameters>,
     db_pool: web::Data<PgPool>,
-) -> Result<HttpResponse, ConfirmError> {
+    client: ClientInfo,
+) -> Result<HttpResponse, Negotiated<ConfirmError>> {
+    // Negotiate the response format based on the request headers
+    let json = wants_json(&req);
+    confirm_subscription(parameters.0, &db_pool, &client)
+        .await
+        .map_err(|e| Negotiated::new(e, json))?;
+
+    if json {
+        Ok(HttpResponse::Ok().json(SubscriptionStatus {
+            status: "confirmed".to_string(),
+        }))
+    } else {
+        Ok(HttpResponse::Ok().finish())
+    }
+}
+
+/// Confirm the subscription associated with the provided token
+async fn confirm_subscription(
+    parameters: Parameters,
+    db_pool: &PgPool,
+    client: &ClientInfo,
+) -> Result<(), ConfirmError> {
     // Get `subscriber_id` from subscription token
-    let subscriber_id = get_subscriber_id_from_token(&parameters.subscription_token, &db_pool)
+    let subscription_token = parameters
+        .subscription_token
+        .ok_or(ConfirmError::MissingToken)?;
+    let subscriber_id = get_subscriber_id_from_token(&subscription_token, db_pool)
         .await
         .context("Failed to retrieve the subscriber id associated with the provided token")?
         .ok_or(ConfirmError::UnknownToken)?;
 
-    // Confirm subscriber if token is valid
-    confirm_subscriber(subscriber_id, &db_pool)
+    // Confirm subscriber if token is valid, recording the consent evidence
+    confirm_subscriber(subscriber_id, client, db_pool)
         .await
         .context("Failed to update subscriber status to `confirmed`")?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(())
 }
 
 /// Get `subscriber_id` from subscription token
@@ -80,16 +135,28 @@ pub async fn get_subscriber_id_from_token(
     Ok(result.map(|r| SubscriberId::new(r.subscriber_id)))
 }
 
-/// Mark subscriber as confirmed
+/// Mark a pending subscriber as confirmed, keeping the evidence of the first confirmation
 #[tracing::instrument(name = "Marking subscriber as confirmed", skip_all)]
-pub async fn confirm_subscriber(subscriber_id: SubscriberId, db_pool: &PgPool) -> sqlx::Result<()> {
+pub async fn confirm_subscriber(
+    subscriber_id: SubscriberId,
+    client: &ClientInfo,
+    db_pool: &PgPool,
+) -> sqlx::Result<()> {
     sqlx::query!(
         r#"
         UPDATE subscriptions
-        SET status = 'confirmed'
-        WHERE id = $1
+        SET
+            status = 'confirmed',
+            confirmed_at = now(),
+            confirm_ip = $2,
+            confirm_user_agent = $3
+        WHERE
+            id = $1 AND
+            status = 'pending_confirmation'
         "#,
-        *subscriber_id
+        *subscriber_id,
+        client.ip,
+        client.user_agent
     )
     .execute(db_pool)
     .await?;
@@ -1,5 +1,5 @@
 mod confirm;
 mod post;
 
-pub use confirm::confirm;
-pub use post::{subscriptions, SubscriberId};
+pub use confirm::{__path_confirm, confirm};
+pub use post::{__path_subscriptions, subscriptions, SubscriberId, SubscriptionStatus};
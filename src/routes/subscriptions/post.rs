@@ -2,7 +2,7 @@ use std::ops::Deref;
 use std::{error, fmt, iter};
 
 use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
 use chrono::Utc;
 use rand::distributions::Alphanumeric;
@@ -10,16 +10,38 @@ use rand::{thread_rng, Rng};
 use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::domain::{EmailAddress, NewSubscriber, SubscriberName};
-use crate::email_client::EmailClient;
+use crate::bot_protection::{BotCheck, BotProtection};
+use crate::client_info::ClientInfo;
+use crate::domain::{ConsentVersion, EmailAddress, NewSubscriber, SubscriberName};
+use crate::outbox::{enqueue_email, EmailKind, OutboxEmail};
+use crate::problem_details::{wants_json, Negotiated, Problem, ProblemDetails};
 use crate::startup::ApplicationBaseUrl;
 use crate::utils::error_chain_fmt;
 
-/// Web form
-#[derive(serde::Deserialize)]
+/// Web form, also accepted as a JSON document
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[schema(as = NewSubscriberForm)]
 pub struct FormData {
+    #[schema(example = "ursula_le_guin@gmail.com")]
     email: String,
+    #[schema(example = "Ursula Le Guin")]
     name: String,
+    /// Version of the privacy notice the subscriber agreed to, defaults to the current one
+    #[serde(default)]
+    consent_version: Option<String>,
+    /// Honeypot field hidden from humans, which must be left empty
+    #[serde(default)]
+    website: Option<String>,
+    /// Response token of the hCaptcha or Turnstile challenge, required if challenge verification is enabled
+    #[serde(default, alias = "h-captcha-response", alias = "cf-turnstile-response")]
+    challenge_response: Option<String>,
+}
+
+/// JSON response body describing the status of a subscription
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct SubscriptionStatus {
+    #[schema(example = "pending_confirmation")]
+    pub status: String,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -28,8 +50,13 @@ impl TryFrom<FormData> for NewSubscriber {
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
         let email = EmailAddress::parse(value.email)?;
         let name = SubscriberName::parse(value.name)?;
+        let consent_version = ConsentVersion::parse(value.consent_version)?;
 
-        Ok(Self { email, name })
+        Ok(Self {
+            email,
+            name,
+            consent_version,
+        })
     }
 }
 
@@ -38,6 +65,10 @@ impl TryFrom<FormData> for NewSubscriber {
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("The challenge could not be verified, please try again")]
+    ChallengeFailed,
+    #[error("Unsupported content type `{0}`, expected `application/x-www-form-urlencoded` or `application/json`")]
+    UnsupportedMediaType(String),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -51,12 +82,23 @@ impl fmt::Debug for SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::ValidationError(_) | Self::ChallengeFailed => StatusCode::BAD_REQUEST,
+            Self::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
             Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
+impl Problem for SubscribeError {
+    fn detail(&self) -> String {
+        match self {
+            Self::ValidationError(e) => e.clone(),
+            Self::ChallengeFailed | Self::UnsupportedMediaType(_) => self.to_string(),
+            Self::UnexpectedError(_) => "Something went wrong".to_string(),
+        }
+    }
+}
+
 /// Subscriber identifier
 #[derive(Copy, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct SubscriberId(Uuid);
@@ -81,23 +123,92 @@ impl Deref for SubscriberId {
     }
 }
 
-/// Subscriptions handler
+/// Subscriptions handler, which accepts either a urlencoded form or a JSON document
+#[utoipa::path(
+    post,
+    path = "/subscriptions",
+    tag = "subscriptions",
+    request_body(content(
+        (FormData = "application/x-www-form-urlencoded"),
+        (FormData = "application/json")
+    )),
+    responses(
+        (status = 200, description = "The subscriber has been added and a confirmation email will be sent shortly", body = SubscriptionStatus),
+        (status = 400, description = "The subscriber data is missing or invalid, or the challenge failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 415, description = "The request body is neither a urlencoded form nor a JSON document", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Something went wrong", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+#[allow(clippy::future_not_send)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
     skip_all,
     fields(
-        subscriber_email = %form.email,
-        subscriber_name= %form.name
+        subscriber_email = tracing::field::Empty,
+        subscriber_name = tracing::field::Empty
     )
 )]
 pub async fn subscriptions(
-    form: web::Form<FormData>,
+    req: HttpRequest,
+    body: web::Bytes,
     db_pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
-) -> Result<HttpResponse, SubscribeError> {
-    // Parse form data to extract subscriber information
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    bot_protection: web::Data<BotProtection>,
+    client: ClientInfo,
+) -> Result<HttpResponse, Negotiated<SubscribeError>> {
+    // Negotiate the response format based on the request headers, describing unsupported formats with problem details
+    // since the client cannot be answered in its own format
+    let json = wants_json(&req);
+    subscribe(&req, &body, &db_pool, &base_url.0, &bot_protection, &client)
+        .await
+        .map_err(|e| {
+            let json = json || matches!(e, SubscribeError::UnsupportedMediaType(_));
+            Negotiated::new(e, json)
+        })?;
+
+    if json {
+        Ok(HttpResponse::Ok().json(SubscriptionStatus {
+            status: "pending_confirmation".to_string(),
+        }))
+    } else {
+        Ok(HttpResponse::Ok().finish())
+    }
+}
+
+/// Add a new subscriber and enqueue their confirmation email
+#[allow(clippy::future_not_send)]
+async fn subscribe(
+    req: &HttpRequest,
+    body: &[u8],
+    db_pool: &PgPool,
+    base_url: &str,
+    bot_protection: &BotProtection,
+    client: &ClientInfo,
+) -> Result<(), SubscribeError> {
+    // Parse request body to extract subscriber information
+    let form = parse_body(req, body)?;
+    tracing::Span::current()
+        .record("subscriber_email", tracing::field::display(&form.email))
+        .record("subscriber_name", tracing::field::display(&form.name));
+
+    // Reject bots before storing anything, pretending to succeed if they fell for the honeypot
+    let bot_check = bot_protection
+        .check(
+            form.website.as_deref(),
+            form.challenge_response.as_deref(),
+            client.ip.as_deref(),
+        )
+        .await
+        .context("Failed to verify the challenge response")?;
+    match bot_check {
+        BotCheck::Passed => {}
+        BotCheck::HoneypotFilled => {
+            tracing::warn!("Ignoring a subscription with a filled honeypot field");
+            return Ok(());
+        }
+        BotCheck::ChallengeFailed => return Err(SubscribeError::ChallengeFailed),
+    }
+    let new_subscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
 
     // Begin database transaction
     let mut transaction = db_pool
@@ -105,8 +216,8 @@ pub async fn subscriptions(
         .await
         .context("Failed to acquire database connection to store a new subscriber")?;
 
-    // Insert new subscriber
-    let subscriber_id = insert_subscriber(&new_subscriber, &mut transaction)
+    // Insert new subscriber along with the consent evidence
+    let subscriber_id = insert_subscriber(&new_subscriber, client, &mut transaction)
         .await
         .context("Failed to insert new subscriber in the database")?;
 
@@ -116,23 +227,37 @@ pub async fn subscriptions(
         .await
         .context("Failed to store confirmation token in the database")?;
 
+    // Write the confirmation email to the outbox, to be sent with retries by the background worker
+    enqueue_confirmation_email(
+        &mut transaction,
+        &new_subscriber,
+        base_url,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to enqueue the confirmation email")?;
+
     // End database transaction
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber")?;
 
-    // Send confirmation email with subscription token
-    send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &base_url.0,
-        &subscription_token,
-    )
-    .await
-    .context("Failed to send confirmation email")?;
+    Ok(())
+}
 
-    Ok(HttpResponse::Ok().finish())
+/// Parse the request body as JSON or as a urlencoded form, depending on its content type without parameters
+fn parse_body(req: &HttpRequest, body: &[u8]) -> Result<FormData, SubscribeError> {
+    match req.content_type() {
+        "application/json" => {
+            serde_json::from_slice(body).map_err(|e| SubscribeError::ValidationError(e.to_string()))
+        }
+        "application/x-www-form-urlencoded" => serde_urlencoded::from_bytes(body)
+            .map_err(|e| SubscribeError::ValidationError(e.to_string())),
+        content_type => Err(SubscribeError::UnsupportedMediaType(
+            content_type.to_string(),
+        )),
+    }
 }
 
 /// Insert a subscriber into the database and return its `subscriber_id`
@@ -140,18 +265,31 @@ pub async fn subscriptions(
 #[tracing::instrument(name = "Saving new subscriber details in the database", skip_all)]
 pub async fn insert_subscriber(
     new_subscriber: &NewSubscriber,
+    client: &ClientInfo,
     transaction: &mut Transaction<'_, Postgres>,
 ) -> sqlx::Result<SubscriberId> {
     let subscriber_id = SubscriberId::new(Uuid::new_v4());
     let query = sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (
+            id,
+            email,
+            name,
+            subscribed_at,
+            status,
+            consent_version,
+            subscribe_ip,
+            subscribe_user_agent
+        )
+        VALUES ($1, $2, $3, $4, 'pending_confirmation', $5, $6, $7)
         "#,
         *subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        new_subscriber.consent_version.as_ref(),
+        client.ip,
+        client.user_agent
     );
     transaction.execute(query).await?;
 
@@ -214,14 +352,14 @@ pub async fn store_token(
     Ok(())
 }
 
-/// Send confirmation email to a new subscriber
-#[tracing::instrument(name = "Sending confirmation email to new subscriber", skip_all)]
-pub async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
+/// Write the confirmation email of a new subscriber to the outbox
+#[tracing::instrument(name = "Enqueueing confirmation email to new subscriber", skip_all)]
+pub async fn enqueue_confirmation_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> reqwest::Result<()> {
+) -> anyhow::Result<()> {
     let confirmation_link =
         format!("{base_url}/subscriptions/confirm?subscription_token={subscription_token}");
     let html_body = &format!(
@@ -232,7 +370,14 @@ pub async fn send_confirmation_email(
         "Welcome to our newsletter!\nVisit {confirmation_link} to confirm your subscription."
     );
 
-    email_client
-        .send_email(&new_subscriber.email, "Welcome!", html_body, text_body)
-        .await
+    let email = OutboxEmail {
+        kind: EmailKind::Confirmation,
+        recipient: &new_subscriber.email,
+        subject: "Welcome!",
+        html_body,
+        text_body,
+        single_use_token: Some(subscription_token),
+    };
+    enqueue_email(transaction, &email).await?;
+    Ok(())
 }
@@ -3,8 +3,12 @@ use std::future::{ready, Ready};
 use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::dev::Payload;
 use actix_web::{FromRequest, HttpRequest};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use webauthn_rs::prelude::{PasskeyAuthentication, PasskeyRegistration};
 
-use crate::authentication::UserId;
+use crate::authentication::{generate_csrf_token, UserId};
 
 /// Session type
 pub struct TypedSession(Session);
@@ -20,6 +24,14 @@ impl FromRequest for TypedSession {
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_EPOCH_KEY: &'static str = "session_epoch";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    const LOGGED_IN_AT_KEY: &'static str = "logged_in_at";
+    const LAST_ACTIVITY_KEY: &'static str = "last_activity";
+    const PENDING_USER_ID_KEY: &'static str = "pending_user_id";
+    const PASSKEY_REGISTRATION_KEY: &'static str = "passkey_registration";
+    const PASSKEY_AUTHENTICATION_KEY: &'static str = "passkey_authentication";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
 
     /// Renew the session key
     pub fn renew(&self) {
@@ -36,6 +48,113 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    /// Insert the user's session epoch into session, to detect sessions that were invalidated later on
+    pub fn insert_session_epoch(&self, session_epoch: i32) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_EPOCH_KEY, session_epoch)
+    }
+
+    /// Get the user's session epoch from session
+    pub fn get_session_epoch(&self) -> Result<Option<i32>, SessionGetError> {
+        self.0.get(Self::SESSION_EPOCH_KEY)
+    }
+
+    /// Get the identifier of the session in the user's session index
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    /// Get the time at which the user logged in, to enforce the maximum session age
+    pub fn get_logged_in_at(&self) -> Result<Option<DateTime<Utc>>, SessionGetError> {
+        self.0.get(Self::LOGGED_IN_AT_KEY)
+    }
+
+    /// Get the time of the last request of the user, to enforce the idle timeout
+    pub fn get_last_activity(&self) -> Result<Option<DateTime<Utc>>, SessionGetError> {
+        self.0.get(Self::LAST_ACTIVITY_KEY)
+    }
+
+    /// Record the time of the last request of the user
+    pub fn insert_last_activity(&self, at: DateTime<Utc>) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::LAST_ACTIVITY_KEY, at)
+    }
+
+    /// Insert the `user_id` of a user who still has to pass the second authentication factor
+    pub fn insert_pending_user_id(&self, user_id: UserId) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_USER_ID_KEY, user_id)
+    }
+
+    /// Get the `user_id` of a user who still has to pass the second authentication factor
+    pub fn get_pending_user_id(&self) -> Result<Option<UserId>, SessionGetError> {
+        self.0.get(Self::PENDING_USER_ID_KEY)
+    }
+
+    /// Insert the state of an ongoing passkey registration ceremony
+    pub fn insert_passkey_registration(
+        &self,
+        state: &PasskeyRegistration,
+    ) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PASSKEY_REGISTRATION_KEY, state)
+    }
+
+    /// Remove and return the state of an ongoing passkey registration ceremony, so that it cannot be replayed
+    pub fn take_passkey_registration(
+        &self,
+    ) -> Result<Option<PasskeyRegistration>, SessionGetError> {
+        let state = self.0.get(Self::PASSKEY_REGISTRATION_KEY)?;
+        self.0.remove(Self::PASSKEY_REGISTRATION_KEY);
+        Ok(state)
+    }
+
+    /// Insert the state of an ongoing passkey authentication ceremony, along with the user being authenticated
+    pub fn insert_passkey_authentication(
+        &self,
+        user_id: UserId,
+        state: &PasskeyAuthentication,
+    ) -> Result<(), SessionInsertError> {
+        self.0
+            .insert(Self::PASSKEY_AUTHENTICATION_KEY, (user_id, state))
+    }
+
+    /// Remove and return the state of an ongoing passkey authentication ceremony, so that it cannot be replayed
+    pub fn take_passkey_authentication(
+        &self,
+    ) -> Result<Option<(UserId, PasskeyAuthentication)>, SessionGetError> {
+        let state = self.0.get(Self::PASSKEY_AUTHENTICATION_KEY)?;
+        self.0.remove(Self::PASSKEY_AUTHENTICATION_KEY);
+        Ok(state)
+    }
+
+    /// Get the CSRF token of the session, if one was issued
+    pub fn get_csrf_token(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+
+    /// Get the CSRF token to be embedded in forms, which is issued when logging in
+    ///
+    /// The token is never issued while rendering a page, so that concurrent page loads all embed the same token.
+    pub fn csrf_token(&self) -> anyhow::Result<String> {
+        self.get_csrf_token()?
+            .context("No CSRF token was issued for the session")
+    }
+
+    /// Start an authenticated session, renewing the session key and the CSRF token to prevent session fixation attacks
+    pub fn log_in(
+        &self,
+        user_id: UserId,
+        session_epoch: i32,
+        session_id: Uuid,
+    ) -> Result<(), SessionInsertError> {
+        self.0.renew();
+        self.0.remove(Self::PENDING_USER_ID_KEY);
+        self.0.insert(Self::CSRF_TOKEN_KEY, generate_csrf_token())?;
+        self.insert_user_id(user_id)?;
+        self.insert_session_epoch(session_epoch)?;
+        self.0.insert(Self::SESSION_ID_KEY, session_id)?;
+        let now = Utc::now();
+        self.0.insert(Self::LOGGED_IN_AT_KEY, now)?;
+        self.insert_last_activity(now)
+    }
+
     /// Purge session data to logout
     pub fn logout(self) {
         self.0.purge();
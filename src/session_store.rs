@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::iter;
+
+use actix_session::storage::{
+    CookieSessionStore, LoadError, RedisSessionStore, SaveError, SessionKey, SessionStore,
+    UpdateError,
+};
+use actix_web::cookie::time::Duration;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+
+use crate::configuration::SessionStoreBackend;
+
+/// Session state, as handled by session stores
+type SessionState = HashMap<String, String>;
+
+/// Generate a pseudo-random session key
+fn generate_session_key() -> SessionKey {
+    let mut rng = thread_rng();
+    let value: String = iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(64)
+        .collect();
+    value
+        .try_into()
+        .expect("Generated session keys are within the allowed size")
+}
+
+/// Get the expiration time of a session state
+fn expires_at(ttl: &Duration) -> DateTime<Utc> {
+    Utc::now() + chrono::Duration::seconds(ttl.whole_seconds())
+}
+
+/// Session store backed by the `sessions` table of the database
+#[derive(Clone)]
+pub struct PostgresSessionStore {
+    db_pool: PgPool,
+}
+
+impl PostgresSessionStore {
+    pub const fn new(db_pool: PgPool) -> Self {
+        Self { db_pool }
+    }
+}
+
+/// Delete the expired session states of the database session store and return how many were deleted
+#[tracing::instrument(skip(db_pool))]
+pub async fn delete_expired_session_states(db_pool: &PgPool) -> sqlx::Result<u64> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM sessions
+        WHERE expires_at < now()
+        "#
+    )
+    .execute(db_pool)
+    .await?
+    .rows_affected();
+
+    tracing::info!("Deleted {n_deleted_rows} expired session states");
+    Ok(n_deleted_rows)
+}
+
+impl SessionStore for PostgresSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let saved_state = sqlx::query!(
+            r#"
+            SELECT state
+            FROM sessions
+            WHERE
+                session_key = $1 AND
+                expires_at > now()
+            "#,
+            session_key.as_ref()
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+        .context("Failed to load the session state")
+        .map_err(LoadError::Other)?;
+
+        saved_state
+            .map(|r| serde_json::from_value(r.state))
+            .transpose()
+            .context("Failed to deserialize the session state")
+            .map_err(LoadError::Deserialization)
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let state = serde_json::to_value(session_state)
+            .context("Failed to serialize the session state")
+            .map_err(SaveError::Serialization)?;
+        let session_key = generate_session_key();
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (session_key, state, expires_at)
+            VALUES ($1, $2, $3)
+            "#,
+            session_key.as_ref(),
+            state,
+            expires_at(ttl)
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to save the session state")
+        .map_err(SaveError::Other)?;
+
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let state = serde_json::to_value(&session_state)
+            .context("Failed to serialize the session state")
+            .map_err(UpdateError::Serialization)?;
+        let n_updated_rows = sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET
+                state = $2,
+                expires_at = $3
+            WHERE session_key = $1
+            "#,
+            session_key.as_ref(),
+            state,
+            expires_at(ttl)
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to update the session state")
+        .map_err(UpdateError::Other)?
+        .rows_affected();
+
+        // The state may have been deleted in the meantime, in which case it is saved under a new key
+        if n_updated_rows > 0 {
+            return Ok(session_key);
+        }
+        self.save(session_state, ttl).await.map_err(|e| match e {
+            SaveError::Serialization(e) => UpdateError::Serialization(e),
+            SaveError::Other(e) => UpdateError::Other(e),
+        })
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET expires_at = $2
+            WHERE session_key = $1
+            "#,
+            session_key.as_ref(),
+            expires_at(ttl)
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to update the session expiration")?;
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM sessions
+            WHERE session_key = $1
+            "#,
+            session_key.as_ref()
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to delete the session state")?;
+        Ok(())
+    }
+}
+
+/// Session store selected in the settings
+///
+/// Cookie sessions keep their whole state in the signed session cookie, so they need neither Redis nor
+/// the database, but they are limited to 4 KB and cannot be destroyed server-side before they expire.
+/// Revocation of admin sessions still works, because it relies on the session index in the database.
+pub enum AppSessionStore {
+    Redis(Box<RedisSessionStore>),
+    Postgres(PostgresSessionStore),
+    Cookie(CookieSessionStore),
+}
+
+impl AppSessionStore {
+    /// Build the session store of the selected backend, connecting to Redis if needed
+    pub async fn new(
+        backend: SessionStoreBackend,
+        redis_uri: Option<&SecretString>,
+        db_pool: &PgPool,
+    ) -> anyhow::Result<Self> {
+        Ok(match backend {
+            SessionStoreBackend::Redis => {
+                let redis_uri =
+                    redis_uri.context("The Redis session store requires a Redis URI")?;
+                Self::Redis(Box::new(
+                    RedisSessionStore::new(redis_uri.expose_secret()).await?,
+                ))
+            }
+            SessionStoreBackend::Postgres => {
+                Self::Postgres(PostgresSessionStore::new(db_pool.clone()))
+            }
+            SessionStoreBackend::Cookie => Self::Cookie(CookieSessionStore::default()),
+        })
+    }
+}
+
+impl Clone for AppSessionStore {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Redis(store) => Self::Redis(store.clone()),
+            Self::Postgres(store) => Self::Postgres(store.clone()),
+            Self::Cookie(_) => Self::Cookie(CookieSessionStore::default()),
+        }
+    }
+}
+
+impl SessionStore for AppSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        match self {
+            Self::Redis(store) => store.load(session_key).await,
+            Self::Postgres(store) => store.load(session_key).await,
+            Self::Cookie(store) => store.load(session_key).await,
+        }
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        match self {
+            Self::Redis(store) => store.save(session_state, ttl).await,
+            Self::Postgres(store) => store.save(session_state, ttl).await,
+            Self::Cookie(store) => store.save(session_state, ttl).await,
+        }
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        match self {
+            Self::Redis(store) => store.update(session_key, session_state, ttl).await,
+            Self::Postgres(store) => store.update(session_key, session_state, ttl).await,
+            Self::Cookie(store) => store.update(session_key, session_state, ttl).await,
+        }
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> anyhow::Result<()> {
+        match self {
+            Self::Redis(store) => store.update_ttl(session_key, ttl).await,
+            Self::Postgres(store) => store.update_ttl(session_key, ttl).await,
+            Self::Cookie(store) => store.update_ttl(session_key, ttl).await,
+        }
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        match self {
+            Self::Redis(store) => store.delete(session_key).await,
+            Self::Postgres(store) => store.delete(session_key).await,
+            Self::Cookie(store) => store.delete(session_key).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_session_keys_are_random() {
+        let session_key = generate_session_key();
+        assert_eq!(session_key.as_ref().len(), 64);
+        assert_ne!(session_key.as_ref(), generate_session_key().as_ref());
+    }
+}
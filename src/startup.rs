@@ -1,25 +1,48 @@
 use std::{io, net, time};
 
-use actix_session::storage::RedisSessionStore;
+use actix_session::config::BrowserSession;
 use actix_session::SessionMiddleware;
-use actix_web::cookie::Key;
+use actix_web::cookie::{self, Key};
 use actix_web::dev::Server;
+use actix_web::http::Method;
 use actix_web::middleware::from_fn;
-use actix_web::{web, App, HttpServer};
+use actix_web::{web, App, HttpServer, Route};
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
 use secrecy::{ExposeSecret, SecretString};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use tracing_actix_web::TracingLogger;
+use webauthn_rs::Webauthn;
 
-use crate::authentication::reject_logged_out_users;
-use crate::configuration::Settings;
+use crate::authentication::{
+    reject_forged_requests, reject_invalid_api_keys, reject_logged_out_users,
+    reject_unauthorized_users, LoginThrottle, Role, SessionTimeouts,
+};
+use crate::bot_protection::BotProtection;
+use crate::client_info::TrustedProxies;
+use crate::configuration::{
+    BotChallengeSettings, IdempotencySettings, LoginThrottleSettings, RateLimitSettings,
+    SessionStoreBackend, Settings,
+};
+use crate::counter_store::connect_redis;
 use crate::email_client::EmailClient;
+use crate::idempotency::idempotency;
+use crate::openapi::{openapi_docs, openapi_json};
+use crate::rate_limit::{rate_limit, RateLimiter};
 use crate::routes::{
-    confirm, dashboard, healthcheck, home, login, login_form, logout, newsletters,
-    newsletters_form, password, password_form, subscriptions,
+    accept_invitation, accept_invitation_form, api_key_revocation, api_keys, api_keys_form,
+    confirm, dashboard, data_erasure, data_export, data_requests_form, healthcheck, home,
+    invitations, invitations_form, login, login_form, logout, newsletters, newsletters_form,
+    other_sessions_revocation, passkey_deletion, passkey_login_finish, passkey_login_start,
+    passkey_registration_finish, passkey_registration_start, passkeys_form, passkeys_script,
+    password, password_form, password_reset, password_reset_confirm, password_reset_confirm_form,
+    password_reset_form, publish_newsletter, replay_success_message, segments, segments_form,
+    session_revocation, sessions_form, subscriber_details, subscribers, subscribers_form,
+    subscriptions, two_factor, two_factor_disable, two_factor_enrolment, two_factor_form,
+    two_factor_settings_form, users, users_form,
 };
+use crate::session_store::AppSessionStore;
 
 /// Application base URL
 pub struct ApplicationBaseUrl(pub String);
@@ -47,19 +70,36 @@ impl Application {
         // Build the email client
         let email_client = config.email_client.client();
 
+        // Build the WebAuthn relying party used by passkeys
+        let webauthn = config.webauthn.webauthn()?;
+
+        // Build the bot protection of public forms, verifying challenges only if a server is configured
+        let bot_protection =
+            BotProtection::new(config.bot_challenge.map(BotChallengeSettings::verifier));
+
         // Run the HTTP server and return its data
         let listener = net::TcpListener::bind(format!(
             "{}:{}",
             config.application.app_host, config.application.app_port
         ))?;
         let port = listener.local_addr()?.port();
+        let session_timeouts = config.application.session_timeouts();
+        let trusted_proxies = config.application.trusted_proxies();
         let server = run_server(
             listener,
             db_pool.clone(),
             email_client,
             config.application.base_url,
             config.application.signing_key,
+            session_timeouts,
+            trusted_proxies,
+            config.session_store,
             config.redis_uri,
+            webauthn,
+            config.login_throttle,
+            config.rate_limit,
+            config.idempotency,
+            bot_protection,
         )
         .await?;
         Ok(Self { server, port })
@@ -77,13 +117,22 @@ impl Application {
 }
 
 /// Run the HTTP server
+#[allow(clippy::too_many_arguments)]
 pub async fn run_server(
     listener: net::TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
     base_url: String,
     signing_key: SecretString,
-    redis_uri: SecretString,
+    session_timeouts: SessionTimeouts,
+    trusted_proxies: TrustedProxies,
+    session_store: SessionStoreBackend,
+    redis_uri: Option<SecretString>,
+    webauthn: Webauthn,
+    login_throttle: LoginThrottleSettings,
+    rate_limit_settings: RateLimitSettings,
+    idempotency_settings: IdempotencySettings,
+    bot_protection: BotProtection,
 ) -> anyhow::Result<Server> {
     // Extract secret key from HMAC secret
     let signing_key = Key::from(signing_key.expose_secret().as_bytes());
@@ -92,43 +141,296 @@ pub async fn run_server(
     let message_store = CookieMessageStore::builder(signing_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
 
-    // Set up Redis session store
-    let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    // Share Redis with the counters below only when it is the selected session store, so that other
+    // deployments do not need it
+    let shared_redis_uri = match session_store {
+        SessionStoreBackend::Redis => redis_uri.as_ref(),
+        SessionStoreBackend::Postgres | SessionStoreBackend::Cookie => None,
+    };
+
+    // Set up the selected session store, where session state expires along with the session
+    let session_store = AppSessionStore::new(session_store, redis_uri.as_ref(), &db_pool).await?;
+    let session_ttl = cookie::time::Duration::seconds(
+        i64::try_from(session_timeouts.max_age.as_secs()).unwrap_or(i64::MAX),
+    );
+
+    // Set up the counters of failed login attempts and the rate limiter of public endpoints, sharing one connection
+    let redis = connect_redis(shared_redis_uri).await;
+    let login_throttle = LoginThrottle::new(redis.clone(), login_throttle);
+    let rate_limiter = RateLimiter::new(redis, rate_limit_settings);
 
     // Prepare data to be added the application context
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let session_timeouts = web::Data::new(session_timeouts);
+    let trusted_proxies = web::Data::new(trusted_proxies);
+    let webauthn = web::Data::new(webauthn);
+    let login_throttle = web::Data::new(login_throttle);
+    let rate_limiter = web::Data::new(rate_limiter);
+    let idempotency_settings = web::Data::new(idempotency_settings);
+    let bot_protection = web::Data::new(bot_protection);
 
     // Start the HTTP server
     Ok(HttpServer::new(move || {
         App::new()
+            .wrap(from_fn(rate_limit))
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(
-                redis_store.clone(),
-                signing_key.clone(),
-            ))
+            .wrap(
+                SessionMiddleware::builder(session_store.clone(), signing_key.clone())
+                    .session_lifecycle(BrowserSession::default().state_ttl(session_ttl))
+                    .build(),
+            )
             .wrap(TracingLogger::default())
-            .route("/", web::get().to(home))
-            .route("/login", web::get().to(login_form))
-            .route("/login", web::post().to(login))
-            .route("/healthcheck", web::get().to(healthcheck))
-            .route("/subscriptions", web::post().to(subscriptions))
-            .route("/subscriptions/confirm", web::get().to(confirm))
+            .configure(register_routes(PUBLIC_ROUTES))
             .service(
-                web::scope("/admin")
+                web::scope(ADMIN_SCOPE)
+                    .wrap(from_fn(reject_forged_requests))
                     .wrap(from_fn(reject_logged_out_users))
-                    .route("/dashboard", web::get().to(dashboard))
-                    .route("/newsletters", web::get().to(newsletters_form))
-                    .route("/newsletters", web::post().to(newsletters))
-                    .route("/password", web::get().to(password_form))
-                    .route("/password", web::post().to(password))
-                    .route("/logout", web::post().to(logout)),
+                    .configure(register_routes(ADMIN_ROUTES)),
+            )
+            .service(
+                web::scope(API_SCOPE)
+                    .wrap(from_fn(reject_invalid_api_keys))
+                    .configure(register_routes(API_ROUTES)),
             )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(session_timeouts.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(webauthn.clone())
+            .app_data(login_throttle.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(idempotency_settings.clone())
+            .app_data(bot_protection.clone())
     })
     .listen(listener)?
     .run())
 }
+
+/// Route of the application, matching requests by method and path within its scope
+pub struct AppRoute {
+    pub method: Method,
+    pub path: &'static str,
+    /// Role that logged-in users need to access the route, for routes of the admin dashboard
+    pub required_role: Option<Role>,
+    handler: fn(Route) -> Route,
+}
+
+/// Build a route matching GET requests
+const fn get(path: &'static str, handler: fn(Route) -> Route) -> AppRoute {
+    AppRoute {
+        method: Method::GET,
+        path,
+        required_role: None,
+        handler,
+    }
+}
+
+/// Build a route matching POST requests
+const fn post(path: &'static str, handler: fn(Route) -> Route) -> AppRoute {
+    AppRoute {
+        method: Method::POST,
+        path,
+        required_role: None,
+        handler,
+    }
+}
+
+/// Build a route matching GET requests of logged-in users with at least the given role
+const fn admin_get(role: Role, path: &'static str, handler: fn(Route) -> Route) -> AppRoute {
+    AppRoute {
+        method: Method::GET,
+        path,
+        required_role: Some(role),
+        handler,
+    }
+}
+
+/// Build a route matching POST requests of logged-in users with at least the given role
+const fn admin_post(role: Role, path: &'static str, handler: fn(Route) -> Route) -> AppRoute {
+    AppRoute {
+        method: Method::POST,
+        path,
+        required_role: Some(role),
+        handler,
+    }
+}
+
+/// Scope of the admin dashboard, restricted to logged-in users with the role required by each route
+const ADMIN_SCOPE: &str = "/admin";
+
+/// Scope of the API, restricted to requests carrying a valid API key
+const API_SCOPE: &str = "/api/v1";
+
+/// Routes outside of any scope
+const PUBLIC_ROUTES: &[AppRoute] = &[
+    get("/", |r| r.to(home)),
+    get("/login", |r| r.to(login_form)),
+    post("/login", |r| r.to(login)),
+    get("/login/two-factor", |r| r.to(two_factor_form)),
+    post("/login/two-factor", |r| r.to(two_factor)),
+    post("/login/passkey/start", |r| r.to(passkey_login_start)),
+    post("/login/passkey/finish", |r| r.to(passkey_login_finish)),
+    get("/assets/passkeys.js", |r| r.to(passkeys_script)),
+    get("/password-reset", |r| r.to(password_reset_form)),
+    post("/password-reset", |r| r.to(password_reset)),
+    get("/password-reset/confirm", |r| {
+        r.to(password_reset_confirm_form)
+    }),
+    post("/password-reset/confirm", |r| r.to(password_reset_confirm)),
+    get("/healthcheck", |r| r.to(healthcheck)),
+    get("/invitations/accept", |r| r.to(accept_invitation_form)),
+    post("/invitations/accept", |r| r.to(accept_invitation)),
+    post("/subscriptions", |r| r.to(subscriptions)),
+    get("/subscriptions/confirm", |r| r.to(confirm)),
+    get("/api/openapi.json", |r| r.to(openapi_json)),
+    get("/api/docs", |r| r.to(openapi_docs)),
+];
+
+/// Routes of the admin dashboard scope
+const ADMIN_ROUTES: &[AppRoute] = &[
+    admin_get(Role::Viewer, "/dashboard", |r| r.to(dashboard)),
+    admin_get(Role::Viewer, "/newsletters", |r| r.to(newsletters_form)),
+    admin_post(Role::Editor, "/newsletters", |r| {
+        r.to(newsletters)
+            .wrap(from_fn(idempotency))
+            .wrap(from_fn(replay_success_message))
+    }),
+    admin_get(Role::Viewer, "/subscribers", |r| r.to(subscribers_form)),
+    admin_post(Role::Editor, "/subscribers", |r| r.to(subscribers)),
+    admin_get(Role::Viewer, "/subscribers/{subscriber_id}", |r| {
+        r.to(subscriber_details)
+    }),
+    admin_get(Role::Viewer, "/segments", |r| r.to(segments_form)),
+    admin_post(Role::Editor, "/segments", |r| r.to(segments)),
+    admin_get(Role::Owner, "/data-requests", |r| r.to(data_requests_form)),
+    admin_post(Role::Owner, "/data-requests/export", |r| r.to(data_export)),
+    admin_post(Role::Owner, "/data-requests/erase", |r| r.to(data_erasure)),
+    admin_get(Role::Owner, "/api-keys", |r| r.to(api_keys_form)),
+    admin_post(Role::Owner, "/api-keys", |r| r.to(api_keys)),
+    admin_post(Role::Owner, "/api-keys/{api_key_id}/revoke", |r| {
+        r.to(api_key_revocation)
+    }),
+    admin_get(Role::Owner, "/users", |r| r.to(users_form)),
+    admin_post(Role::Owner, "/users", |r| r.to(users)),
+    admin_get(Role::Owner, "/invitations", |r| r.to(invitations_form)),
+    admin_post(Role::Owner, "/invitations", |r| r.to(invitations)),
+    admin_get(Role::Viewer, "/2fa", |r| r.to(two_factor_settings_form)),
+    admin_post(Role::Viewer, "/2fa", |r| r.to(two_factor_enrolment)),
+    admin_post(Role::Viewer, "/2fa/disable", |r| r.to(two_factor_disable)),
+    admin_get(Role::Viewer, "/passkeys", |r| r.to(passkeys_form)),
+    admin_post(Role::Viewer, "/passkeys/register/start", |r| {
+        r.to(passkey_registration_start)
+    }),
+    admin_post(Role::Viewer, "/passkeys/register/finish", |r| {
+        r.to(passkey_registration_finish)
+    }),
+    admin_post(Role::Viewer, "/passkeys/{passkey_id}/delete", |r| {
+        r.to(passkey_deletion)
+    }),
+    admin_get(Role::Viewer, "/sessions", |r| r.to(sessions_form)),
+    admin_post(Role::Viewer, "/sessions/revoke-others", |r| {
+        r.to(other_sessions_revocation)
+    }),
+    admin_post(Role::Viewer, "/sessions/{session_id}/revoke", |r| {
+        r.to(session_revocation)
+    }),
+    admin_get(Role::Viewer, "/password", |r| r.to(password_form)),
+    admin_post(Role::Viewer, "/password", |r| r.to(password)),
+    admin_post(Role::Viewer, "/logout", |r| r.to(logout)),
+];
+
+/// Routes of the API scope
+const API_ROUTES: &[AppRoute] = &[post("/newsletters", |r| {
+    r.to(publish_newsletter).wrap(from_fn(idempotency))
+})];
+
+/// Get the method and full path of every route of the application
+pub fn app_routes() -> impl Iterator<Item = (Method, String)> {
+    [
+        ("", PUBLIC_ROUTES),
+        (ADMIN_SCOPE, ADMIN_ROUTES),
+        (API_SCOPE, API_ROUTES),
+    ]
+    .into_iter()
+    .flat_map(|(scope, routes)| {
+        routes
+            .iter()
+            .map(move |r| (r.method.clone(), format!("{scope}{}", r.path)))
+    })
+}
+
+/// Register routes on the application or on one of its scopes, rejecting users without the required role
+fn register_routes(routes: &'static [AppRoute]) -> impl FnOnce(&mut web::ServiceConfig) {
+    move |cfg| {
+        for route in routes {
+            let mut handler = (route.handler)(web::route().method(route.method.clone()));
+            if let Some(required_role) = route.required_role {
+                handler = handler.wrap(from_fn(move |req, next| {
+                    reject_unauthorized_users(required_role, req, next)
+                }));
+            }
+            cfg.route(route.path, handler);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get the role required by an admin route
+    fn required_role(method: &Method, path: &str) -> Option<Role> {
+        ADMIN_ROUTES
+            .iter()
+            .find(|r| r.method == method && r.path == path)
+            .and_then(|r| r.required_role)
+    }
+
+    #[test]
+    fn every_admin_route_requires_a_role() {
+        for route in ADMIN_ROUTES {
+            assert!(
+                route.required_role.is_some(),
+                "{} {}{} does not require any role",
+                route.method,
+                ADMIN_SCOPE,
+                route.path
+            );
+        }
+    }
+
+    #[test]
+    fn viewers_can_browse_admin_pages_and_manage_their_own_account() {
+        for (method, path) in [
+            (Method::GET, "/subscribers"),
+            (Method::POST, "/logout"),
+            (Method::POST, "/2fa/disable"),
+            (Method::POST, "/passkeys/register/start"),
+            (Method::POST, "/sessions/revoke-others"),
+        ] {
+            assert_eq!(required_role(&method, path), Some(Role::Viewer));
+        }
+    }
+
+    #[test]
+    fn changing_content_requires_an_editor() {
+        for path in ["/newsletters", "/segments", "/subscribers"] {
+            assert_eq!(required_role(&Method::POST, path), Some(Role::Editor));
+        }
+    }
+
+    #[test]
+    fn managing_users_and_personal_data_requires_an_owner() {
+        for (method, path) in [
+            (Method::GET, "/invitations"),
+            (Method::POST, "/api-keys/{api_key_id}/revoke"),
+            (Method::POST, "/data-requests/export"),
+            (Method::GET, "/users"),
+        ] {
+            assert_eq!(required_role(&method, path), Some(Role::Owner));
+        }
+    }
+}
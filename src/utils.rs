@@ -1,7 +1,8 @@
 use std::{error, fmt};
 
+use actix_web::dev::Payload;
 use actix_web::http::header::LOCATION;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use anyhow::Context;
 use sqlx::{PgPool, Postgres, Transaction};
 
@@ -46,6 +47,29 @@ pub fn e303_see_other(location: &str) -> HttpResponse {
         .finish()
 }
 
+/// Build a request payload from a body that was already extracted, so that it can be put back for the handler
+pub fn bytes_to_payload(body: web::Bytes) -> Payload {
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body);
+    Payload::from(payload)
+}
+
+/// Escape a string so that it can be safely embedded in HTML
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 /// Retrieve the username that matches a `user_id` from the database
 #[tracing::instrument(name = "Get Username", skip(db_pool))]
 pub async fn get_username(user_id: UserId, db_pool: &PgPool) -> anyhow::Result<String> {
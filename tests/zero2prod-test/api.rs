@@ -0,0 +1,342 @@
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use wiremock::ResponseTemplate;
+
+use zero2prod::idempotency::IdempotencyKey;
+
+use crate::helpers::{assert_is_redirect_to, when_sending_an_email, TestApp};
+
+/// Newsletter issue used across the API tests
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content_text": "Newsletter body as plain text",
+        "content_html": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+#[sqlx::test]
+async fn requests_without_a_valid_api_key_are_rejected(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    // No API key at all
+    let response = app
+        .api_client
+        .post(format!("{}/api/v1/newsletters", &app.address))
+        .json(&newsletter_body())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+
+    // Unknown API key
+    let response = app
+        .post_api_newsletters(
+            "z2p_not-a-real-key",
+            IdempotencyKey::generate().as_ref(),
+            &newsletter_body(),
+        )
+        .await;
+    assert_eq!(response.status(), 401);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn newsletters_published_through_the_api_are_delivered(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.create_confirmed_subscriber().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key("newsletters:publish").await;
+    app.post_logout().await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Publish the newsletter twice with the same idempotency key
+    let idempotency_key = IdempotencyKey::generate().to_string();
+    let response = app
+        .post_api_newsletters(&api_key, &idempotency_key, &newsletter_body())
+        .await;
+    assert_eq!(response.status(), 202);
+    let first: serde_json::Value = response.json().await.unwrap();
+    let response = app
+        .post_api_newsletters(&api_key, &idempotency_key, &newsletter_body())
+        .await;
+    assert_eq!(response.status(), 202);
+    let second: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(first["newsletter_issue_id"], second["newsletter_issue_id"]);
+
+    // Consume all enqueued tasks, expecting a single email
+    app.dispatch_all_pending_emails().await;
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn requests_still_being_processed_are_rejected_with_a_409(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn_with(&db_pool, |c| {
+        c.idempotency.wait_timeout_millis = 100;
+    })
+    .await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key("newsletters:publish").await;
+    app.post_logout().await;
+
+    // Simulate an in-flight request by holding the lock on its idempotency key
+    let idempotency_key = IdempotencyKey::generate();
+    let mut transaction = db_pool.begin().await.unwrap();
+    sqlx::query!(
+        "INSERT INTO idempotency (user_id, idempotency_key, created_at) VALUES ($1, $2, now())",
+        *app.test_user.user_id,
+        idempotency_key.as_ref()
+    )
+    .execute(&mut *transaction)
+    .await
+    .unwrap();
+
+    // Publish the newsletter with the same key
+    let response = app
+        .post_api_newsletters(&api_key, idempotency_key.as_ref(), &newsletter_body())
+        .await;
+    assert_eq!(response.status(), 409);
+    assert_eq!(response.headers()["Retry-After"], "1");
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+
+    // Publish it again once the in-flight request has been aborted
+    transaction.rollback().await.unwrap();
+    let response = app
+        .post_api_newsletters(&api_key, idempotency_key.as_ref(), &newsletter_body())
+        .await;
+    assert_eq!(response.status(), 202);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn reusing_an_idempotency_key_for_a_different_request_is_rejected(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key("newsletters:publish").await;
+    app.post_logout().await;
+
+    // Publish the newsletter
+    let idempotency_key = IdempotencyKey::generate().to_string();
+    let response = app
+        .post_api_newsletters(&api_key, &idempotency_key, &newsletter_body())
+        .await;
+    assert_eq!(response.status(), 202);
+
+    // Publish a different newsletter with the same key
+    let mut body = newsletter_body();
+    body["title"] = "Another newsletter title".into();
+    let response = app
+        .post_api_newsletters(&api_key, &idempotency_key, &body)
+        .await;
+    assert_eq!(response.status(), 422);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 422);
+    assert_eq!(
+        problem["detail"],
+        "The idempotency key was already used for a different request, use a new key to send this one"
+    );
+
+    // Retrying the first request with its fields in another order still returns the saved response
+    let response = app
+        .api_client
+        .post(format!("{}/api/v1/newsletters", &app.address))
+        .bearer_auth(&api_key)
+        .header("Idempotency-Key", &idempotency_key)
+        .header("Content-Type", "application/json")
+        .body(
+            r#"{
+                "content_text": "Newsletter body as plain text",
+                "content_html": "<p>Newsletter body as HTML</p>",
+                "title": "Newsletter title"
+            }"#,
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+    let n_issues = sqlx::query!(r#"SELECT count(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 1);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn api_keys_are_stored_hashed_and_record_their_usage(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key("newsletters:publish").await;
+
+    let response = app
+        .post_api_newsletters(
+            &api_key,
+            IdempotencyKey::generate().as_ref(),
+            &newsletter_body(),
+        )
+        .await;
+    assert_eq!(response.status(), 202);
+
+    let saved = sqlx::query!("SELECT key_prefix, key_hash, last_used_at FROM api_keys")
+        .fetch_one(&db_pool)
+        .await
+        .expect("Failed to fetch saved API key");
+    assert!(api_key.starts_with(&saved.key_prefix));
+    assert_ne!(saved.key_hash, api_key);
+    assert!(saved.last_used_at.is_some());
+
+    // The admin page only shows the key prefix
+    let html = app.get_api_keys_html().await;
+    assert!(html.contains(&saved.key_prefix));
+    assert!(!html.contains(&api_key));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn revoked_api_keys_are_rejected(_pool_opts: PgPoolOptions, conn_opts: PgConnectOptions) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key("newsletters:publish").await;
+
+    let api_key_id = sqlx::query!("SELECT api_key_id FROM api_keys")
+        .fetch_one(&db_pool)
+        .await
+        .expect("Failed to fetch saved API key")
+        .api_key_id;
+    let response = app.post_api_key_revocation(&api_key_id.to_string()).await;
+    assert_is_redirect_to(&response, "/admin/api-keys");
+    let html = app.get_api_keys_html().await;
+    assert!(html.contains("<p><i>The API key has been revoked</i></p>"));
+
+    let response = app
+        .post_api_newsletters(
+            &api_key,
+            IdempotencyKey::generate().as_ref(),
+            &newsletter_body(),
+        )
+        .await;
+    assert_eq!(response.status(), 401);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn api_keys_of_demoted_users_cannot_publish(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key("newsletters:publish").await;
+
+    // Demote the owner of the key to viewer
+    sqlx::query!(
+        "UPDATE users SET role = 'viewer' WHERE user_id = $1",
+        *app.test_user.user_id
+    )
+    .execute(&db_pool)
+    .await
+    .unwrap();
+
+    let response = app
+        .post_api_newsletters(
+            &api_key,
+            IdempotencyKey::generate().as_ref(),
+            &newsletter_body(),
+        )
+        .await;
+    assert_eq!(response.status(), 403);
+    let n_issues = sqlx::query!(r#"SELECT count(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn invalid_api_requests_are_rejected_with_problem_details(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key("newsletters:publish").await;
+
+    let test_cases = vec![
+        (
+            serde_json::json!({"title": "Newsletter title"}),
+            IdempotencyKey::generate().to_string(),
+            "missing content",
+        ),
+        (newsletter_body(), String::new(), "empty idempotency key"),
+        (
+            serde_json::json!({
+                "title": "Newsletter title",
+                "content_text": "Newsletter body as plain text",
+                "content_html": "<p>Newsletter body as HTML</p>",
+                "segment_id": "00000000-0000-0000-0000-000000000000",
+            }),
+            IdempotencyKey::generate().to_string(),
+            "unknown segment",
+        ),
+    ];
+
+    for (body, idempotency_key, description) in test_cases {
+        let response = app
+            .post_api_newsletters(&api_key, &idempotency_key, &body)
+            .await;
+        assert_eq!(
+            response.status(),
+            400,
+            "The API did not fail with 400 Bad Request when the request had {description}"
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["status"], 400);
+    }
+
+    db_pool.close().await;
+}
@@ -0,0 +1,195 @@
+use secrecy::SecretString;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::PgPool;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use zero2prod::configuration::BotChallengeSettings;
+
+use crate::helpers::{when_sending_an_email, TestApp};
+
+/// Spin up a test application that verifies challenges against a mock server, which accepts `valid-token`
+async fn spawn_with_challenge(db_pool: &PgPool) -> (TestApp, MockServer) {
+    let challenge_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/siteverify"))
+        .and(body_string_contains("secret=challenge-secret"))
+        .and(body_string_contains("response=valid-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": true
+        })))
+        .mount(&challenge_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/siteverify"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": false,
+            "error-codes": ["invalid-input-response"]
+        })))
+        .mount(&challenge_server)
+        .await;
+
+    let verify_url = format!("{}/siteverify", challenge_server.uri())
+        .parse()
+        .unwrap();
+    let app = TestApp::spawn_with(db_pool, |c| {
+        c.bot_challenge = Some(BotChallengeSettings {
+            verify_url,
+            secret_key: SecretString::from("challenge-secret"),
+            timeout_millis: 1000,
+        });
+    })
+    .await;
+    (app, challenge_server)
+}
+
+/// Count the stored subscriptions
+async fn count_subscriptions(db_pool: &PgPool) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(db_pool)
+        .await
+        .expect("Failed to count subscriptions")
+        .count
+}
+
+#[sqlx::test]
+async fn subscriptions_filling_the_honeypot_are_silently_ignored(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let body =
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&website=https%3A%2F%2Fspam.example.com";
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(count_subscriptions(&db_pool).await, 0);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn an_empty_honeypot_does_not_prevent_subscribing(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&website=";
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(count_subscriptions(&db_pool).await, 1);
+    app.dispatch_all_pending_emails().await;
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn subscriptions_without_a_valid_challenge_response_are_rejected(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let (app, _challenge_server) = spawn_with_challenge(&db_pool).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let test_cases = [
+        (
+            "name=le%20guin&email=ursula_le_guin%40gmail.com",
+            "missing challenge response",
+        ),
+        (
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&h-captcha-response=forged-token",
+            "invalid challenge response",
+        ),
+    ];
+    for (body, description) in test_cases {
+        let response = app.post_subscriptions(body.into()).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not return a 400 Bad Request with {description}"
+        );
+    }
+    assert_eq!(count_subscriptions(&db_pool).await, 0);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn subscriptions_with_a_valid_challenge_response_are_accepted(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let (app, _challenge_server) = spawn_with_challenge(&db_pool).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // hCaptcha widgets submit their token in a form field
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&h-captcha-response=valid-token";
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Turnstile tokens are accepted in JSON documents as well
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "le_guin@example.com",
+            "cf-turnstile-response": "valid-token"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(count_subscriptions(&db_pool).await, 2);
+    app.dispatch_all_pending_emails().await;
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn json_clients_receive_problem_details_when_the_challenge_fails(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let (app, _challenge_server) = spawn_with_challenge(&db_pool).await;
+
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "challenge_response": "forged-token"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        problem["detail"],
+        "The challenge could not be verified, please try again"
+    );
+
+    db_pool.close().await;
+}
@@ -0,0 +1,182 @@
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+use zero2prod::authentication::CSRF_TOKEN_HEADER;
+
+use crate::helpers::{assert_is_redirect_to, TestApp};
+
+#[sqlx::test]
+async fn admin_forms_embed_the_csrf_token_of_the_session(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+
+    let csrf_token = app.csrf_token().await;
+    assert_eq!(csrf_token.len(), 32);
+    let hidden_field =
+        format!(r#"<input hidden type="text" name="csrf_token" value="{csrf_token}">"#);
+    assert!(app.get_newsletters_html().await.contains(&hidden_field));
+    assert!(app.get_password_html().await.contains(&hidden_field));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn admin_posts_without_a_csrf_token_are_rejected(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+
+    // Post forms the way a third-party page would, without the token
+    let new_password = uuid::Uuid::new_v4().to_string();
+    let test_cases = [
+        (
+            "/admin/password",
+            serde_json::json!({
+                "old_password": &app.test_user.password,
+                "new_password": &new_password,
+                "new_password2": &new_password,
+            }),
+        ),
+        ("/admin/logout", serde_json::json!({})),
+    ];
+    for (path, body) in test_cases {
+        let response = app
+            .api_client
+            .post(format!("{}{path}", &app.address))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(
+            response.status().as_u16(),
+            403,
+            "The API did not reject a POST to {path} without a CSRF token"
+        );
+    }
+
+    // The user is still logged in with the same password
+    let response = app.get_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.post_logout().await;
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn admin_posts_with_a_mismatched_csrf_token_are_rejected(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+
+    // In the form body
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .form(&serde_json::json!({"csrf_token": "forged-token"}))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status().as_u16(), 403);
+
+    // In the header, with problem details for JSON clients
+    let response = app
+        .api_client
+        .post(format!("{}/admin/passkeys/register/start", &app.address))
+        .header(CSRF_TOKEN_HEADER, "forged-token")
+        .json(&serde_json::json!({}))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status().as_u16(), 403);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 403);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn the_csrf_token_can_be_submitted_as_a_form_field(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+
+    let csrf_token = app.csrf_token().await;
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .form(&serde_json::json!({"csrf_token": csrf_token}))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn the_csrf_token_is_renewed_on_login(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let old_csrf_token = app.csrf_token().await;
+
+    // Login again
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+    let csrf_token = app.csrf_token().await;
+    assert_ne!(csrf_token, old_csrf_token);
+
+    // The token of the previous session is no longer accepted
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .header(CSRF_TOKEN_HEADER, old_csrf_token)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status().as_u16(), 403);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn concurrent_page_loads_after_login_embed_the_same_csrf_token(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+
+    // Load two admin pages at once, as two browser tabs would right after login
+    let (newsletters_html, password_html) =
+        tokio::join!(app.get_newsletters_html(), app.get_password_html());
+
+    // Both forms can be submitted with the token of the session
+    let csrf_token = app.csrf_token().await;
+    let hidden_field =
+        format!(r#"<input hidden type="text" name="csrf_token" value="{csrf_token}">"#);
+    assert!(newsletters_html.contains(&hidden_field));
+    assert!(password_html.contains(&hidden_field));
+
+    db_pool.close().await;
+}
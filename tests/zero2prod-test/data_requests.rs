@@ -0,0 +1,137 @@
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+use crate::helpers::{assert_is_redirect_to, TestApp};
+
+#[sqlx::test]
+async fn you_must_be_logged_in_to_handle_data_subject_requests(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    let body = serde_json::json!({"email": "ursula_le_guin@gmail.com"});
+    let response = app.post_data_export(&body).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let body = serde_json::json!({"email": "ursula_le_guin@gmail.com", "mode": "erase"});
+    let response = app.post_data_erasure(&body).await;
+    assert_is_redirect_to(&response, "/login");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn data_export_returns_all_data_held_about_a_subject(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.create_unconfirmed_subscriber().await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    // Login
+    app.test_user.login(&app).await;
+
+    // Export the data
+    let body = serde_json::json!({"email": &email});
+    let response = app.post_data_export(&body).await;
+    assert_eq!(response.status(), 200);
+    let data: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(data["subscriptions"][0]["email"], email.as_str());
+    assert_eq!(data["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(data["emails"][0]["kind"], "confirmation");
+
+    // Check the audit trail
+    let audit = sqlx::query!("SELECT kind, source FROM data_subject_requests")
+        .fetch_one(&db_pool)
+        .await
+        .unwrap();
+    assert_eq!(audit.kind, "export");
+    assert_eq!(audit.source, "admin");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn data_erasure_removes_all_data_held_about_a_subject(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.create_unconfirmed_subscriber().await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    // Login
+    app.test_user.login(&app).await;
+
+    // Erase the data
+    let body = serde_json::json!({"email": &email, "mode": "erase"});
+    let response = app.post_data_erasure(&body).await;
+    assert_is_redirect_to(&response, "/admin/data-requests");
+
+    // Follow the redirect
+    let html = app.get_data_requests_html().await;
+    assert!(html.contains("(3 records affected)"));
+
+    // Check that no data is left and that the request was audited
+    let n_subscriptions = sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscriptions, 0);
+    let audit = sqlx::query!("SELECT kind, affected_rows FROM data_subject_requests")
+        .fetch_one(&db_pool)
+        .await
+        .unwrap();
+    assert_eq!(audit.kind, "erasure");
+    assert_eq!(audit.affected_rows, 3);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn data_pseudonymisation_replaces_personal_data(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.create_confirmed_subscriber().await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    // Login
+    app.test_user.login(&app).await;
+
+    // Pseudonymise the data
+    let body = serde_json::json!({"email": &email, "mode": "pseudonymise"});
+    let response = app.post_data_erasure(&body).await;
+    assert_is_redirect_to(&response, "/admin/data-requests");
+
+    // Check that the subscription row is kept without personal data
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
+        .fetch_one(&db_pool)
+        .await
+        .unwrap();
+    assert_ne!(saved.email, email);
+    assert!(saved.email.ends_with("@erased.invalid"));
+    assert_eq!(saved.name, "erased");
+    assert_eq!(saved.status, "erased");
+
+    db_pool.close().await;
+}
@@ -1,4 +1,4 @@
-use std::{env, io, sync};
+use std::{env, io, net, sync};
 
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
@@ -8,6 +8,7 @@ use fake::faker::name::en::Name;
 use fake::Fake;
 use fdlimit::raise_fd_limit;
 use linkify::{LinkFinder, LinkKind};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use reqwest::Url;
 use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 use sqlx::PgPool;
@@ -15,7 +16,7 @@ use uuid::Uuid;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, MockBuilder, MockServer, ResponseTemplate};
 
-use zero2prod::authentication::UserId;
+use zero2prod::authentication::{UserId, CSRF_TOKEN_HEADER};
 use zero2prod::configuration::Settings;
 use zero2prod::delivery_worker::{try_execute_task, ExecutionResult};
 use zero2prod::email_client::EmailClient;
@@ -40,9 +41,12 @@ static TRACING: sync::LazyLock<()> = sync::LazyLock::new(|| {
             default_filter_level,
             io::sink,
         ));
-    };
+    }
 });
 
+/// Header in which the API client forwards requests for the test client address, as a reverse proxy would
+pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
 /// Confirmation links embedded in the request to the email API
 pub struct ConfirmationLinks {
     pub html: Url,
@@ -52,10 +56,12 @@ pub struct ConfirmationLinks {
 /// Test application data
 pub struct TestApp {
     pub address: String,
+    pub db_pool: PgPool,
     pub port: u16,
     pub email_server: MockServer,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    pub client_ip: String,
     pub email_client: EmailClient,
 }
 
@@ -67,6 +73,11 @@ impl TestApp {
 
     /// Spin up a test application and return its data
     pub async fn spawn(db_pool: &PgPool) -> Self {
+        Self::spawn_with(db_pool, |_| {}).await
+    }
+
+    /// Spin up a test application with customized settings and return its data
+    pub async fn spawn_with(db_pool: &PgPool, customize: impl FnOnce(&mut Settings)) -> Self {
         // Initialize logging
         sync::LazyLock::force(&TRACING);
 
@@ -83,6 +94,15 @@ impl TestApp {
             c.application.app_port = 0;
             // Use the mock server as email API
             c.email_client.base_url = email_server.uri();
+            // Keep progressive login delays short
+            c.login_throttle.base_delay_millis = 10;
+            c.login_throttle.max_delay_millis = 50;
+            // Tests subscribe the same email addresses over and over, and their buckets are shared by all the
+            // tests when rate limits are counted in Redis
+            c.rate_limit.email.capacity = u32::MAX;
+            // The API client connects through the loopback interface, as a reverse proxy would
+            c.application.trusted_proxies = vec![net::IpAddr::from([127, 0, 0, 1])];
+            customize(&mut c);
             c
         };
 
@@ -97,10 +117,22 @@ impl TestApp {
         let port = app.port();
         let address = format!("http://127.0.0.1:{port}");
 
-        // Build the API client
+        // Build the API client, forwarding requests for a distinct client address so that login failures and rate
+        // limits are counted per test
+        let client_ip = format!(
+            "10.{}.{}.{}",
+            rand::random::<u8>(),
+            rand::random::<u8>(),
+            rand::random::<u8>().max(1)
+        );
+        let forwarded_for = HeaderMap::from_iter([(
+            HeaderName::from_static(X_FORWARDED_FOR),
+            HeaderValue::from_str(&client_ip).unwrap(),
+        )]);
         let api_client = reqwest::Client::builder()
             .redirect(reqwest::redirect::Policy::none())
             .cookie_store(true)
+            .default_headers(forwarded_for)
             .build()
             .unwrap();
 
@@ -112,14 +144,26 @@ impl TestApp {
         let _ = tokio::spawn(app.run_until_stopped());
         Self {
             address,
+            db_pool: db_pool.clone(),
             port,
             email_server,
             test_user,
             api_client,
+            client_ip,
             email_client,
         }
     }
 
+    /// Perform a GET request to the subscription confirmation endpoint
+    pub async fn get_subscriptions_confirm(&self, subscription_token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/subscriptions/confirm", &self.address))
+            .query(&[("subscription_token", subscription_token)])
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
     /// Perform a POST request to the subscriptions endpoint
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
@@ -131,6 +175,16 @@ impl TestApp {
             .expect("Failed to send request")
     }
 
+    /// Perform a POST request to the subscriptions endpoint with a JSON body
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
     /// Extract confirmation links embedded in the request to the email API
     pub fn confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         // Parse the request body as JSON
@@ -178,11 +232,12 @@ impl TestApp {
             .mount_as_scoped(&self.email_server)
             .await;
 
-        // Subscribe to the newsletter using the API
+        // Subscribe to the newsletter using the API, and let the worker send the confirmation email
         self.post_subscriptions(body)
             .await
             .error_for_status()
             .unwrap();
+        self.dispatch_all_pending_emails().await;
 
         // Inspect the requests received by the mock server to retrieve the confirmation link and return it
         let email_request = &self
@@ -201,7 +256,9 @@ impl TestApp {
         let confirmation_link = self.create_unconfirmed_subscriber().await;
 
         // Confirm subscription to the newsletter using the API
-        reqwest::get(confirmation_link.html)
+        self.api_client
+            .get(confirmation_link.html)
+            .send()
             .await
             .unwrap()
             .error_for_status()
@@ -209,10 +266,12 @@ impl TestApp {
     }
 
     /// Consume all enqueued tasks
-    pub async fn dispatch_all_pending_emails(&self, db_pool: &PgPool) {
+    pub async fn dispatch_all_pending_emails(&self) {
         loop {
             if matches!(
-                try_execute_task(db_pool, &self.email_client).await.unwrap(),
+                try_execute_task(&self.db_pool, &self.email_client)
+                    .await
+                    .unwrap(),
                 ExecutionResult::EmptyQueue
             ) {
                 break;
@@ -228,6 +287,7 @@ impl TestApp {
     {
         self.api_client
             .post(format!("{}/admin/newsletters", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
             .form(&body)
             .send()
             .await
@@ -248,6 +308,279 @@ impl TestApp {
         self.get_newsletters().await.text().await.unwrap()
     }
 
+    /// POST to the subscribers endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_subscribers<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/subscribers", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// GET to the subscriber details endpoint
+    pub async fn get_subscriber_details(&self, subscriber_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{subscriber_id}",
+                &self.address
+            ))
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// GET to the segments endpoint
+    pub async fn get_segments(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/segments", &self.address))
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// GET to the segments endpoint and extract HTML
+    pub async fn get_segments_html(&self) -> String {
+        self.get_segments().await.text().await.unwrap()
+    }
+
+    /// POST to the segments endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_segments<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/segments", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// POST to the data subject access request endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_data_export<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/data-requests/export", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// POST to the data subject erasure request endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_data_erasure<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/data-requests/erase", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// GET to the data subject requests endpoint and extract HTML
+    pub async fn get_data_requests_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/data-requests", &self.address))
+            .send()
+            .await
+            .expect("Failed to send request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// POST to the API keys endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_api_keys<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/api-keys", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// GET to the API keys endpoint and extract HTML
+    pub async fn get_api_keys_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/api-keys", &self.address))
+            .send()
+            .await
+            .expect("Failed to send request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// POST to the API key revocation endpoint
+    pub async fn post_api_key_revocation(&self, api_key_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/api-keys/{api_key_id}/revoke",
+                &self.address
+            ))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// Create an API key through the admin pages and return it (requires being logged in)
+    pub async fn create_api_key(&self, scopes: &str) -> String {
+        let html = self
+            .post_api_keys(&serde_json::json!({
+                "name": "CI pipeline",
+                "scopes": scopes,
+            }))
+            .await
+            .text()
+            .await
+            .unwrap();
+        html.split("<code id=\"api-key\">")
+            .nth(1)
+            .and_then(|s| s.split("</code>").next())
+            .expect("The API key was not displayed")
+            .to_string()
+    }
+
+    /// POST to the newsletters API endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_api_newsletters<Body>(
+        &self,
+        api_key: &str,
+        idempotency_key: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/api/v1/newsletters", &self.address))
+            .bearer_auth(api_key)
+            .header("Idempotency-Key", idempotency_key)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// POST to the users endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_users<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/users", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// GET to the users endpoint and extract HTML
+    pub async fn get_users_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Failed to send request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// POST to the invitations endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_invitations<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/invitations", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// GET to the invitations endpoint and extract HTML
+    pub async fn get_invitations_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/invitations", &self.address))
+            .send()
+            .await
+            .expect("Failed to send request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// POST to the invitation acceptance endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_accept_invitation<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/invitations/accept", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// POST to the password reset request endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_password_reset<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/password-reset", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// POST to the new password endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_password_reset_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/password-reset/confirm", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
     /// POST to the login endpoint
     #[allow(clippy::future_not_send)]
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
@@ -262,6 +595,132 @@ impl TestApp {
             .expect("Failed to send request")
     }
 
+    /// POST to the second authentication factor endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_login_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/two-factor", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// GET to the two-factor authentication settings endpoint and extract HTML
+    pub async fn get_two_factor_settings_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/2fa", &self.address))
+            .send()
+            .await
+            .expect("Failed to send request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// POST to the two-factor enrolment endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_two_factor_enrolment<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/2fa", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// POST to the two-factor removal endpoint
+    #[allow(clippy::future_not_send)]
+    pub async fn post_two_factor_disable<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/2fa/disable", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// POST to the passkey login endpoint that starts the ceremony
+    pub async fn post_passkey_login_start(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/passkey/start", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// POST to the passkey login endpoint that completes the ceremony
+    pub async fn post_passkey_login_finish(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/passkey/finish", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// GET to the passkeys endpoint and extract HTML
+    pub async fn get_passkeys_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/passkeys", &self.address))
+            .send()
+            .await
+            .expect("Failed to send request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// POST to the passkey registration endpoint that starts the ceremony
+    pub async fn post_passkey_registration_start(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/passkeys/register/start", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .json(&serde_json::json!({}))
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// POST to the passkey registration endpoint that completes the ceremony
+    pub async fn post_passkey_registration_finish(
+        &self,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/passkeys/register/finish", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// POST to the passkey deletion endpoint
+    pub async fn post_passkey_deletion(&self, passkey_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/passkeys/{passkey_id}/delete",
+                &self.address
+            ))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
     /// GET to the login endpoint and extract HTML
     pub async fn get_login_html(&self) -> String {
         self.api_client
@@ -274,6 +733,51 @@ impl TestApp {
             .unwrap()
     }
 
+    /// GET to the sessions endpoint and extract HTML
+    pub async fn get_sessions_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to send request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// POST to the session revocation endpoint
+    pub async fn post_session_revocation(&self, session_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/sessions/{session_id}/revoke",
+                &self.address
+            ))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// POST to the endpoint that revokes all other sessions
+    pub async fn post_other_sessions_revocation(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/sessions/revoke-others", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to send request")
+    }
+
+    /// Retrieve the CSRF token of the current session from the admin dashboard, if logged in
+    pub async fn csrf_token(&self) -> String {
+        let html = self.get_dashboard_html().await;
+        html.split(r#"name="csrf_token" value=""#)
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap_or_default()
+            .to_string()
+    }
+
     /// GET to the admin dashboard endpoint
     pub async fn get_dashboard(&self) -> reqwest::Response {
         self.api_client
@@ -310,6 +814,7 @@ impl TestApp {
     {
         self.api_client
             .post(format!("{}/admin/password", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
             .form(body)
             .send()
             .await
@@ -320,6 +825,7 @@ impl TestApp {
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
             .post(format!("{}/admin/logout", &self.address))
+            .header(CSRF_TOKEN_HEADER, self.csrf_token().await)
             .send()
             .await
             .expect("Failed to send request")
@@ -369,6 +875,21 @@ impl TestUser {
         .expect("Failed to store test user in the database");
     }
 
+    /// Generate and store a test user with the specified role
+    pub async fn generate_with_role(db_pool: &PgPool, role: &str) -> Self {
+        let user = Self::generate();
+        user.store(db_pool).await;
+        sqlx::query!(
+            "UPDATE users SET role = $1 WHERE user_id = $2",
+            role,
+            *user.user_id
+        )
+        .execute(db_pool)
+        .await
+        .expect("Failed to set test user role");
+        user
+    }
+
     /// Login to the test application
     pub async fn login(&self, app: &TestApp) -> reqwest::Response {
         app.post_login(&serde_json::json!({
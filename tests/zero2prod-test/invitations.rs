@@ -0,0 +1,205 @@
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{assert_is_redirect_to, when_sending_an_email, TestApp, TestUser};
+
+/// Invite a collaborator as the logged-in test user and return the invitation token
+async fn invite_collaborator(app: &TestApp, email: &str) -> String {
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_invitations(&serde_json::json!({"email": email, "role": "editor"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/invitations");
+
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let link = app.confirmation_links(email_request).html;
+    link.query_pairs()
+        .find(|(k, _)| k == "invitation_token")
+        .map(|(_, v)| v.into_owned())
+        .expect("The invitation link does not contain a token")
+}
+
+#[sqlx::test]
+async fn you_must_be_logged_in_to_invite_collaborators(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    let response = app
+        .post_invitations(&serde_json::json!({"email": "collaborator@example.com"}))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn invited_collaborators_can_create_an_account_and_log_in(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+
+    // Invite a collaborator
+    let token = invite_collaborator(&app, "collaborator@example.com").await;
+    let html = app.get_invitations_html().await;
+    assert!(html.contains("<p><i>An invitation has been sent to collaborator@example.com</i></p>"));
+    app.post_logout().await;
+
+    // Follow the invitation link
+    let html = app
+        .api_client
+        .get(format!(
+            "{}/invitations/accept?invitation_token={token}",
+            &app.address
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("collaborator@example.com"));
+
+    // Choose username and password
+    let username = TestUser::fake_username();
+    let password = TestUser::fake_password(16);
+    let response = app
+        .post_accept_invitation(&serde_json::json!({
+            "invitation_token": &token,
+            "username": &username,
+            "password": &password,
+            "password_check": &password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("<p><i>Your account has been created, you can now log in</i></p>"));
+
+    // Log in with the new credentials
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &username,
+            "password": &password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn invitations_can_only_be_accepted_once(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let token = invite_collaborator(&app, "collaborator@example.com").await;
+    app.post_logout().await;
+
+    let count_users = || async {
+        sqlx::query!(r#"SELECT count(*) AS "count!" FROM users"#)
+            .fetch_one(&db_pool)
+            .await
+            .unwrap()
+            .count
+    };
+    let n_users = count_users().await;
+
+    let password = TestUser::fake_password(16);
+    for username in [TestUser::fake_username(), TestUser::fake_username()] {
+        app.post_accept_invitation(&serde_json::json!({
+            "invitation_token": &token,
+            "username": username,
+            "password": &password,
+            "password_check": &password,
+        }))
+        .await;
+    }
+
+    let html = app.get_login_html().await;
+    assert!(html.contains("<p><i>The invitation link is invalid or has expired</i></p>"));
+    assert_eq!(count_users().await, n_users + 1);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn expired_invitations_are_rejected(_pool_opts: PgPoolOptions, conn_opts: PgConnectOptions) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let token = invite_collaborator(&app, "collaborator@example.com").await;
+    app.post_logout().await;
+
+    sqlx::query!("UPDATE invitations SET expires_at = now() - interval '1 hour'")
+        .execute(&db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/invitations/accept?invitation_token={token}",
+            &app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn invited_email_addresses_already_in_use_ignoring_case_are_not_assigned(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    sqlx::query!(
+        "UPDATE users SET email = 'collaborator@example.com' WHERE user_id = $1",
+        *app.test_user.user_id
+    )
+    .execute(&db_pool)
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    // Invite a collaborator with the email address of the test user, in a different case
+    let token = invite_collaborator(&app, "Collaborator@Example.com").await;
+    app.post_logout().await;
+
+    // The account is created without taking over the email address
+    let username = TestUser::fake_username();
+    let password = TestUser::fake_password(16);
+    let response = app
+        .post_accept_invitation(&serde_json::json!({
+            "invitation_token": &token,
+            "username": &username,
+            "password": &password,
+            "password_check": &password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let saved = sqlx::query!("SELECT email FROM users WHERE username = $1", username)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, None);
+
+    db_pool.close().await;
+}
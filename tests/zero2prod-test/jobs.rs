@@ -0,0 +1,249 @@
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::PgPool;
+use wiremock::ResponseTemplate;
+
+use zero2prod::idempotency::IdempotencyKey;
+
+use crate::helpers::{assert_is_redirect_to, when_sending_an_email, TestApp};
+
+/// Queued job data
+struct QueuedJob {
+    kind: String,
+    attempts: i32,
+    last_error: Option<String>,
+    is_due: bool,
+}
+
+/// Retrieve the jobs in the queue
+async fn queued_jobs(db_pool: &PgPool) -> Vec<QueuedJob> {
+    sqlx::query_as!(
+        QueuedJob,
+        r#"
+        SELECT kind, attempts, last_error, run_at <= now() AS "is_due!"
+        FROM jobs
+        "#
+    )
+    .fetch_all(db_pool)
+    .await
+    .unwrap()
+}
+
+/// Email written to the outbox
+struct OutboxEmail {
+    kind: String,
+    status: String,
+    attempts: i32,
+    last_error: Option<String>,
+    sent_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Retrieve the emails written to the outbox
+async fn outbox_emails(db_pool: &PgPool) -> Vec<OutboxEmail> {
+    sqlx::query_as!(
+        OutboxEmail,
+        r#"
+        SELECT kind, status, attempts, last_error, sent_at
+        FROM outbox
+        "#
+    )
+    .fetch_all(db_pool)
+    .await
+    .unwrap()
+}
+
+/// Login and publish a newsletter issue to all confirmed subscribers
+async fn publish_newsletter(app: &TestApp) {
+    app.test_user.login(app).await;
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "content_text": "Newsletter body as plain text",
+        "content_html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": IdempotencyKey::generate()
+    });
+    let response = app.post_newsletters(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[sqlx::test]
+async fn newsletter_deliveries_are_written_to_the_outbox_and_queued_as_jobs(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.create_confirmed_subscriber().await;
+    app.create_confirmed_subscriber().await;
+
+    publish_newsletter(&app).await;
+
+    // The confirmation emails have already been sent, the newsletter issue is pending
+    let emails = outbox_emails(&db_pool).await;
+    assert_eq!(emails.len(), 4);
+    let newsletters: Vec<_> = emails.iter().filter(|e| e.kind == "newsletter").collect();
+    assert_eq!(newsletters.len(), 2);
+    assert!(newsletters
+        .iter()
+        .all(|e| e.status == "pending" && e.attempts == 0));
+    let jobs = queued_jobs(&db_pool).await;
+    assert_eq!(jobs.len(), 2);
+    assert!(jobs
+        .iter()
+        .all(|j| j.kind == "outbox_dispatch" && j.attempts == 0 && j.is_due));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn dispatched_emails_are_marked_as_sent_in_the_outbox(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.create_confirmed_subscriber().await;
+    publish_newsletter(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+    assert!(queued_jobs(&db_pool).await.is_empty());
+
+    // The confirmation email and the newsletter issue are both kept as sent
+    let emails = outbox_emails(&db_pool).await;
+    assert_eq!(emails.len(), 2);
+    assert!(emails
+        .iter()
+        .all(|e| e.status == "sent" && e.attempts == 1 && e.sent_at.is_some()));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn sent_emails_are_logged_with_their_single_use_token_redacted(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    let confirmation_links = app.create_unconfirmed_subscriber().await;
+    let subscription_token = confirmation_links
+        .html
+        .query_pairs()
+        .find(|(k, _)| k == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    let email = sqlx::query!(
+        r#"
+        SELECT html_body, text_body, single_use_token
+        FROM outbox
+        WHERE kind = 'confirmation'
+        "#
+    )
+    .fetch_one(&db_pool)
+    .await
+    .unwrap();
+    for body in [email.html_body, email.text_body] {
+        assert!(body.contains("Welcome to our newsletter!"));
+        assert!(body.contains("subscription_token=[redacted]"));
+        assert!(!body.contains(&subscription_token));
+    }
+    assert!(email.single_use_token.is_none());
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn transient_delivery_failures_are_retried_later(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.create_confirmed_subscriber().await;
+    publish_newsletter(&app).await;
+
+    // The email API is down
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // The delivery is attempted once, then postponed
+    app.dispatch_all_pending_emails().await;
+    let jobs = queued_jobs(&db_pool).await;
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].attempts, 1);
+    assert!(jobs[0].last_error.is_some());
+    assert!(!jobs[0].is_due);
+    let email = outbox_emails(&db_pool)
+        .await
+        .into_iter()
+        .find(|e| e.kind == "newsletter")
+        .unwrap();
+    assert_eq!(email.status, "pending");
+    assert_eq!(email.attempts, 1);
+    assert!(email.last_error.is_some());
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn deliveries_to_invalid_email_addresses_are_skipped(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.create_confirmed_subscriber().await;
+    sqlx::query!("UPDATE subscriptions SET email = 'not-an-email'")
+        .execute(&db_pool)
+        .await
+        .unwrap();
+    publish_newsletter(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_pending_emails().await;
+    assert!(queued_jobs(&db_pool).await.is_empty());
+    let email = outbox_emails(&db_pool)
+        .await
+        .into_iter()
+        .find(|e| e.kind == "newsletter")
+        .unwrap();
+    assert_eq!(email.status, "failed");
+    assert!(email.last_error.is_some());
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn jobs_of_unknown_kind_are_discarded(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO jobs (job_id, kind, payload, run_at, attempts, created_at)
+        VALUES (gen_random_uuid(), 'unknown', '{}', now(), 0, now())
+        "#
+    )
+    .execute(&db_pool)
+    .await
+    .unwrap();
+
+    app.dispatch_all_pending_emails().await;
+    assert!(queued_jobs(&db_pool).await.is_empty());
+
+    db_pool.close().await;
+}
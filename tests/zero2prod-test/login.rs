@@ -1,6 +1,6 @@
 use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 
-use crate::helpers::{assert_is_redirect_to, TestApp, TestUser};
+use crate::helpers::{assert_is_redirect_to, TestApp, TestUser, X_FORWARDED_FOR};
 use crate::FAKE_PASSWORD_LEN;
 
 #[sqlx::test]
@@ -48,3 +48,156 @@ async fn redirect_to_admin_dashboard_after_login_success(
 
     db_pool.close().await;
 }
+
+/// Number of failures that locks out a username, as set in the base configuration
+const MAX_USERNAME_FAILURES: usize = 10;
+
+/// Number of failures that locks out a client IP, as set in the base configuration
+const MAX_IP_FAILURES: usize = 50;
+
+/// Try to login with a wrong password and return the flash message displayed afterwards
+async fn fail_login(app: &TestApp, username: &str) -> String {
+    let body = serde_json::json!({
+        "username": username,
+        "password": TestUser::fake_password(FAKE_PASSWORD_LEN),
+    });
+    let response = app.post_login(&body).await;
+    assert_is_redirect_to(&response, "/login");
+    app.get_login_html().await
+}
+
+#[sqlx::test]
+async fn repeated_failures_lock_out_the_username(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    // Fail until the username is locked out
+    for _ in 0..MAX_USERNAME_FAILURES {
+        let html = fail_login(&app, &app.test_user.username).await;
+        assert!(html.contains("<p><i>Authentication failed</i></p>"));
+    }
+
+    // The right password is not even checked anymore
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("<p><i>Too many failed login attempts, please try again later</i></p>"));
+    let response = app.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn lockouts_do_not_reveal_whether_a_username_exists(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    let mut messages = Vec::new();
+    for username in [TestUser::fake_username(), app.test_user.username.clone()] {
+        for _ in 0..MAX_USERNAME_FAILURES {
+            fail_login(&app, &username).await;
+        }
+        messages.push(fail_login(&app, &username).await);
+    }
+    assert_eq!(messages[0], messages[1]);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn a_successful_login_resets_the_username_failures(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    // Fail just below the lockout threshold, then login
+    for _ in 1..MAX_USERNAME_FAILURES {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    // Another failure does not lock out the username
+    let html = fail_login(&app, &app.test_user.username).await;
+    assert!(html.contains("<p><i>Authentication failed</i></p>"));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn repeated_failures_lock_out_the_client(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    // Fail with many different usernames from the same client
+    for _ in 0..MAX_IP_FAILURES {
+        fail_login(&app, &TestUser::fake_username()).await;
+    }
+
+    // Valid credentials are rejected as well
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("Too many failed login attempts"));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn forged_forwarded_addresses_do_not_escape_the_client_lockout(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    // Fail with many different usernames, claiming a different client address each time, to which the proxy appends
+    // the actual one
+    for i in 0..MAX_IP_FAILURES {
+        let body = serde_json::json!({
+            "username": TestUser::fake_username(),
+            "password": TestUser::fake_password(FAKE_PASSWORD_LEN),
+        });
+        let response = app
+            .api_client
+            .post(format!("{}/login", &app.address))
+            .header(X_FORWARDED_FOR, format!("203.0.113.{i}, {}", app.client_ip))
+            .form(&body)
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_is_redirect_to(&response, "/login");
+    }
+
+    // Valid credentials are rejected as well, whatever the claimed address
+    let body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    });
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.address))
+        .header(X_FORWARDED_FOR, format!("203.0.113.100, {}", app.client_ip))
+        .form(&body)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_is_redirect_to(&response, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("Too many failed login attempts"));
+
+    db_pool.close().await;
+}
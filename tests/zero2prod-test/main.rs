@@ -1,10 +1,26 @@
+mod api;
+mod bot_protection;
+mod csrf;
 mod dashboard;
+mod data_requests;
 mod healthcheck;
 mod helpers;
+mod invitations;
+mod jobs;
 mod login;
 mod newsletters;
+mod openapi;
+mod passkeys;
 mod password;
+mod password_reset;
+mod rate_limit;
+mod roles;
+mod segments;
+mod session_store;
+mod sessions;
+mod subscribers;
 mod subscriptions;
 mod subscriptions_confirm;
+mod two_factor;
 
 const FAKE_PASSWORD_LEN: usize = 32;
@@ -3,7 +3,8 @@ use std::time::Duration;
 use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 use wiremock::ResponseTemplate;
 
-use zero2prod::idempotency::IdempotencyKey;
+use zero2prod::configuration::IdempotencySettings;
+use zero2prod::idempotency::{delete_expired_keys, try_processing, IdempotencyKey, NextAction};
 
 use crate::helpers::{assert_is_redirect_to, when_sending_an_email, TestApp};
 
@@ -43,7 +44,7 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers(
     ));
 
     // Consume all enqueued tasks
-    app.dispatch_all_pending_emails(&db_pool).await;
+    app.dispatch_all_pending_emails().await;
 
     db_pool.close().await;
 }
@@ -84,7 +85,7 @@ async fn newsletters_are_delivered_to_confirmed_subscribers(
     ));
 
     // Consume all enqueued tasks
-    app.dispatch_all_pending_emails(&db_pool).await;
+    app.dispatch_all_pending_emails().await;
 
     db_pool.close().await;
 }
@@ -217,7 +218,7 @@ async fn newsletter_creation_is_idempotent(_pool_opts: PgPoolOptions, conn_opts:
     ));
 
     // Consume all enqueued tasks
-    app.dispatch_all_pending_emails(&db_pool).await;
+    app.dispatch_all_pending_emails().await;
 
     db_pool.close().await;
 }
@@ -260,7 +261,233 @@ async fn concurrent_form_submission_is_handled_gracefully(
     );
 
     // Consume all enqueued tasks
-    app.dispatch_all_pending_emails(&db_pool).await;
+    app.dispatch_all_pending_emails().await;
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn form_submissions_still_being_processed_are_rejected_with_a_409(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn_with(&db_pool, |c| {
+        c.idempotency.wait_timeout_millis = 100;
+    })
+    .await;
+
+    // Login
+    app.test_user.login(&app).await;
+
+    // Simulate an in-flight request by holding the lock on its idempotency key
+    let idempotency_key = IdempotencyKey::generate();
+    let mut transaction = db_pool.begin().await.unwrap();
+    sqlx::query!(
+        "INSERT INTO idempotency (user_id, idempotency_key, created_at) VALUES ($1, $2, now())",
+        *app.test_user.user_id,
+        idempotency_key.as_ref()
+    )
+    .execute(&mut *transaction)
+    .await
+    .unwrap();
+
+    // Submit the newsletter form with the same key
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "content_text": "Newsletter body as plain text",
+        "content_html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": idempotency_key
+    });
+    let response = app.post_newsletters(&body).await;
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(response.headers()["Retry-After"], "1");
+
+    // Submit the form again once the in-flight request has been aborted
+    transaction.rollback().await.unwrap();
+    let response = app.post_newsletters(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn the_lock_timeout_only_bounds_the_wait_for_the_idempotency_key(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    let settings = IdempotencySettings {
+        retention_secs: 24 * 60 * 60,
+        wait_timeout_millis: 100,
+    };
+
+    let NextAction::StartProcessing(mut transaction) = try_processing(
+        &db_pool,
+        &IdempotencyKey::generate(),
+        app.test_user.user_id,
+        b"fingerprint",
+        &settings,
+    )
+    .await
+    .unwrap() else {
+        panic!("The request was not processed");
+    };
+
+    // The queries of the request itself wait for locks as long as the server default allows
+    let lock_timeout = sqlx::query!(r#"SELECT current_setting('lock_timeout') AS "lock_timeout!""#)
+        .fetch_one(&mut *transaction)
+        .await
+        .unwrap()
+        .lock_timeout;
+    assert_eq!(lock_timeout, "0");
+    transaction.rollback().await.unwrap();
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn reusing_an_idempotency_key_for_a_different_issue_is_rejected(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    // Login
+    app.test_user.login(&app).await;
+
+    // Publish the newsletter
+    let idempotency_key = IdempotencyKey::generate();
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "content_text": "Newsletter body as plain text",
+        "content_html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": idempotency_key
+    });
+    let response = app.post_newsletters(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Publish a different newsletter with the same key
+    let body = serde_json::json!({
+        "title": "Another newsletter title",
+        "content_text": "Newsletter body as plain text",
+        "content_html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": idempotency_key
+    });
+    let response = app.post_newsletters(&body).await;
+    assert_eq!(response.status().as_u16(), 422);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("This form was already submitted with different values"));
+
+    // Only the first newsletter was stored
+    let n_issues = sqlx::query!(r#"SELECT count(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 1);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn expired_idempotency_keys_are_handled_as_fresh_requests(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    // Create a confirmed subscriber for which we expect two newsletters
+    app.create_confirmed_subscriber().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Login
+    app.test_user.login(&app).await;
+
+    // Publish the newsletter
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "content_text": "Newsletter body as plain text",
+        "content_html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": IdempotencyKey::generate()
+    });
+    let response = app.post_newsletters(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Publish the newsletter again once the idempotency key has expired
+    sqlx::query!("UPDATE idempotency SET created_at = now() - interval '2 days'")
+        .execute(&db_pool)
+        .await
+        .unwrap();
+    let response = app.post_newsletters(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Consume all enqueued tasks
+    app.dispatch_all_pending_emails().await;
+    let n_issues = sqlx::query!(r#"SELECT count(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 2);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn expired_idempotency_keys_are_cleaned_up(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    // Login
+    app.test_user.login(&app).await;
+
+    // Publish two newsletters, one of them long ago
+    for _ in 0..2 {
+        let body = serde_json::json!({
+            "title": "Newsletter title",
+            "content_text": "Newsletter body as plain text",
+            "content_html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": IdempotencyKey::generate()
+        });
+        let response = app.post_newsletters(&body).await;
+        assert_is_redirect_to(&response, "/admin/newsletters");
+    }
+    sqlx::query!(
+        r#"
+        UPDATE idempotency
+        SET created_at = now() - interval '2 days'
+        WHERE idempotency_key = (SELECT min(idempotency_key) FROM idempotency)
+        "#
+    )
+    .execute(&db_pool)
+    .await
+    .unwrap();
+
+    // Only the expired key is deleted
+    let n_deleted = delete_expired_keys(&db_pool, Duration::from_secs(24 * 60 * 60))
+        .await
+        .unwrap();
+    assert_eq!(n_deleted, 1);
+    let n_keys = sqlx::query!(r#"SELECT count(*) AS "count!" FROM idempotency"#)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_keys, 1);
 
     db_pool.close().await;
 }
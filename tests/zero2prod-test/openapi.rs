@@ -0,0 +1,136 @@
+use std::collections::BTreeSet;
+
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use utoipa::OpenApi;
+
+use zero2prod::openapi::ApiDoc;
+use zero2prod::startup::app_routes;
+
+use crate::helpers::TestApp;
+
+/// Routes that serve HTML pages or the specification itself, and are deliberately left out of it
+const UNDOCUMENTED_ROUTES: &[&str] = &[
+    "GET /",
+    "GET /login",
+    "POST /login",
+    "GET /login/two-factor",
+    "POST /login/two-factor",
+    "POST /login/passkey/start",
+    "POST /login/passkey/finish",
+    "GET /assets/passkeys.js",
+    "GET /password-reset",
+    "POST /password-reset",
+    "GET /password-reset/confirm",
+    "POST /password-reset/confirm",
+    "GET /invitations/accept",
+    "POST /invitations/accept",
+    "GET /admin/dashboard",
+    "GET /admin/newsletters",
+    "POST /admin/newsletters",
+    "GET /admin/subscribers",
+    "POST /admin/subscribers",
+    "GET /admin/subscribers/{subscriber_id}",
+    "GET /admin/segments",
+    "POST /admin/segments",
+    "GET /admin/data-requests",
+    "POST /admin/data-requests/export",
+    "POST /admin/data-requests/erase",
+    "GET /admin/api-keys",
+    "POST /admin/api-keys",
+    "POST /admin/api-keys/{api_key_id}/revoke",
+    "GET /admin/users",
+    "POST /admin/users",
+    "GET /admin/invitations",
+    "POST /admin/invitations",
+    "GET /admin/2fa",
+    "POST /admin/2fa",
+    "POST /admin/2fa/disable",
+    "GET /admin/passkeys",
+    "POST /admin/passkeys/register/start",
+    "POST /admin/passkeys/register/finish",
+    "POST /admin/passkeys/{passkey_id}/delete",
+    "GET /admin/sessions",
+    "POST /admin/sessions/revoke-others",
+    "POST /admin/sessions/{session_id}/revoke",
+    "GET /admin/password",
+    "POST /admin/password",
+    "POST /admin/logout",
+    "GET /api/openapi.json",
+    "GET /api/docs",
+];
+
+/// Extract the `METHOD /path` pairs registered in the application, including their scope prefix
+fn registered_routes() -> BTreeSet<String> {
+    app_routes()
+        .map(|(method, path)| format!("{method} {path}"))
+        .collect()
+}
+
+/// Extract the `METHOD /path` pairs documented in the `OpenAPI` specification
+fn documented_routes() -> BTreeSet<String> {
+    let spec = ApiDoc::openapi();
+    let mut routes = BTreeSet::new();
+    for (path, item) in spec.paths.paths {
+        for (method, operation) in [
+            ("GET", item.get),
+            ("POST", item.post),
+            ("PUT", item.put),
+            ("PATCH", item.patch),
+            ("DELETE", item.delete),
+        ] {
+            if operation.is_some() {
+                routes.insert(format!("{method} {path}"));
+            }
+        }
+    }
+    routes
+}
+
+#[test]
+fn the_openapi_specification_matches_the_registered_routes() {
+    let registered = registered_routes();
+    let mut expected = documented_routes();
+    expected.extend(UNDOCUMENTED_ROUTES.iter().map(ToString::to_string));
+
+    let undocumented: Vec<_> = registered.difference(&expected).collect();
+    let missing: Vec<_> = expected.difference(&registered).collect();
+    assert!(
+        undocumented.is_empty(),
+        "Routes missing from the OpenAPI specification: {undocumented:?}"
+    );
+    assert!(
+        missing.is_empty(),
+        "Routes in the OpenAPI specification that are not registered: {missing:?}"
+    );
+}
+
+#[sqlx::test]
+async fn the_openapi_specification_is_served(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/api/openapi.json", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    let spec: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(spec, serde_json::to_value(ApiDoc::openapi()).unwrap());
+    assert!(spec["paths"]["/api/v1/newsletters"]["post"].is_object());
+
+    let response = app
+        .api_client
+        .get(format!("{}/api/docs", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 200);
+    assert!(response.text().await.unwrap().contains("Redoc"));
+
+    db_pool.close().await;
+}
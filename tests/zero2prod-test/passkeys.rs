@@ -0,0 +1,195 @@
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, TestApp, TestUser};
+
+/// Registration response that does not match any ceremony
+fn fake_registration(name: &str) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "credential": {
+            "id": "AAAA",
+            "rawId": "AAAA",
+            "type": "public-key",
+            "response": {
+                "attestationObject": "AAAA",
+                "clientDataJSON": "AAAA"
+            },
+            "extensions": {}
+        }
+    })
+}
+
+/// Authentication response that does not match any ceremony
+fn fake_authentication() -> serde_json::Value {
+    serde_json::json!({
+        "id": "AAAA",
+        "rawId": "AAAA",
+        "type": "public-key",
+        "response": {
+            "authenticatorData": "AAAA",
+            "clientDataJSON": "AAAA",
+            "signature": "AAAA",
+            "userHandle": null
+        },
+        "extensions": {}
+    })
+}
+
+#[sqlx::test]
+async fn you_must_be_logged_in_to_manage_passkeys(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    let response = app.post_passkey_registration_start().await;
+    assert_is_redirect_to(&response, "/login");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn registration_starts_a_ceremony_bound_to_the_logged_in_user(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_passkey_registration_start().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let options: serde_json::Value = response.json().await.unwrap();
+    let options = &options["publicKey"];
+    assert_eq!(options["rp"]["id"], "localhost");
+    assert_eq!(options["user"]["name"], app.test_user.username.as_str());
+    assert!(options["challenge"].as_str().is_some_and(|c| !c.is_empty()));
+
+    // The page that runs the ceremony loads the shared script
+    let html = app.get_passkeys_html().await;
+    assert!(html.contains(r#"<script src="/assets/passkeys.js"></script>"#));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn registration_cannot_be_completed_without_a_ceremony(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_passkey_registration_finish(&fake_registration("Laptop"))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        problem["detail"],
+        "The passkey ceremony was not started or has already been completed"
+    );
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn an_invalid_credential_is_rejected_and_the_ceremony_is_consumed(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+
+    // An invalid name does not consume the ceremony
+    app.post_passkey_registration_start().await;
+    let response = app
+        .post_passkey_registration_finish(&fake_registration(" "))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // A forged credential does
+    let response = app
+        .post_passkey_registration_finish(&fake_registration("Laptop"))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["detail"], "The passkey could not be verified");
+    let response = app
+        .post_passkey_registration_finish(&fake_registration("Laptop"))
+        .await;
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        problem["detail"],
+        "The passkey ceremony was not started or has already been completed"
+    );
+
+    // No passkey was stored
+    let n_passkeys = sqlx::query!(r#"SELECT count(*) AS "count!" FROM passkeys"#)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_passkeys, 0);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn passkey_login_does_not_reveal_whether_a_user_exists(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    let mut details = Vec::new();
+    for username in [TestUser::fake_username(), app.test_user.username.clone()] {
+        let response = app
+            .post_passkey_login_start(&serde_json::json!({"username": username}))
+            .await;
+        assert_eq!(response.status().as_u16(), 400);
+        let problem: serde_json::Value = response.json().await.unwrap();
+        details.push(problem["detail"].clone());
+    }
+    assert_eq!(details[0], details[1]);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn passkey_login_cannot_be_completed_without_a_ceremony(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    let response = app.post_passkey_login_finish(&fake_authentication()).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn deleting_an_unknown_passkey_displays_an_error(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_passkey_deletion(&Uuid::new_v4().to_string()).await;
+    assert_is_redirect_to(&response, "/admin/passkeys");
+    let html = app.get_passkeys_html().await;
+    assert!(html.contains("The passkey does not exist"));
+
+    db_pool.close().await;
+}
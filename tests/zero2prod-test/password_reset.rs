@@ -0,0 +1,182 @@
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::PgPool;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{assert_is_redirect_to, when_sending_an_email, TestApp, TestUser};
+
+/// Email address assigned to the test user
+const TEST_USER_EMAIL: &str = "admin@example.com";
+
+/// Assign an email address to the test user, so that it can reset its password
+async fn set_test_user_email(app: &TestApp, db_pool: &PgPool) {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        TEST_USER_EMAIL,
+        *app.test_user.user_id
+    )
+    .execute(db_pool)
+    .await
+    .unwrap();
+}
+
+/// Request a password reset for the test user and return the token sent by email
+async fn request_password_reset(app: &TestApp) -> String {
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_password_reset(&serde_json::json!({"email": TEST_USER_EMAIL}))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let link = app.confirmation_links(email_request).html;
+    link.query_pairs()
+        .find(|(k, _)| k == "reset_token")
+        .map(|(_, v)| v.into_owned())
+        .expect("The password reset link does not contain a token")
+}
+
+#[sqlx::test]
+async fn unknown_email_addresses_do_not_receive_reset_links(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_password_reset(&serde_json::json!({"email": "nobody@example.com"}))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("a password reset link has been sent to it"));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn email_addresses_are_unique_ignoring_case(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    set_test_user_email(&app, &db_pool).await;
+
+    // Another user cannot have the same address in a different case
+    let result = sqlx::query!(
+        "UPDATE users SET email = $1 WHERE username = 'admin'",
+        TEST_USER_EMAIL.to_uppercase()
+    )
+    .execute(&db_pool)
+    .await;
+    assert!(result.is_err());
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn password_reset_changes_the_password_and_invalidates_sessions(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    set_test_user_email(&app, &db_pool).await;
+
+    // Start a session with the old password
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Reset the password
+    let token = request_password_reset(&app).await;
+    let new_password = TestUser::fake_password(16);
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "reset_token": &token,
+            "new_password": &new_password,
+            "new_password2": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("<p><i>Your password has been reset, you can now log in</i></p>"));
+
+    // The existing session is no longer valid
+    let response = app.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // The old password no longer works, the new one does
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // The reset link cannot be used twice
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "reset_token": &token,
+            "new_password": &new_password,
+            "new_password2": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("<p><i>The password reset link is invalid or has expired</i></p>"));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn new_passwords_follow_the_password_rules(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    set_test_user_email(&app, &db_pool).await;
+    let token = request_password_reset(&app).await;
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "reset_token": &token,
+            "new_password": "short",
+            "new_password2": "short",
+        }))
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/password-reset/confirm?reset_token={token}"),
+    );
+
+    // The token is still usable after a rejected password
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/password-reset/confirm?reset_token={token}",
+            &app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<p><i>The password must be at least 12 characters long</i></p>"));
+
+    db_pool.close().await;
+}
@@ -0,0 +1,149 @@
+use fake::faker::internet::en::SafeEmail;
+use fake::Fake;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{when_sending_an_email, TestApp, X_FORWARDED_FOR};
+
+#[sqlx::test]
+async fn requests_above_the_ip_limit_are_rejected_with_a_429(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn_with(&db_pool, |c| {
+        c.rate_limit.ip.capacity = 3;
+        c.rate_limit.ip.refill_per_minute = 1;
+    })
+    .await;
+
+    // The bucket allows a burst of requests
+    for _ in 0..3 {
+        let response = app.get_subscriptions_confirm("unknown-token").await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Then the client has to wait for a token to be refilled
+    let response = app.get_subscriptions_confirm("unknown-token").await;
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn forged_forwarded_addresses_do_not_reset_the_ip_bucket(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn_with(&db_pool, |c| {
+        c.rate_limit.ip.capacity = 3;
+        c.rate_limit.ip.refill_per_minute = 1;
+    })
+    .await;
+
+    // Claim a different client address on each request, which is ignored since the proxy appends the actual one
+    let mut statuses = Vec::new();
+    for i in 0..4 {
+        let response = app
+            .api_client
+            .get(format!("{}/subscriptions/confirm", &app.address))
+            .query(&[("subscription_token", "unknown-token")])
+            .header(X_FORWARDED_FOR, format!("203.0.113.{i}, {}", app.client_ip))
+            .send()
+            .await
+            .expect("Failed to send request");
+        statuses.push(response.status().as_u16());
+    }
+    assert_eq!(statuses, [401, 401, 401, 429]);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn json_clients_receive_problem_details_when_rate_limited(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn_with(&db_pool, |c| {
+        c.rate_limit.ip.capacity = 1;
+        c.rate_limit.ip.refill_per_minute = 1;
+    })
+    .await;
+
+    let body = serde_json::json!({"name": "le guin"});
+    app.post_subscriptions_json(&body).await;
+    let response = app.post_subscriptions_json(&body).await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 429);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn subscriptions_above_the_email_limit_do_not_send_emails(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn_with(&db_pool, |c| {
+        c.rate_limit.email.capacity = 2;
+        c.rate_limit.email.refill_per_minute = 1;
+    })
+    .await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Subscribing the same address is limited, regardless of case
+    let email: String = SafeEmail().fake();
+    let mut statuses = Vec::new();
+    for email in [email.clone(), email.to_uppercase(), email.clone()] {
+        let body = serde_urlencoded::to_string([("name", "le guin"), ("email", &email)]).unwrap();
+        statuses.push(app.post_subscriptions(body).await.status().as_u16());
+    }
+    assert_eq!(statuses[0], 200);
+    assert_ne!(statuses[1], 429);
+    assert_eq!(statuses[2], 429);
+
+    // Other addresses are not affected
+    let other: String = SafeEmail().fake();
+    let body = serde_urlencoded::to_string([("name", "le guin"), ("email", &other)]).unwrap();
+    let response = app.post_subscriptions(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn the_login_page_is_not_rate_limited(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn_with(&db_pool, |c| {
+        c.rate_limit.ip.capacity = 1;
+        c.rate_limit.ip.refill_per_minute = 1;
+    })
+    .await;
+
+    for _ in 0..3 {
+        assert!(app.get_login_html().await.contains("<title>Login</title>"));
+    }
+
+    db_pool.close().await;
+}
@@ -0,0 +1,187 @@
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+use zero2prod::authentication::{set_role, Role};
+use zero2prod::idempotency::IdempotencyKey;
+
+use crate::helpers::{assert_is_redirect_to, TestApp, TestUser};
+
+#[sqlx::test]
+async fn viewers_can_browse_but_cannot_publish_newsletters(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    let viewer = TestUser::generate_with_role(&db_pool, "viewer").await;
+    viewer.login(&app).await;
+
+    // Viewers can see the dashboard and the subscribers
+    let html = app.get_dashboard_html().await;
+    assert!(html.contains("Your role: viewer"));
+    let response = app
+        .api_client
+        .get(format!("{}/admin/subscribers", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    // Viewers cannot publish newsletters
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "content_text": "Newsletter body as plain text",
+            "content_html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": IdempotencyKey::generate(),
+        }))
+        .await;
+    assert_eq!(response.status(), 403);
+    let n_issues = sqlx::query!(r#"SELECT count(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn editors_cannot_manage_users(_pool_opts: PgPoolOptions, conn_opts: PgConnectOptions) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    let editor = TestUser::generate_with_role(&db_pool, "editor").await;
+    editor.login(&app).await;
+
+    let response = app
+        .post_invitations(&serde_json::json!({
+            "email": "collaborator@example.com",
+            "role": "owner",
+        }))
+        .await;
+    assert_eq!(response.status(), 403);
+
+    let response = app
+        .api_client
+        .get(format!("{}/admin/users", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn owners_can_change_roles_but_not_remove_the_last_owner(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    let editor = TestUser::generate_with_role(&db_pool, "editor").await;
+    sqlx::query!("UPDATE users SET role = 'viewer' WHERE username = 'admin'")
+        .execute(&db_pool)
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    // Demote the editor to viewer
+    let response = app
+        .post_users(&serde_json::json!({
+            "user_id": editor.user_id.to_string(),
+            "role": "viewer",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html = app.get_users_html().await;
+    assert!(html.contains("<p><i>The role has been changed to viewer</i></p>"));
+
+    // The test user is the only owner left and cannot be demoted
+    let response = app
+        .post_users(&serde_json::json!({
+            "user_id": app.test_user.user_id.to_string(),
+            "role": "editor",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html = app.get_users_html().await;
+    assert!(
+        html.contains("<p><i>The role cannot be changed, at least one owner is required</i></p>")
+    );
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn owners_cannot_give_a_user_the_email_address_of_another(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    let editor = TestUser::generate_with_role(&db_pool, "editor").await;
+    sqlx::query!("UPDATE users SET email = 'admin@example.com' WHERE username = 'admin'")
+        .execute(&db_pool)
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    // The email address is taken, ignoring case, so neither it nor the role are changed
+    let response = app
+        .post_users(&serde_json::json!({
+            "user_id": editor.user_id.to_string(),
+            "role": "viewer",
+            "email": "Admin@Example.com",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html = app.get_users_html().await;
+    assert!(html.contains("<p><i>The email address is already used by another user</i></p>"));
+    let row = sqlx::query!(
+        "SELECT email, role FROM users WHERE user_id = $1",
+        *editor.user_id
+    )
+    .fetch_one(&db_pool)
+    .await
+    .unwrap();
+    assert_eq!(row.email, None);
+    assert_eq!(row.role, "editor");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn concurrent_demotions_do_not_remove_the_last_owner(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    let owner = TestUser::generate_with_role(&db_pool, "owner").await;
+    sqlx::query!("UPDATE users SET role = 'viewer' WHERE username = 'admin'")
+        .execute(&db_pool)
+        .await
+        .unwrap();
+
+    // Demote both owners at the same time
+    let db_pool = &db_pool;
+    let demote = |user_id| async move {
+        let mut transaction = db_pool.begin().await.unwrap();
+        let changed = set_role(user_id, Role::Editor, &mut transaction)
+            .await
+            .unwrap();
+        transaction.commit().await.unwrap();
+        changed
+    };
+    let (changed1, changed2) = tokio::join!(demote(app.test_user.user_id), demote(owner.user_id));
+    assert_ne!(changed1, changed2);
+    let n_owners = sqlx::query!(r#"SELECT count(*) AS "count!" FROM users WHERE role = 'owner'"#)
+        .fetch_one(db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_owners, 1);
+
+    db_pool.close().await;
+}
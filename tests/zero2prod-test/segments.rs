@@ -0,0 +1,156 @@
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use wiremock::ResponseTemplate;
+
+use zero2prod::idempotency::IdempotencyKey;
+
+use crate::helpers::{assert_is_redirect_to, when_sending_an_email, TestApp};
+
+#[sqlx::test]
+async fn you_must_be_logged_in_to_manage_segments(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    // Try to access the segments page
+    let response = app.get_segments().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Try to create a segment
+    let body = serde_json::json!({
+        "name": "Beta testers",
+        "tags": "beta",
+    });
+    let response = app.post_segments(&body).await;
+    assert_is_redirect_to(&response, "/login");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn invalid_segment_definitions_are_rejected(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    // Login
+    app.test_user.login(&app).await;
+
+    // Try to create a segment with an invalid tag
+    let body = serde_json::json!({
+        "name": "Beta testers",
+        "tags": "beta testers",
+    });
+    let response = app.post_segments(&body).await;
+    assert_is_redirect_to(&response, "/admin/segments");
+
+    // Follow the redirect
+    let html = app.get_segments_html().await;
+    assert!(html.contains("<p><i>beta testers is not a valid subscriber tag</i></p>"));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn newsletters_targeted_at_a_segment_are_delivered_only_to_matching_subscribers(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    // Create two confirmed subscribers, only one of which is tagged as `beta`
+    app.create_confirmed_subscriber().await;
+    app.create_confirmed_subscriber().await;
+    let beta_email = sqlx::query!("SELECT email FROM subscriptions LIMIT 1")
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .email;
+
+    // Login
+    app.test_user.login(&app).await;
+
+    // Tag the subscriber
+    let body = serde_json::json!({
+        "email": &beta_email,
+        "tags": "beta",
+        "attributes": "plan=pro",
+    });
+    let response = app.post_subscribers(&body).await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    // Create the segment
+    let body = serde_json::json!({
+        "name": "Beta testers",
+        "tags": "beta",
+        "attributes": "plan=pro",
+    });
+    let response = app.post_segments(&body).await;
+    assert_is_redirect_to(&response, "/admin/segments");
+
+    // Follow the redirect
+    let html = app.get_segments_html().await;
+    assert!(html.contains("<p><i>The segment `Beta testers` has been created</i></p>"));
+
+    // Preview the matching count before sending
+    let segment_id = sqlx::query!("SELECT segment_id FROM segments")
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .segment_id;
+    let html = app.get_newsletters_html().await;
+    assert!(html.contains("All confirmed subscribers (2)"));
+    assert!(html.contains(&format!(
+        "<option value=\"{segment_id}\">Beta testers (1)</option>"
+    )));
+
+    // Publish the newsletter to the segment, for which we expect only one newsletter
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "content_text": "Newsletter body as plain text",
+        "content_html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": IdempotencyKey::generate(),
+        "segment_id": segment_id,
+    });
+    let response = app.post_newsletters(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Consume all enqueued tasks
+    app.dispatch_all_pending_emails().await;
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn newsletters_targeted_at_an_unknown_segment_are_rejected(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    // Login
+    app.test_user.login(&app).await;
+
+    // Try to publish the newsletter to a segment that does not exist
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "content_text": "Newsletter body as plain text",
+        "content_html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": IdempotencyKey::generate(),
+        "segment_id": "00000000-0000-0000-0000-000000000000",
+    });
+    let response = app.post_newsletters(&body).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    db_pool.close().await;
+}
@@ -0,0 +1,142 @@
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::PgPool;
+
+use zero2prod::configuration::{SessionStoreBackend, Settings};
+use zero2prod::session_store::delete_expired_session_states;
+use zero2prod::startup::Application;
+
+use crate::helpers::{assert_is_redirect_to, TestApp};
+
+/// Spin up a test application using a session store backend and no Redis
+async fn spawn_with_session_store(db_pool: &PgPool, backend: SessionStoreBackend) -> TestApp {
+    TestApp::spawn_with(db_pool, |c| {
+        c.session_store = backend;
+        c.redis_uri = None;
+    })
+    .await
+}
+
+/// Count the session states stored in the database
+async fn stored_sessions(db_pool: &PgPool) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM sessions"#)
+        .fetch_one(db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+/// Login, access the admin dashboard and logout
+async fn check_session_lifecycle(app: &TestApp) {
+    // Login
+    let response = app.test_user.login(app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html = app.get_dashboard_html().await;
+    assert!(html.contains(&format!("Welcome {}", app.test_user.username)));
+
+    // Logout
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[sqlx::test]
+async fn sessions_can_be_stored_in_the_database(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = spawn_with_session_store(&db_pool, SessionStoreBackend::Postgres).await;
+
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert_eq!(stored_sessions(&db_pool).await, 1);
+
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(stored_sessions(&db_pool).await, 0);
+
+    check_session_lifecycle(&app).await;
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn expired_session_states_are_deleted_by_the_maintenance(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = spawn_with_session_store(&db_pool, SessionStoreBackend::Postgres).await;
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // The session expires without the user logging out
+    sqlx::query!("UPDATE sessions SET expires_at = now() - interval '1 minute'")
+        .execute(&db_pool)
+        .await
+        .unwrap();
+    let response = app.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(stored_sessions(&db_pool).await, 1);
+
+    // Its state is kept until the maintenance deletes it
+    let n_deleted = delete_expired_session_states(&db_pool).await.unwrap();
+    assert_eq!(n_deleted, 1);
+    assert_eq!(stored_sessions(&db_pool).await, 0);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn sessions_can_be_stored_in_cookies(_pool_opts: PgPoolOptions, conn_opts: PgConnectOptions) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = spawn_with_session_store(&db_pool, SessionStoreBackend::Cookie).await;
+
+    check_session_lifecycle(&app).await;
+    assert_eq!(stored_sessions(&db_pool).await, 0);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn cookie_sessions_can_be_revoked(_pool_opts: PgPoolOptions, conn_opts: PgConnectOptions) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = spawn_with_session_store(&db_pool, SessionStoreBackend::Cookie).await;
+
+    // Login
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Revoke the session from the index, as another device would
+    sqlx::query!(
+        "DELETE FROM user_sessions WHERE user_id = $1",
+        *app.test_user.user_id
+    )
+    .execute(&db_pool)
+    .await
+    .unwrap();
+
+    // The signed cookie is still valid, but the session is rejected
+    let response = app.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn the_redis_session_store_requires_a_redis_uri(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let mut config = Settings::get_config().unwrap();
+    config.application.app_port = 0;
+    config.session_store = SessionStoreBackend::Redis;
+    config.redis_uri = None;
+
+    let outcome = Application::build_with_db_pool(config, &db_pool).await;
+    assert!(outcome.is_err());
+
+    db_pool.close().await;
+}
@@ -0,0 +1,268 @@
+use std::time::Duration;
+
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::PgPool;
+
+use zero2prod::authentication::{delete_expired_user_sessions, SessionTimeouts};
+
+use crate::helpers::{assert_is_redirect_to, TestApp, TestUser};
+use crate::FAKE_PASSWORD_LEN;
+
+/// Login as the test user of an application from another device, which has its own cookies
+async fn login_from_another_device(app: &TestApp, db_pool: &PgPool) -> TestApp {
+    let other_device = TestApp::spawn(db_pool).await;
+    let response = other_device
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    other_device
+}
+
+/// Retrieve the identifiers of the indexed sessions of the test user
+async fn session_ids(app: &TestApp, db_pool: &PgPool) -> Vec<String> {
+    sqlx::query!(
+        r#"
+        SELECT session_id
+        FROM user_sessions
+        WHERE user_id = $1
+        "#,
+        *app.test_user.user_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.session_id.to_string())
+    .collect()
+}
+
+#[sqlx::test]
+async fn you_must_be_logged_in_to_see_your_sessions(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/admin/sessions", &app.address))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_is_redirect_to(&response, "/login");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn sessions_are_listed_with_the_current_one_highlighted(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let _other_device = login_from_another_device(&app, &db_pool).await;
+
+    let ids = session_ids(&app, &db_pool).await;
+    assert_eq!(ids.len(), 2);
+    let html = app.get_sessions_html().await;
+    assert_eq!(html.matches("This session").count(), 1);
+    assert_eq!(html.matches("/revoke\" method=\"post\">").count(), 1);
+    assert!(ids.iter().any(|id| html.contains(id.as_str())));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn other_sessions_can_be_logged_out(_pool_opts: PgPoolOptions, conn_opts: PgConnectOptions) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let other_device = login_from_another_device(&app, &db_pool).await;
+
+    let response = app.post_other_sessions_revocation().await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html = app.get_sessions_html().await;
+    assert!(html.contains("1 other session(s) have been logged out"));
+
+    // Only the current session is still valid
+    let response = other_device.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn a_single_session_can_be_logged_out(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let before = session_ids(&app, &db_pool).await;
+    let other_device = login_from_another_device(&app, &db_pool).await;
+    let other_session_id = session_ids(&app, &db_pool)
+        .await
+        .into_iter()
+        .find(|id| !before.contains(id))
+        .unwrap();
+
+    let response = app.post_session_revocation(&other_session_id).await;
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html = app.get_sessions_html().await;
+    assert!(html.contains("The session has been logged out"));
+    let response = other_device.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Revoking it again displays an error
+    app.post_session_revocation(&other_session_id).await;
+    let html = app.get_sessions_html().await;
+    assert!(html.contains("The session does not exist"));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn changing_the_password_logs_out_other_sessions(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let other_device = login_from_another_device(&app, &db_pool).await;
+
+    let new_password = TestUser::fake_password(FAKE_PASSWORD_LEN);
+    let response = app
+        .post_password(&serde_json::json!({
+            "old_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password2": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    let response = other_device.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn logging_out_removes_the_session_from_the_index(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    assert_eq!(session_ids(&app, &db_pool).await.len(), 1);
+
+    app.post_logout().await;
+    assert!(session_ids(&app, &db_pool).await.is_empty());
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn idle_sessions_expire(_pool_opts: PgPoolOptions, conn_opts: PgConnectOptions) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn_with(&db_pool, |c| c.application.session_idle_timeout_secs = 2).await;
+    app.test_user.login(&app).await;
+
+    // Requests made before the idle timeout keep the session alive
+    for _ in 0..3 {
+        tokio::time::sleep(Duration::from_millis(1200)).await;
+        let response = app.get_dashboard().await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Once the session is idle for too long, it expires
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    let response = app.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("Your session has expired due to inactivity, please log in again"));
+    assert!(session_ids(&app, &db_pool).await.is_empty());
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn sessions_expire_after_the_maximum_age_despite_activity(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn_with(&db_pool, |c| c.application.session_max_age_secs = 3).await;
+    app.test_user.login(&app).await;
+
+    for _ in 0..2 {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let response = app.get_dashboard().await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let response = app.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("Your session has expired, please log in again"));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn abandoned_sessions_are_not_listed_and_are_deleted_once_expired(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let before = session_ids(&app, &db_pool).await;
+    let _other_device = login_from_another_device(&app, &db_pool).await;
+    let abandoned_session_id = session_ids(&app, &db_pool)
+        .await
+        .into_iter()
+        .find(|id| !before.contains(id))
+        .unwrap();
+
+    // The other device is never used again, so its session expires without being revoked
+    sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET last_seen_at = now() - interval '1 day'
+        WHERE session_id = $1::text::uuid
+        "#,
+        abandoned_session_id
+    )
+    .execute(&db_pool)
+    .await
+    .unwrap();
+    let html = app.get_sessions_html().await;
+    assert!(html.contains("This session"));
+    assert!(!html.contains(abandoned_session_id.as_str()));
+
+    // The cleanup deletes it from the index
+    let timeouts = SessionTimeouts {
+        idle: Duration::from_secs(30 * 60),
+        max_age: Duration::from_secs(12 * 60 * 60),
+    };
+    let n_deleted = delete_expired_user_sessions(timeouts, &db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_deleted, 1);
+    assert_eq!(session_ids(&app, &db_pool).await, before);
+
+    db_pool.close().await;
+}
@@ -0,0 +1,63 @@
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+
+use crate::helpers::{assert_is_redirect_to, TestApp};
+
+#[sqlx::test]
+async fn you_must_be_logged_in_to_see_subscriber_details(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.create_unconfirmed_subscriber().await;
+    let id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .id;
+
+    let response = app.get_subscriber_details(&id.to_string()).await;
+    assert_is_redirect_to(&response, "/login");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn subscriber_details_show_the_consent_evidence(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.create_confirmed_subscriber().await;
+    let id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .id;
+
+    // Login
+    app.test_user.login(&app).await;
+
+    // Check the subscriber details page
+    let response = app.get_subscriber_details(&id.to_string()).await;
+    assert_eq!(response.status(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<tr><th>Status</th><td>confirmed</td></tr>"));
+    assert!(html.contains(&format!(
+        "<tr><th>Consent text version</th><td>{}</td></tr>",
+        zero2prod::domain::CURRENT_CONSENT_VERSION
+    )));
+    assert!(html.contains(&format!(
+        "<tr><th>Confirm IP address</th><td>{}</td></tr>",
+        app.client_ip
+    )));
+
+    // Unknown subscribers are not found
+    let response = app
+        .get_subscriber_details(&uuid::Uuid::new_v4().to_string())
+        .await;
+    assert_eq!(response.status(), 404);
+
+    db_pool.close().await;
+}
@@ -56,6 +56,50 @@ async fn subscribe_persists_the_new_subscriber(
     db_pool.close().await;
 }
 
+#[sqlx::test]
+async fn subscribe_persists_the_consent_evidence(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&consent_version=v2";
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "zero2prod-test")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    let saved = sqlx::query!(
+        r#"
+        SELECT consent_version, subscribe_ip, subscribe_user_agent, confirmed_at
+        FROM subscriptions
+        "#
+    )
+    .fetch_one(&db_pool)
+    .await
+    .expect("Failed to fetch saved subscription");
+
+    assert_eq!(saved.consent_version.as_deref(), Some("v2"));
+    assert_eq!(saved.subscribe_ip.as_deref(), Some(app.client_ip.as_str()));
+    assert_eq!(
+        saved.subscribe_user_agent.as_deref(),
+        Some("zero2prod-test")
+    );
+    assert!(saved.confirmed_at.is_none());
+
+    db_pool.close().await;
+}
+
 #[sqlx::test]
 async fn subscribe_returns_a_400_when_data_is_missing(
     _pool_opts: PgPoolOptions,
@@ -126,6 +170,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data(
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     db_pool.close().await;
 }
@@ -145,6 +190,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link(
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.confirmation_links(email_request);
 
@@ -153,6 +199,45 @@ async fn subscribe_sends_a_confirmation_email_with_a_link(
     db_pool.close().await;
 }
 
+#[sqlx::test]
+async fn subscribe_succeeds_and_retries_the_confirmation_email_if_the_email_api_is_down(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // The subscription does not wait for the email API
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status(), 200);
+
+    // The failed confirmation email stays pending in the outbox, to be retried later
+    app.dispatch_all_pending_emails().await;
+    let email = sqlx::query!("SELECT kind, recipient, status, attempts FROM outbox")
+        .fetch_one(&db_pool)
+        .await
+        .expect("Failed to fetch the outbox email");
+    assert_eq!(email.kind, "confirmation");
+    assert_eq!(email.recipient, "ursula_le_guin@gmail.com");
+    assert_eq!(email.status, "pending");
+    assert_eq!(email.attempts, 1);
+    let job = sqlx::query!("SELECT kind, attempts FROM jobs")
+        .fetch_one(&db_pool)
+        .await
+        .expect("Failed to fetch queued job");
+    assert_eq!(job.kind, "outbox_dispatch");
+    assert_eq!(job.attempts, 1);
+
+    db_pool.close().await;
+}
+
 #[sqlx::test]
 async fn subscribe_fails_if_there_is_a_fatal_database_error(
     _pool_opts: PgPoolOptions,
@@ -178,3 +263,119 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error(
 
     db_pool.close().await;
 }
+
+#[sqlx::test]
+async fn subscribe_accepts_a_json_body_and_returns_json(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+    });
+    let response = app.post_subscriptions_json(&body).await;
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, "pending_confirmation");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn subscribe_returns_problem_details_for_invalid_json(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    let test_cases = vec![
+        (serde_json::json!({"name": "le guin"}), "missing the email"),
+        (
+            serde_json::json!({"name": "", "email": "ursula_le_guin@gmail.com"}),
+            "empty name",
+        ),
+        (
+            serde_json::json!({"name": "Ursula", "email": "definitely-not-an-email"}),
+            "invalid email",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_subscriptions_json(&body).await;
+
+        assert_eq!(
+            response.status(),
+            400,
+            "The API did not fail with 400 Bad Request when the payload had {description}"
+        );
+        assert_eq!(
+            response.headers()["Content-Type"],
+            "application/problem+json"
+        );
+
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["status"], 400);
+        assert_eq!(problem["title"], "Bad Request");
+        assert!(problem["detail"].is_string());
+    }
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn subscribe_rejects_unsupported_content_types_with_a_415(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    for content_type in ["text/plain", "application/xml"] {
+        let response = app
+            .api_client
+            .post(format!("{}/subscriptions", &app.address))
+            .header("Content-Type", content_type)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to send request");
+
+        assert_eq!(
+            response.status(),
+            415,
+            "The API did not fail with 415 Unsupported Media Type for {content_type}"
+        );
+        assert_eq!(
+            response.headers()["Content-Type"],
+            "application/problem+json"
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["status"], 415);
+        assert!(problem["detail"].as_str().unwrap().contains(content_type));
+    }
+    let n_subscriptions = sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscriptions, 0);
+
+    db_pool.close().await;
+}
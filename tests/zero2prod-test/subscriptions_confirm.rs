@@ -35,6 +35,7 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called(
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.confirmation_links(email_request);
@@ -61,6 +62,7 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber(
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
     let confirmation_links = app.confirmation_links(email_request);
 
@@ -70,14 +72,50 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber(
         .error_for_status()
         .unwrap();
 
-    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
-        .fetch_one(&db_pool)
-        .await
-        .expect("Failed to fetch saved subscription");
+    let saved =
+        sqlx::query!("SELECT email, name, status, confirmed_at, confirm_ip FROM subscriptions")
+            .fetch_one(&db_pool)
+            .await
+            .expect("Failed to fetch saved subscription");
 
     assert_eq!(saved.email, "ursula_le_guin@gmail.com");
     assert_eq!(saved.name, "le guin");
     assert_eq!(saved.status, "confirmed");
+    assert!(saved.confirmed_at.is_some());
+    assert_eq!(saved.confirm_ip.as_deref(), Some("127.0.0.1"));
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn confirmations_with_an_unknown_token_return_problem_details_for_json_clients(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token=unknown",
+            app.address
+        ))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        problem["detail"],
+        "There is no subscriber associated with the provided token"
+    );
 
     db_pool.close().await;
 }
@@ -0,0 +1,192 @@
+use chrono::Utc;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::helpers::{assert_is_redirect_to, TestApp};
+
+/// Extract the text of the first element following a marker in an HTML page
+fn extract_after<'a>(html: &'a str, marker: &str) -> &'a str {
+    html.split(marker)
+        .nth(1)
+        .and_then(|rest| rest.split("</code>").next())
+        .expect("The marker was not found in the page")
+}
+
+/// Compute the TOTP code of a secret for the current time step shifted by `offset` steps
+fn totp_code(secret: &str, offset: i64) -> String {
+    let secret = Secret::Encoded(secret.to_string()).to_bytes().unwrap();
+    let totp = TOTP::new_unchecked(Algorithm::SHA1, 6, 1, 30, secret, None, String::new());
+    let time = Utc::now().timestamp() + offset * 30;
+    totp.generate(u64::try_from(time).unwrap())
+}
+
+/// Enrol the logged-in test user and return the TOTP secret and the recovery codes
+async fn enrol(app: &TestApp) -> (String, Vec<String>) {
+    let html = app.get_two_factor_settings_html().await;
+    let secret = extract_after(&html, r#"<code id="totp-secret">"#).to_string();
+    assert!(html.contains("otpauth://totp/"));
+
+    let response = app
+        .post_two_factor_enrolment(&serde_json::json!({"code": totp_code(&secret, 0)}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    let recovery_codes: Vec<String> = html
+        .split("<li><code>")
+        .skip(1)
+        .map(|s| s.split("</code>").next().unwrap().to_string())
+        .collect();
+    assert_eq!(recovery_codes.len(), 10);
+
+    (secret, recovery_codes)
+}
+
+/// Login again with the password of the test user, stopping at the second factor
+async fn login_with_password(app: &TestApp) {
+    app.post_logout().await;
+    let response = app.test_user.login(app).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[sqlx::test]
+async fn an_invalid_code_does_not_enable_two_factor_authentication(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+
+    // Confirm the enrolment with a wrong code
+    app.get_two_factor_settings_html().await;
+    let response = app
+        .post_two_factor_enrolment(&serde_json::json!({"code": "not-a-code"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/2fa");
+    let html = app.get_two_factor_settings_html().await;
+    assert!(html.contains("The authentication code is invalid"));
+
+    // The password is still enough to login
+    app.post_logout().await;
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn login_requires_the_second_factor_once_enabled(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let (secret, _) = enrol(&app).await;
+
+    // The password alone does not start a session
+    login_with_password(&app).await;
+    let response = app.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // A wrong code is rejected
+    let response = app
+        .post_login_two_factor(&serde_json::json!({"code": "000000x"}))
+        .await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let response = app.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // A fresh code starts the session
+    let code = totp_code(&secret, 1);
+    let response = app
+        .post_login_two_factor(&serde_json::json!({"code": &code}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html = app.get_dashboard_html().await;
+    assert!(html.contains(&format!("Welcome {}", app.test_user.username)));
+
+    // The same code cannot be replayed
+    login_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({"code": &code}))
+        .await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn recovery_codes_can_only_be_used_once(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let (_, recovery_codes) = enrol(&app).await;
+
+    // Login with a recovery code
+    login_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({"code": &recovery_codes[0]}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // The recovery code cannot be used again
+    login_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({"code": &recovery_codes[0]}))
+        .await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn the_second_factor_step_requires_a_valid_password_first(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+
+    let response = app
+        .post_login_two_factor(&serde_json::json!({"code": "123456"}))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    db_pool.close().await;
+}
+
+#[sqlx::test]
+async fn two_factor_authentication_can_be_disabled_with_a_valid_code(
+    _pool_opts: PgPoolOptions,
+    conn_opts: PgConnectOptions,
+) {
+    let db_pool = TestApp::init_test_db_pool(conn_opts);
+    let app = TestApp::spawn(&db_pool).await;
+    app.test_user.login(&app).await;
+    let (_, recovery_codes) = enrol(&app).await;
+
+    // A wrong code does not disable the second factor
+    let response = app
+        .post_two_factor_disable(&serde_json::json!({"code": "wrong"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/2fa");
+    let html = app.get_two_factor_settings_html().await;
+    assert!(html.contains("Two-factor authentication is enabled"));
+
+    // A valid code disables it, and the password is enough to login again
+    let response = app
+        .post_two_factor_disable(&serde_json::json!({"code": &recovery_codes[1]}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/2fa");
+    app.post_logout().await;
+    let response = app.test_user.login(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    db_pool.close().await;
+}